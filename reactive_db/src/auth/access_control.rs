@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::RngCore;
+
+use crate::config::config_reader::{AuthConfig, Permission, UserConfig};
+use crate::networking::types::Credentials;
+
+const ANY_TABLE: &str = "*";
+const ANONYMOUS_USER: &str = "anonymous";
+const SALT_SIZE: usize = 16;
+
+// Identity attached to a connection after its handshake
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    roles: Vec<String>,
+    // Set when auth is not configured or for requests made by the server itself
+    unrestricted: bool,
+}
+
+impl Principal {
+    // No roles, only allowed anything when auth is not configured
+    pub fn anonymous() -> Principal {
+        Principal {
+            name: ANONYMOUS_USER.to_string(),
+            roles: vec![],
+            unrestricted: false,
+        }
+    }
+
+    pub fn unrestricted(name: &str) -> Principal {
+        Principal {
+            name: name.to_string(),
+            roles: vec![],
+            unrestricted: true,
+        }
+    }
+}
+
+// Checks credentials against `DbConfig.auth` and resolves per table rights
+pub struct AccessControl {
+    users: Vec<UserConfig>,
+    roles: HashMap<String, HashMap<String, HashSet<Permission>>>,
+    anonymous_roles: Option<Vec<String>>,
+    enabled: bool,
+}
+
+impl AccessControl {
+    pub fn new(config: Option<AuthConfig>) -> AccessControl {
+        let config = match config {
+            Some(config) => config,
+            None => {
+                return AccessControl {
+                    users: vec![],
+                    roles: HashMap::new(),
+                    anonymous_roles: None,
+                    enabled: false,
+                }
+            }
+        };
+        let mut roles = HashMap::new();
+        for role in config.roles {
+            let mut tables = HashMap::new();
+            for (table, permissions) in role.tables {
+                tables.insert(table, permissions.into_iter().collect());
+            }
+            roles.insert(role.name, tables);
+        }
+        AccessControl {
+            users: config.users,
+            roles,
+            anonymous_roles: config.anonymous_roles,
+            enabled: true,
+        }
+    }
+
+    pub fn authenticate(&self, credentials: Option<&Credentials>) -> Result<Principal, String> {
+        if !self.enabled {
+            return Ok(Principal::unrestricted(ANONYMOUS_USER));
+        }
+        let user = match credentials {
+            None => {
+                let mut principal = Principal::anonymous();
+                principal.roles = self.anonymous_roles.clone().unwrap_or_default();
+                return Ok(principal);
+            }
+            Some(Credentials::ApiToken(token)) => {
+                self.users.iter().find(|user| matches_hash(&user.api_token_hash, token))
+            }
+            Some(Credentials::UserPassword(user_name, password)) => {
+                self.users
+                    .iter()
+                    .find(|user| &user.name == user_name && matches_hash(&user.password_hash, password))
+            }
+        };
+        match user {
+            Some(user) => Ok(Principal {
+                name: user.name.clone(),
+                roles: user.roles.clone(),
+                unrestricted: false,
+            }),
+            None => Err("Invalid credentials".to_string()),
+        }
+    }
+
+    pub fn is_allowed(&self, principal: &Principal, table: &str, permission: Permission) -> bool {
+        if !self.enabled || principal.unrestricted {
+            return true;
+        }
+        principal.roles.iter().any(|role_name| match self.roles.get(role_name) {
+            Some(tables) => [table, ANY_TABLE].iter().any(|name| match tables.get(*name) {
+                Some(permissions) => permissions.contains(&permission),
+                None => false,
+            }),
+            None => false,
+        })
+    }
+
+    pub fn check(&self, principal: &Principal, table: &str, permission: Permission) -> Result<(), String> {
+        if self.is_allowed(principal, table, permission) {
+            return Ok(());
+        }
+        Err(format!("{} does not have {:?} access to table {}", principal.name, permission, table))
+    }
+}
+
+// An argon2id hash in PHC string format, with the random salt it was made with stored in it
+pub fn hash_secret(secret: &str) -> Result<String, String> {
+    let mut salt = [0; SALT_SIZE];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Unable to hash secret: {}", e))
+}
+
+// Hashes that can't be parsed never match, the comparison itself takes the same time wherever they differ
+fn matches_hash(expected: &Option<String>, secret: &str) -> bool {
+    let expected = match expected.as_deref().map(PasswordHash::new) {
+        Some(Ok(expected)) => expected,
+        _ => return false,
+    };
+    Argon2::default().verify_password(secret.as_bytes(), &expected).is_ok()
+}
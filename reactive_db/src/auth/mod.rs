@@ -0,0 +1,4 @@
+mod access_control;
+mod tests;
+
+pub use self::access_control::{hash_secret, AccessControl, Principal};
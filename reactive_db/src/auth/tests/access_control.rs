@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::auth::access_control::hash_secret;
+    use crate::auth::AccessControl;
+    use crate::config::config_reader::{AuthConfig, Permission, RoleConfig, UserConfig};
+    use crate::networking::types::Credentials;
+
+    fn get_access_control() -> AccessControl {
+        let mut reader_tables = BTreeMap::new();
+        reader_tables.insert("derived".to_string(), vec![Permission::Read, Permission::Listen]);
+        let mut writer_tables = BTreeMap::new();
+        writer_tables.insert("*".to_string(), vec![Permission::Insert, Permission::Delete]);
+        AccessControl::new(Some(AuthConfig {
+            users: vec![
+                UserConfig {
+                    name: "backend".to_string(),
+                    password_hash: None,
+                    api_token_hash: Some(hash_secret("backend-token").unwrap()),
+                    roles: vec!["writer".to_string()],
+                },
+                UserConfig {
+                    name: "alice".to_string(),
+                    password_hash: Some(hash_secret("hunter2").unwrap()),
+                    api_token_hash: None,
+                    roles: vec!["reader".to_string()],
+                },
+            ],
+            roles: vec![
+                RoleConfig { name: "reader".to_string(), tables: reader_tables },
+                RoleConfig { name: "writer".to_string(), tables: writer_tables },
+            ],
+            anonymous_roles: None,
+        }))
+    }
+
+    #[test]
+    fn authenticates_tokens_and_passwords() {
+        let access_control = get_access_control();
+        let backend = access_control
+            .authenticate(Some(&Credentials::ApiToken("backend-token".to_string())))
+            .unwrap();
+        assert_eq!(backend.name, "backend");
+        let alice = access_control
+            .authenticate(Some(&Credentials::UserPassword("alice".to_string(), "hunter2".to_string())))
+            .unwrap();
+        assert_eq!(alice.name, "alice");
+        assert!(access_control
+            .authenticate(Some(&Credentials::UserPassword("alice".to_string(), "wrong".to_string())))
+            .is_err());
+        assert!(access_control
+            .authenticate(Some(&Credentials::ApiToken("hunter2".to_string())))
+            .is_err());
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        let first = hash_secret("hunter2").unwrap();
+        assert!(first.starts_with("$argon2id$"));
+        assert_ne!(first, hash_secret("hunter2").unwrap());
+    }
+
+    #[test]
+    fn grants_rights_per_table() {
+        let access_control = get_access_control();
+        let alice = access_control
+            .authenticate(Some(&Credentials::UserPassword("alice".to_string(), "hunter2".to_string())))
+            .unwrap();
+        assert!(access_control.is_allowed(&alice, "derived", Permission::Read));
+        assert!(access_control.is_allowed(&alice, "derived", Permission::Listen));
+        assert!(!access_control.is_allowed(&alice, "derived", Permission::Insert));
+        assert!(!access_control.is_allowed(&alice, "users", Permission::Read));
+
+        let backend = access_control
+            .authenticate(Some(&Credentials::ApiToken("backend-token".to_string())))
+            .unwrap();
+        assert!(access_control.is_allowed(&backend, "users", Permission::Insert));
+        assert!(access_control.is_allowed(&backend, "grades", Permission::Delete));
+        assert!(!access_control.is_allowed(&backend, "users", Permission::Read));
+    }
+
+    #[test]
+    fn anonymous_connections_get_nothing_by_default() {
+        let access_control = get_access_control();
+        let anonymous = access_control.authenticate(None).unwrap();
+        assert!(!access_control.is_allowed(&anonymous, "derived", Permission::Read));
+    }
+
+    #[test]
+    fn everything_is_allowed_without_auth_config() {
+        let access_control = AccessControl::new(None);
+        let anonymous = access_control.authenticate(None).unwrap();
+        assert!(access_control.is_allowed(&anonymous, "users", Permission::Delete));
+    }
+}
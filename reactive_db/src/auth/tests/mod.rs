@@ -0,0 +1 @@
+mod access_control;
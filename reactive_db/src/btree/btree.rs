@@ -1,14 +1,26 @@
 use serde_json::Result;
+use std::cmp;
+use std::collections::HashSet;
 use std::io;
 use std::io::{Cursor, Error, ErrorKind};
 
-use crate::{btree::node::{IndexValue, Node, NodeEntry}, storage::storage_engine::StorageEngine};
+use crate::{btree::node::{IndexValue, Node, NodeEntry}, storage::{buffer_pool::PageCopy, storage_engine::{StorageEngine, StorageStats}}};
+use tracing::warn;
 
 pub struct BTree {
     node_size: u32,
     storage_manager: Box<dyn StorageEngine>,
 }
 
+// What a structural check has found so far
+struct TreeCheck {
+    problems: Vec<String>,
+    visited: HashSet<u32>,
+    // Leaves in key order as the tree reaches them
+    leaves: Vec<u32>,
+    leaf_depth: Option<usize>,
+}
+
 pub enum InsertResult {
     Normal,
     Rebalance(u32, IndexValue, u32),
@@ -34,6 +46,18 @@ impl BTree {
         });
     }
 
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.storage_manager.flush()
+    }
+
+    pub fn start_copy(&mut self, destination: &str) -> io::Result<PageCopy> {
+        self.storage_manager.start_copy(destination)
+    }
+
+    pub fn storage_stats(&mut self) -> io::Result<StorageStats> {
+        self.storage_manager.stats()
+    }
+
     pub fn delete(
         &mut self,
         index: IndexValue,
@@ -49,12 +73,12 @@ impl BTree {
                 None => 0,
             },
         };
-        let (_, deleted_entry, _) = self.delete_helper(&entry, 1, entry.right_ref != 0)?;
+        let deleted_entry = self.delete_helper(&entry, entry.right_ref != 0)?;
         let mut deleted = deleted_entry;
         let mut deleted_indexes = vec![];
         while delete_all_nodes && deleted != None {
             deleted_indexes.push(deleted.unwrap().right_ref);
-            let (_, deleted_entry, _) = match self.delete_helper(&entry, 1, entry.right_ref != 0) {
+            let deleted_entry = match self.delete_helper(&entry, entry.right_ref != 0) {
                 Ok(t) => t,
                 _ => {
                     self.storage_manager.end_session();
@@ -125,12 +149,9 @@ impl BTree {
             right_ref: 0,
             left_ref: None,
         };
-        let found_node = self.search_helper(&dummy_entry, 1)?;
+        let (_, found_node, pos) = self.seek(&dummy_entry)?;
         self.storage_manager.end_session();
-        return match self.find_entry_in_node(&found_node, &dummy_entry, false) {
-            Some((entry, _)) => Ok(Some(entry)),
-            None => Ok(None),
-        };
+        Ok(found_node.entries.get(pos).filter(|entry| entry.index == dummy_entry.index).cloned())
     }
 
     // Searches for exact values and never
@@ -141,60 +162,25 @@ impl BTree {
             right_ref: 0,
             left_ref: None,
         };
-        let found_node = self.search_helper(&dummy_entry, 1)?;
-        let mut cursor = 0;
-        loop {
-            if cursor >= found_node.entries.len() {
-                return Ok(vec![])
-            }
-            if found_node.entries[cursor].index == dummy_entry.index {
-                break
-            }
-            cursor += 1;
-        }
-        let mut current_node:Node = found_node;
+        let (_, mut current_node, mut cursor) = self.seek(&dummy_entry)?;
         let mut output = vec![];
         loop {
-            if cursor >= current_node.entries.len(){
+            if cursor >= current_node.entries.len() {
                 if current_node.next_node == 0 {
                     break;
                 }
                 current_node = self.get_node(current_node.next_node)?;
                 cursor = 0;
+                continue;
             }
             if current_node.entries[cursor].index != dummy_entry.index {
                 break;
             }
             output.push(current_node.entries[cursor].clone());
-            cursor+=1;
+            cursor += 1;
         }
         self.storage_manager.end_session();
         Ok(output)
-        /*
-        return match self.find_entry_in_node(&found_node, &dummy_entry, false) {
-            Some((entry, pos)) => {
-                let mut cursor = pos;
-                let mut current_node:Node = found_node;
-                let mut output = vec![];
-                loop {
-                    if cursor >= current_node.entries.len(){
-                        if current_node.next_node == 0 {
-                            break;
-                        }
-                        current_node = self.get_node(current_node.next_node)?;
-                        cursor = 0;
-                    }
-                    if current_node.entries[cursor].index != dummy_entry.index {
-                        break;
-                    }
-                    output.push(current_node.entries[cursor].clone());
-                    cursor+=1;
-                }
-                self.storage_manager.end_session();
-                Ok(output)
-            },
-            None => Ok(vec![]),
-        };*/
     }
 
     pub fn greater_than(&mut self, index: IndexValue) -> io::Result<Vec<NodeEntry>> {
@@ -204,11 +190,7 @@ impl BTree {
             right_ref: 0,
             left_ref: None,
         };
-        let found_node = self.search_helper(&dummy_entry, 1)?;
-        let start_pos = match found_node.entries.binary_search(&dummy_entry) {
-            Ok(pos) => pos,
-            Err(pos) => pos,
-        };
+        let (_, found_node, start_pos) = self.seek(&dummy_entry)?;
         let mut output = vec![];
         output.extend(found_node.entries[start_pos..].to_vec());
         let mut next_node = found_node.next_node;
@@ -223,33 +205,20 @@ impl BTree {
     }
 
     pub fn less_than(&mut self, index: IndexValue, equals: bool) -> io::Result<Vec<NodeEntry>> {
-        let dummy_entry = NodeEntry {
-            index: index,
-            right_ref: 0,
-            left_ref: None,
-        };
         self.storage_manager.start_read_session()?;
         let mut output = vec![];
         let mut current_node = self.get_node(1)?;
         while !current_node.leaf {
             current_node = self.get_node(current_node.entries[0].left_ref.unwrap())?;
         }
-        let mut pos = current_node.entries.len();
-        while pos == current_node.entries.len() {
-            pos = match current_node.entries.binary_search(&dummy_entry) {
-                Ok(pos) => pos,
-                Err(pos) => pos,
-            };
-            if pos < current_node.entries.len() {
-                if equals {
-                    output.extend(current_node.entries[..=pos].to_vec());
-                } else {
-                    output.extend(current_node.entries[..pos].to_vec());
-                }
-            } else {
-                output.extend(current_node.entries);
-            }
-            if current_node.next_node == 0 {
+        loop {
+            let end = current_node
+                .entries
+                .partition_point(|entry| entry.index < index || (equals && entry.index == index));
+            let whole_node = end == current_node.entries.len();
+            output.extend(current_node.entries.drain(..end));
+            // Entries are sorted along the leaf chain, so the first one past the bound ends the scan
+            if !whole_node || current_node.next_node == 0 {
                 break;
             }
             current_node = self.get_node(current_node.next_node)?;
@@ -258,6 +227,150 @@ impl BTree {
         return Ok(output);
     }
 
+    /// Describes everything wrong with the tree's structure, empty when it's sound
+    pub fn check(&mut self) -> io::Result<Vec<String>> {
+        self.storage_manager.start_read_session()?;
+        let mut check = TreeCheck {
+            problems: vec![],
+            visited: HashSet::new(),
+            leaves: vec![],
+            leaf_depth: None,
+        };
+        self.check_node(1, None, None, 0, &mut check);
+
+        // Walking the leaves has to meet them in the order the tree holds them
+        let mut chain = vec![];
+        let mut next_node = check.leaves.first().copied().unwrap_or(0);
+        while next_node != 0 {
+            if chain.contains(&next_node) {
+                check.problems.push(format!("leaf chain loops back to block {}", next_node));
+                break;
+            }
+            chain.push(next_node);
+            next_node = match self.get_node(next_node) {
+                Ok(node) => node.next_node,
+                Err(e) => {
+                    check.problems.push(format!("leaf chain reaches unreadable block {}: {}", next_node, e));
+                    break;
+                }
+            };
+        }
+        if let Some(at) = (0..cmp::max(chain.len(), check.leaves.len())).find(|at| chain.get(*at) != check.leaves.get(*at)) {
+            check.problems.push(format!(
+                "leaf chain has block {:?} where the tree has block {:?} after {} leaves",
+                chain.get(at), check.leaves.get(at), at
+            ));
+        }
+
+        let unreachable: Vec<u32> = self.storage_manager.stored_ids()?
+            .into_iter()
+            .filter(|id| !check.visited.contains(id))
+            .collect();
+        if !unreachable.is_empty() {
+            check.problems.push(format!("blocks {:?} aren't reachable from the root", unreachable));
+        }
+        self.storage_manager.end_session();
+        Ok(check.problems)
+    }
+
+    // Entries have to be in order and within the separators that led to the node
+    fn check_node(
+        &mut self,
+        node_ref: u32,
+        lower: Option<&IndexValue>,
+        upper: Option<&IndexValue>,
+        depth: usize,
+        check: &mut TreeCheck,
+    ) {
+        if !check.visited.insert(node_ref) {
+            check.problems.push(format!("block {} is reachable more than once", node_ref));
+            return;
+        }
+        let node = match self.get_node(node_ref) {
+            Ok(node) => node,
+            Err(e) => {
+                check.problems.push(format!("block {} is unreadable: {}", node_ref, e));
+                return;
+            }
+        };
+        if node.entries.windows(2).any(|pair| pair[0].index > pair[1].index) {
+            check.problems.push(format!("block {} has entries out of order", node_ref));
+        }
+        let outside = node.entries.iter().find(|entry| {
+            lower.is_some_and(|lower| entry.index < *lower) || upper.is_some_and(|upper| entry.index > *upper)
+        });
+        if let Some(entry) = outside {
+            check.problems.push(format!("block {} has {:?} outside its parent's separators", node_ref, entry.index));
+        }
+        if node.leaf {
+            match check.leaf_depth {
+                Some(leaf_depth) if leaf_depth != depth => {
+                    check.problems.push(format!("leaf {} is {} levels deep, others are {}", node_ref, depth, leaf_depth));
+                }
+                _ => check.leaf_depth = Some(depth),
+            }
+            check.leaves.push(node_ref);
+            return;
+        }
+        if node.entries.is_empty() {
+            check.problems.push(format!("block {} has no children", node_ref));
+            return;
+        }
+        if node.entries.windows(2).any(|pair| pair[1].left_ref != Some(pair[0].right_ref)) {
+            check.problems.push(format!("block {} has children that don't line up", node_ref));
+        }
+        match node.entries[0].left_ref {
+            Some(left_ref) => self.check_node(left_ref, lower, Some(&node.entries[0].index), depth + 1, check),
+            None => check.problems.push(format!("block {} has no leftmost child", node_ref)),
+        }
+        for (at, entry) in node.entries.iter().enumerate() {
+            let next_separator = node.entries.get(at + 1).map(|next| &next.index).or(upper);
+            self.check_node(entry.right_ref, Some(&entry.index), next_separator, depth + 1, check);
+        }
+    }
+
+    /// The lowest entry, None when the index is empty
+    pub fn first(&mut self) -> io::Result<Option<NodeEntry>> {
+        self.storage_manager.start_read_session()?;
+        let mut current_node = self.get_node(1)?;
+        while !current_node.leaf {
+            current_node = self.get_node(current_node.entries[0].left_ref.unwrap())?;
+        }
+        // Deletes can leave empty leaves behind
+        let first = loop {
+            let next_node = current_node.next_node;
+            if let Some(entry) = current_node.entries.into_iter().next() {
+                break Some(entry);
+            }
+            if next_node == 0 {
+                break None;
+            }
+            current_node = self.get_node(next_node)?;
+        };
+        self.storage_manager.end_session();
+        Ok(first)
+    }
+
+    /// Every entry in index order, walking the leaves from the leftmost one
+    pub fn entries(&mut self) -> io::Result<Vec<NodeEntry>> {
+        self.storage_manager.start_read_session()?;
+        let mut output = vec![];
+        let mut current_node = self.get_node(1)?;
+        while !current_node.leaf {
+            current_node = self.get_node(current_node.entries[0].left_ref.unwrap())?;
+        }
+        loop {
+            let next_node = current_node.next_node;
+            output.extend(current_node.entries);
+            if next_node == 0 {
+                break;
+            }
+            current_node = self.get_node(next_node)?;
+        }
+        self.storage_manager.end_session();
+        Ok(output)
+    }
+
     fn insert_helper(
         &mut self,
         current_node_ref: u32,
@@ -277,17 +390,19 @@ impl BTree {
                 } else {
                     insert_entry(&entry, &mut right);
                 }
-                self.storage_manager.delete_data(current_node_ref)?;
-
-                // Get New Block
-                let mut left_block = self.storage_manager.allocate_block();
+                // The left half keeps the block so the leaf before it still links to it, only the root moves
+                let mut left_block = current_node_ref;
                 if left_block == 1 {
-                    left_block = self.storage_manager.allocate_block();
+                    self.storage_manager.delete_data(current_node_ref)?;
+                    left_block = self.storage_manager.allocate_block()?;
+                    if left_block == 1 {
+                        left_block = self.storage_manager.allocate_block()?;
+                    }
                 }
 
-                let mut right_block = self.storage_manager.allocate_block();
+                let mut right_block = self.storage_manager.allocate_block()?;
                 if right_block == 1 {
-                    right_block = self.storage_manager.allocate_block();
+                    right_block = self.storage_manager.allocate_block()?;
                 }
 
                 let left_node = Node {
@@ -304,7 +419,7 @@ impl BTree {
                 let encoded_left = unwrap_encode(&left_node);
                 let encoded_right = unwrap_encode(&right_node);
                 if left_block == 1 || right_block == 1 {
-                    println!("Left or right are zero");
+                    warn!("Split wrote a node to block 1");
                 }
 
                 self.storage_manager
@@ -347,27 +462,26 @@ impl BTree {
                         left_ref: Some(left_ref),
                         right_ref: right_ref,
                     };
-                    if current_node.entries.len() >= self.node_size as usize {
+                    // Goes right after the child that split, with equal values a search could put it elsewhere
+                    insert_non_leaf_entry_at(&new_entry, &mut current_node.entries, (dest_pos + 1) as usize);
+                    if current_node.entries.len() > self.node_size as usize {
+                        // The median moves up alone, its children are already the last of the left half
+                        // and the first of the right one
                         let median_index = current_node.entries.len() / 2;
-                        let mut left = current_node.entries[..median_index].to_vec();
-                        let mut right = current_node.entries[median_index..].to_vec();
+                        let left = current_node.entries[..median_index].to_vec();
+                        let right = current_node.entries[median_index + 1..].to_vec();
                         let median_value = &current_node.entries[median_index];
-                        if new_entry < *median_value {
-                            insert_non_leaf_entry(&new_entry, &mut left);
-                        } else {
-                            insert_non_leaf_entry(&new_entry, &mut right);
-                        }
                         self.storage_manager.delete_data(current_node_ref)?;
 
                         // Get New Block
-                        let mut left_block = self.storage_manager.allocate_block();
+                        let mut left_block = self.storage_manager.allocate_block()?;
                         if left_block == 1 {
-                            left_block = self.storage_manager.allocate_block();
+                            left_block = self.storage_manager.allocate_block()?;
                         }
 
-                        let mut right_block = self.storage_manager.allocate_block();
+                        let mut right_block = self.storage_manager.allocate_block()?;
                         if right_block == 1 {
-                            right_block = self.storage_manager.allocate_block();
+                            right_block = self.storage_manager.allocate_block()?;
                         }
 
                         let left_node = Node {
@@ -384,7 +498,7 @@ impl BTree {
                         let encoded_left = unwrap_encode(&left_node);
                         let encoded_right = unwrap_encode(&right_node);
                         if left_block == 1 || right_block == 1 {
-                            println!("Left or right are zero");
+                            warn!("Split wrote a node to block 1");
                         }
 
                         self.storage_manager
@@ -397,8 +511,6 @@ impl BTree {
                             right_block,
                         ));
                     } else {
-                        insert_non_leaf_entry(&new_entry, &mut current_node.entries);
-
                         self.storage_manager.delete_data(current_node_ref)?;
                         self.storage_manager
                             .write_data(unwrap_encode(&current_node), Some(current_node_ref))?;
@@ -410,117 +522,54 @@ impl BTree {
         }
     }
 
-    fn delete_helper(
-        &mut self,
-        index: &NodeEntry,
-        current_node_ref: u32,
-        block_num_match: bool,
-    ) -> io::Result<(bool, Option<NodeEntry>, IndexValue)> {
-        let mut current_node = self.get_node(current_node_ref)?;
-        if current_node.leaf {
-            let found_entry = self.find_entry_in_node(&current_node, index, block_num_match);
-            let deleted = match found_entry {
-                Some((entry, loc)) => {
-                    current_node.entries.remove(loc);
-                    Some(entry)
-                }
-                None => None,
-            };
-            if deleted != None && current_node.entries.len() == 0 {
-                self.storage_manager.delete_data(current_node_ref)?;
-                return Ok((true, deleted, IndexValue::Integer(0)));
-            } else if deleted != None {
-                self.storage_manager.delete_data(current_node_ref)?;
-                self.storage_manager
-                    .write_data(serde_json::to_vec(&current_node)?, Some(current_node_ref))?;
+    // Emptied nodes stay where they are so the leaf chain and the separators above them stay valid,
+    // compaction rebuilds the tree without them
+    fn delete_helper(&mut self, index: &NodeEntry, block_num_match: bool) -> io::Result<Option<NodeEntry>> {
+        let (mut node_ref, mut node, _) = self.seek(index)?;
+        // Equal values can run on into the leaves after this one
+        loop {
+            if let Some((entry, loc)) = self.find_entry_in_node(&node, index, block_num_match) {
+                node.entries.remove(loc);
+                self.storage_manager.delete_data(node_ref)?;
+                self.storage_manager.write_data(serde_json::to_vec(&node)?, Some(node_ref))?;
+                return Ok(Some(entry));
             }
-            if current_node.entries.len() == 0 {
-                return Ok((false, deleted, IndexValue::Integer(0)));
+            let continues = node.entries.last().is_none_or(|last| last.index <= index.index);
+            if !continues || node.next_node == 0 {
+                return Ok(None);
             }
-            return Ok((false, deleted, current_node.entries[0].index.clone()));
-        }
-        let mut found_index: usize = match current_node.entries.binary_search(index) {
-            Ok(pos) => pos,
-            Err(pos) => pos,
-        };
-        if found_index == current_node.entries.len() {
-            found_index = found_index - 1;
+            node_ref = node.next_node;
+            node = self.get_node(node_ref)?;
         }
-        // Left side
-        let (should_rebalance, deleted_entry, new_index) =
-            match index < &current_node.entries[found_index as usize] {
-                true => self.delete_helper(
-                    index,
-                    current_node.entries[found_index as usize].left_ref.unwrap(),
-                    block_num_match,
-                )?,
+    }
+
+    // The leaf holding the first entry that isn't below the index, with the entry's position in it.
+    // Values equal to a separator can be on either side of it, so the search keeps left of them
+    fn seek(&mut self, index: &NodeEntry) -> io::Result<(u32, Node, usize)> {
+        let mut node_ref = 1;
+        let mut node = self.get_node(node_ref)?;
+        while !node.leaf {
+            let found_index = cmp::min(
+                node.entries.partition_point(|entry| entry.index < index.index),
+                node.entries.len() - 1,
+            );
+            node_ref = match index <= &node.entries[found_index] {
+                // Left side
+                true => node.entries[found_index].left_ref.unwrap(),
                 // Right side
-                false => self.delete_helper(
-                    index,
-                    current_node.entries[found_index as usize].right_ref,
-                    block_num_match,
-                )?,
+                false => node.entries[found_index].right_ref,
             };
-        if deleted_entry == None {
-            return Ok((should_rebalance, deleted_entry, new_index));
+            node = self.get_node(node_ref)?;
         }
-        current_node.entries[found_index].index = new_index;
-        if should_rebalance {
-            if current_node.entries.len() == 1 {
-                self.storage_manager.delete_data(current_node_ref)?;
-                return Ok((true, deleted_entry, IndexValue::Integer(0)));
-            } else {
-                // No need to re-assign left and right ref if removal was on right most node
-                if current_node.entries.len() - 1 == found_index {
-                    if index < &current_node.entries[found_index as usize] {
-                        current_node.entries[found_index as usize - 1].right_ref =
-                            current_node.entries[found_index as usize].right_ref;
-                        current_node.entries.remove(found_index);
-                    } else {
-                        current_node.entries.remove(found_index);
-                    }
-                } else if found_index == 0 {
-                    if index < &current_node.entries[found_index as usize] {
-                        current_node.entries.remove(found_index);
-                    } else {
-                        current_node.entries[found_index as usize + 1].left_ref =
-                            current_node.entries[found_index as usize].left_ref;
-                        current_node.entries.remove(found_index);
-                    }
-                } else {
-                    current_node.entries[found_index + 1 as usize].right_ref = current_node.entries
-                        [found_index - 1 as usize]
-                        .left_ref
-                        .unwrap();
-                    current_node.entries.remove(found_index);
-                }
+        // Deletes can leave leaves with nothing at or above the index before the one that has it
+        loop {
+            let pos = node.entries.partition_point(|entry| entry.index < index.index);
+            if pos < node.entries.len() || node.next_node == 0 {
+                return Ok((node_ref, node, pos));
             }
+            node_ref = node.next_node;
+            node = self.get_node(node_ref)?;
         }
-        self.storage_manager.delete_data(current_node_ref)?;
-        self.storage_manager
-            .write_data(serde_json::to_vec(&current_node)?, Some(current_node_ref))?;
-        return Ok((false, deleted_entry, current_node.entries[0].index.clone()));
-    }
-
-    fn search_helper(&mut self, index: &NodeEntry, current_node_ref: u32) -> io::Result<Node> {
-        let current_node = self.get_node(current_node_ref)?;
-        if current_node.leaf {
-            return Ok(current_node);
-        }
-        let mut found_index: usize = match current_node.entries.binary_search(index) {
-            Ok(pos) => pos,
-            Err(pos) => pos,
-        };
-        if found_index == current_node.entries.len() {
-            found_index = found_index - 1;
-        }
-        if index < &current_node.entries[found_index as usize] {
-            return self.search_helper(
-                index,
-                current_node.entries[found_index as usize].left_ref.unwrap(),
-            );
-        }
-        return self.search_helper(index, current_node.entries[found_index as usize].right_ref);
     }
 
     fn get_node(&mut self, location: u32) -> io::Result<Node> {
@@ -552,39 +601,19 @@ impl BTree {
         };
     }
 
+    // Only looks in this node, with match_reference the entry also has to point at the same row
     fn find_entry_in_node(
-        &mut self,
+        &self,
         node: &Node,
         entry: &NodeEntry,
         match_reference: bool,
     ) -> Option<(NodeEntry, usize)> {
-        match node.entries.binary_search(&entry) {
-            Ok(pos) => Some((node.entries[pos].clone(), pos)),
-            Err(pos) => {
-                if pos < node.entries.len() {
-                    if node.entries[pos].index == entry.index && !match_reference {
-                        return Some((node.entries[pos].clone(), pos));
-                    } else if node.entries[pos].index == entry.index && match_reference {
-                        let mut current_pos = pos;
-                        while node.entries[current_pos].index == entry.index
-                            && current_pos < node.entries.len()
-                        {
-                            if node.entries[current_pos].right_ref == entry.right_ref {
-                                return Some((node.entries[pos].clone(), pos));
-                            }
-                            current_pos += 1;
-                        }
-                        if current_pos < node.entries.len() && node.next_node != 0 {
-                            let next_node = self.get_node(node.next_node).unwrap();
-                            return self.find_entry_in_node(&next_node, entry, true);
-                        }
-                    }
-                    None
-                } else {
-                    None
-                }
-            }
-        }
+        let first = node.entries.partition_point(|e| e.index < entry.index);
+        node.entries[first..]
+            .iter()
+            .take_while(|e| e.index == entry.index)
+            .position(|e| !match_reference || e.right_ref == entry.right_ref)
+            .map(|offset| (node.entries[first + offset].clone(), first + offset))
     }
 }
 
@@ -608,13 +637,12 @@ fn unwrap_encode(node: &Node) -> Vec<u8> {
     };
 }
 
-fn insert_non_leaf_entry(entry: &NodeEntry, destination: &mut Vec<NodeEntry>) -> usize {
-    let location = insert_entry(&entry, destination);
+fn insert_non_leaf_entry_at(entry: &NodeEntry, destination: &mut Vec<NodeEntry>, location: usize) {
+    destination.insert(location, entry.clone());
     if location > 0 {
         destination[location - 1].right_ref = entry.left_ref.unwrap();
     }
     if location < destination.len() - 1 {
         destination[location + 1].left_ref = Some(entry.right_ref);
     }
-    return location;
 }
@@ -0,0 +1,31 @@
+use crate::config::config_reader::DbConfig;
+
+use super::open_database;
+
+pub struct Compaction {
+    pub table: String,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl Compaction {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+// Rewrites the named table, or every table, into densely packed files
+pub fn compact(config: DbConfig, table: Option<&str>) -> Result<Vec<Compaction>, String> {
+    let mut dbm = open_database(config, false)?;
+    let names = match table {
+        Some(table) => vec![table.to_string()],
+        None => dbm.reader().table_names(),
+    };
+    let mut compactions = vec![];
+    for name in names {
+        let (bytes_before, bytes_after) = dbm.compact(&name)?;
+        compactions.push(Compaction { table: name, bytes_before, bytes_after });
+    }
+    dbm.shutdown().map_err(|e| format!("Unable to flush compacted tables: {}", e))?;
+    Ok(compactions)
+}
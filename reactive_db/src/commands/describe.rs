@@ -0,0 +1,8 @@
+use crate::config::config_reader::DbConfig;
+use crate::database::describe::TableDescription;
+
+use super::open_database;
+
+pub fn describe(config: DbConfig) -> Result<Vec<TableDescription>, String> {
+    open_database(config, false)?.describe_graph()
+}
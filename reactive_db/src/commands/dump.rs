@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_reader::{DbConfig, TableConfig};
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::hooks::transforms::errors_table;
+use crate::types::Entry;
+
+use super::open_database;
+
+const DUMP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct DumpFile {
+    pub version: u32,
+    pub tables: BTreeMap<String, Vec<Entry>>,
+}
+
+// Writes every table, derived ones included so the output can be inspected on its own
+pub fn dump(config: DbConfig, output: &mut dyn Write) -> Result<(), String> {
+    let dbm = open_database(config, false)?;
+    let reader = dbm.reader();
+    let mut tables = BTreeMap::new();
+    for name in reader.table_names() {
+        let entries = reader.scan(&name)?;
+        tables.insert(name, entries);
+    }
+    let dump_file = DumpFile { version: DUMP_VERSION, tables };
+    serde_json::to_writer_pretty(&mut *output, &dump_file).map_err(|e| format!("Unable to write dump: {}", e))?;
+    output.write_all(b"\n").map_err(|e| format!("Unable to write dump: {}", e))
+}
+
+// Only source tables are replayed, derived tables are rebuilt by their transforms and rows get new ids
+pub fn restore(config: DbConfig, input: &mut dyn Read) -> Result<usize, String> {
+    let dump_file: DumpFile = serde_json::from_reader(input).map_err(|e| format!("Unable to read dump: {}", e))?;
+    if dump_file.version != DUMP_VERSION {
+        return Err(format!("Unsupported dump version {}", dump_file.version));
+    }
+    let source_tables: Vec<String> = config.tables.iter().filter_map(|table| match table {
+        TableConfig::Source(source_config) => Some(source_config.name.clone()),
+        TableConfig::Derived(_) => None,
+    }).collect();
+    // Failed rows are written again when the derived tables are rebuilt
+    let known_tables: Vec<String> = config.tables.iter().flat_map(|table| match table {
+        TableConfig::Source(source_config) => vec![source_config.name.clone()],
+        TableConfig::Derived(derived_config) => vec![derived_config.name.clone(), errors_table(&derived_config.name)],
+    }).collect();
+    for name in dump_file.tables.keys() {
+        if !known_tables.contains(name) {
+            return Err(format!("Dump contains table {} which is not in the config", name));
+        }
+    }
+
+    let mut dbm = open_database(config, true)?;
+    for name in &source_tables {
+        if !dbm.reader().scan(name)?.is_empty() {
+            return Err(format!("Table {} already has data, restore needs an empty data directory", name));
+        }
+    }
+    let mut restored = 0;
+    for name in &source_tables {
+        let entries = match dump_file.tables.get(name) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for entry in entries {
+            let mut entry = entry.clone();
+            entry.remove(ROW_ID_COLUMN_NAME);
+            dbm.insert_entry(name, entry, None)?;
+            restored += 1;
+        }
+    }
+    dbm.shutdown().map_err(|e| format!("Unable to flush restored data: {}", e))?;
+    Ok(restored)
+}
@@ -0,0 +1,26 @@
+mod tests;
+
+pub mod compact;
+pub mod describe;
+pub mod dump;
+pub mod rebuild;
+pub mod snapshot;
+pub mod verify;
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::config_reader::DbConfig;
+use crate::database::database_manager::DatabaseManager;
+
+// Opens the data directory a config points at without starting the server, fails while a server has it open
+fn open_database(config: DbConfig, create: bool) -> Result<DatabaseManager, String> {
+    let destination = config.storage_destination.clone();
+    if !Path::new(&destination).is_dir() {
+        if !create {
+            return Err(format!("Data directory {} does not exist", destination));
+        }
+        fs::create_dir_all(&destination).map_err(|e| format!("Unable to create {}: {}", destination, e))?;
+    }
+    DatabaseManager::from_config(config, destination)
+}
@@ -0,0 +1,12 @@
+use crate::config::config_reader::DbConfig;
+use crate::database::database_manager::TableChange;
+
+use super::open_database;
+
+// Recomputes a derived table and the tables downstream of it, progress is written to stderr
+pub fn rebuild(config: DbConfig, table: &str) -> Result<Vec<TableChange>, String> {
+    let mut dbm = open_database(config, false)?;
+    let changes = dbm.rebuild(table, &mut |name, replayed| eprintln!("{}: replayed {} rows", name, replayed))?;
+    dbm.shutdown().map_err(|e| format!("Unable to flush rebuilt tables: {}", e))?;
+    Ok(changes)
+}
@@ -0,0 +1,47 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::config::config_reader::DbConfig;
+use crate::constants::LOCK_FILE;
+use crate::database::snapshot::read_manifest;
+use crate::storage::directory_lock::DirectoryLock;
+
+use super::open_database;
+
+// Copies a complete snapshot into an empty data directory, returns how many files it restored
+pub fn restore_snapshot(config: DbConfig, snapshot: &str) -> Result<usize, String> {
+    let manifest = read_manifest(snapshot)?;
+    let destination = config.storage_destination.clone();
+    fs::create_dir_all(&destination).map_err(|e| format!("Unable to create {}: {}", destination, e))?;
+    {
+        let _lock = DirectoryLock::acquire(&destination).map_err(|e| e.to_string())?;
+        let existing = fs::read_dir(&destination).map_err(|e| format!("Unable to read {}: {}", destination, e))?;
+        for entry in existing {
+            let entry = entry.map_err(|e| format!("Unable to read {}: {}", destination, e))?;
+            if entry.file_name() != LOCK_FILE {
+                return Err(format!("{} is not empty, a snapshot can only be restored into an empty data directory", destination));
+            }
+        }
+        for file in &manifest.files {
+            // Names come from the manifest, so they're kept from pointing outside either directory
+            if Path::new(&file.name).file_name() != Some(OsStr::new(&file.name)) {
+                return Err(format!("Snapshot lists an invalid file name {}", file.name));
+            }
+            let source = Path::new(snapshot).join(&file.name);
+            let bytes = fs::metadata(&source).map_err(|e| format!("Unable to read {}: {}", source.display(), e))?.len();
+            if bytes != file.bytes {
+                return Err(format!("{} is {} bytes, the snapshot recorded {}", source.display(), bytes, file.bytes));
+            }
+            let target = Path::new(&destination).join(&file.name);
+            fs::copy(&source, &target)
+                .and_then(|_| File::open(&target)?.sync_all())
+                .map_err(|e| format!("Unable to copy {}: {}", source.display(), e))?;
+        }
+        File::open(&destination).and_then(|directory| directory.sync_all()).map_err(|e| format!("Unable to sync {}: {}", destination, e))?;
+    }
+    // Opening the database checks the files can be read, tables the snapshot didn't have start out empty
+    let mut dbm = open_database(config, false)?;
+    dbm.shutdown().map_err(|e| format!("Unable to flush restored tables: {}", e))?;
+    Ok(manifest.files.len())
+}
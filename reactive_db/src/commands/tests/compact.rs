@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::commands::compact::compact;
+    use crate::commands::verify::verify;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::types::EntryValue;
+
+    use crate::commands::tests::{config, person};
+
+    #[test]
+    fn reclaims_the_space_of_deleted_rows() {
+        let destination = "db/compact";
+        let _ = fs::remove_dir_all(destination);
+        fs::create_dir(destination).unwrap();
+        {
+            let mut dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+            for n in 0..400 {
+                dbm.insert_entry("people", person(&format!("person number {:0>40}", n), n), None).unwrap();
+            }
+            for n in 0..390 {
+                dbm.delete_all("people", "age".to_string(), EntryValue::Integer(n)).unwrap();
+            }
+            dbm.shutdown().unwrap();
+        }
+
+        assert!(verify(config(destination)).unwrap().is_empty());
+
+        let compactions = compact(config(destination), Some("people")).unwrap();
+        assert_eq!(compactions.len(), 1);
+        assert!(compactions[0].reclaimed() > 0);
+        assert!(!Path::new("db/compact/people.compaction").exists());
+        assert!(!Path::new("db/compact/people.db.compacting").exists());
+        assert!(verify(config(destination)).unwrap().is_empty());
+
+        let mut dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+        let mut ages: Vec<EntryValue> = dbm.reader().scan("people").unwrap().iter()
+            .map(|entry| entry.get("age").unwrap().clone())
+            .collect();
+        ages.sort_by_key(|age| format!("{:?}", age));
+        assert_eq!(ages, (390..400).map(EntryValue::Integer).collect::<Vec<EntryValue>>());
+        let found = dbm.reader().find_one("people", "age".to_string(), EntryValue::Integer(395)).unwrap().unwrap();
+        assert_eq!(found.get("name"), Some(&EntryValue::Str(format!("person number {:0>40}", 395))));
+        // New rows go into the compacted files like any others
+        dbm.insert_entry("people", person("ann", 34), None).unwrap();
+        assert_eq!(dbm.reader().scan("people").unwrap().len(), 11);
+        dbm.shutdown().unwrap();
+        drop(dbm);
+
+        // Every table is compacted without a name
+        let compactions = compact(config(destination), None).unwrap();
+        let tables: Vec<&str> = compactions.iter().map(|compaction| compaction.table.as_str()).collect();
+        assert!(tables.contains(&"people") && tables.contains(&"adults"));
+        assert!(verify(config(destination)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn finishes_an_interrupted_compaction_on_open() {
+        let destination = "db/compact_interrupted";
+        let _ = fs::remove_dir_all(destination);
+        fs::create_dir(destination).unwrap();
+        {
+            let mut dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+            dbm.insert_entry("people", person("ann", 34), None).unwrap();
+            dbm.shutdown().unwrap();
+        }
+        // As if the server died after the marker was written but before the files were moved
+        fs::copy("db/compact_interrupted/people.db", "db/compact_interrupted/people.db.compacting").unwrap();
+        fs::write("db/compact_interrupted/people.compaction", "db/compact_interrupted/people.db").unwrap();
+
+        let dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+        assert!(!Path::new("db/compact_interrupted/people.compaction").exists());
+        assert!(!Path::new("db/compact_interrupted/people.db.compacting").exists());
+        assert_eq!(dbm.reader().scan("people").unwrap().len(), 1);
+    }
+}
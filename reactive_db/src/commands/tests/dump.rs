@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::commands::dump::{dump, restore};
+    use crate::commands::verify::verify;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::types::EntryValue;
+
+    use crate::commands::tests::{config, person};
+
+    #[test]
+    fn restores_a_dump_and_recomputes_derived_tables() {
+        let source = "db/dump_source";
+        let destination = "db/dump_destination";
+        let _ = fs::remove_dir_all(source);
+        let _ = fs::remove_dir_all(destination);
+        fs::create_dir(source).unwrap();
+        {
+            let mut dbm = DatabaseManager::from_config(config(source), source.to_string()).unwrap();
+            for (name, age) in [("ann", 34), ("bob", 12), ("cat", 18)].iter() {
+                dbm.insert_entry("people", person(name, *age), None).unwrap();
+            }
+            dbm.shutdown().unwrap();
+        }
+        assert!(verify(config(source)).unwrap().is_empty());
+
+        let mut dumped = vec![];
+        dump(config(source), &mut dumped).unwrap();
+        assert_eq!(restore(config(destination), &mut dumped.as_slice()).unwrap(), 3);
+        assert!(verify(config(destination)).unwrap().is_empty());
+
+        let dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+        let mut names: Vec<EntryValue> = dbm.reader().scan("adults").unwrap().iter()
+            .map(|entry| entry.get("name").unwrap().clone())
+            .collect();
+        names.sort_by_key(|name| format!("{:?}", name));
+        assert_eq!(names, vec![EntryValue::Str("ann".to_string()), EntryValue::Str("cat".to_string())]);
+        assert_eq!(dbm.reader().scan("people").unwrap().len(), 3);
+        drop(dbm);
+
+        // A second restore would duplicate every row
+        assert!(restore(config(destination), &mut dumped.as_slice()).is_err());
+    }
+
+    #[test]
+    fn refuses_missing_data_directories() {
+        assert!(dump(config("db/dump_missing"), &mut vec![]).is_err());
+        assert!(verify(config("db/dump_missing")).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+mod compact;
+mod dump;
+mod verify;
+
+#[cfg(test)]
+use crate::config::config_reader::DbConfig;
+#[cfg(test)]
+use crate::types::EntryValue;
+#[cfg(test)]
+use crate::Entry;
+
+// People and the adults derived from them, kept in the given directory
+#[cfg(test)]
+fn config(storage_destination: &str) -> DbConfig {
+    let mut config: DbConfig = serde_yaml::from_str("
+storage_destination: unset
+tables:
+  - Source:
+      name: people
+      columns:
+        name: Str
+        age: Integer
+  - Derived:
+      name: adults
+      transform_definition:
+        FilterTransform:
+          source_table: people
+          filter: age > 17
+").unwrap();
+    config.storage_destination = storage_destination.to_string();
+    config
+}
+
+#[cfg(test)]
+fn person(name: &str, age: isize) -> Entry {
+    let mut entry = Entry::new();
+    entry.insert("name".to_string(), EntryValue::Str(name.to_string()));
+    entry.insert("age".to_string(), EntryValue::Integer(age));
+    entry
+}
@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::commands::verify::{repair, verify};
+    use crate::database::database_manager::DatabaseManager;
+    use crate::types::EntryValue;
+
+    use crate::commands::tests::{config, person};
+
+    #[test]
+    fn rebuilds_indexes_that_lost_rows() {
+        let destination = "db/verify_repair";
+        let _ = fs::remove_dir_all(destination);
+        fs::create_dir(destination).unwrap();
+        {
+            let mut dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+            for n in 0..50 {
+                dbm.insert_entry("people", person(&format!("person {}", n), n), None).unwrap();
+            }
+            dbm.shutdown().unwrap();
+        }
+        assert!(verify(config(destination)).unwrap().is_empty());
+        assert!(repair(config(destination)).unwrap().is_empty());
+
+        // As if the index file was lost in a crash, it comes back empty
+        fs::remove_file("db/verify_repair/people_age.index").unwrap();
+        let problems = verify(config(destination)).unwrap();
+        assert_eq!(problems.len(), 50);
+        assert!(problems[0].contains("missing from index age"));
+
+        assert_eq!(repair(config(destination)).unwrap(), vec!["people".to_string()]);
+        assert!(verify(config(destination)).unwrap().is_empty());
+        let dbm = DatabaseManager::from_config(config(destination), destination.to_string()).unwrap();
+        let found = dbm.reader().find_one("people", "age".to_string(), EntryValue::Integer(42)).unwrap().unwrap();
+        assert_eq!(found.get("name"), Some(&EntryValue::Str("person 42".to_string())));
+    }
+}
@@ -0,0 +1,29 @@
+use crate::config::config_reader::DbConfig;
+
+use super::open_database;
+
+// Checks that every table's indexes and stored rows agree, returning what doesn't
+pub fn verify(config: DbConfig) -> Result<Vec<String>, String> {
+    let dbm = open_database(config, false)?;
+    let reader = dbm.reader();
+    let mut problems = vec![];
+    for name in reader.table_names() {
+        problems.extend(reader.verify(&name)?);
+    }
+    Ok(problems)
+}
+
+// Rebuilds the indexes of every table verify finds a problem in, returning those tables
+pub fn repair(config: DbConfig) -> Result<Vec<String>, String> {
+    let mut dbm = open_database(config, false)?;
+    let reader = dbm.reader();
+    let mut repaired = vec![];
+    for name in reader.table_names() {
+        if !reader.verify(&name)?.is_empty() {
+            dbm.rebuild_indexes(&name)?;
+            repaired.push(name);
+        }
+    }
+    dbm.shutdown().map_err(|e| format!("Unable to flush rebuilt indexes: {}", e))?;
+    Ok(repaired)
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+
+use crate::hooks::transforms::errors_table;
+use crate::storage::storage_engine::StorageOptions;
+use super::config_parser::{parse_actions, parse_transform_definition};
+use super::config_diff::table_name;
+use super::config_reader::{DbConfig, TableConfig, TransformType};
+use super::table_graph::{TableGraph, TableSummary};
+
+// Parses every table the way the server would and returns the graph of tables
+pub fn check_config(config: &DbConfig) -> Result<TableGraph, String> {
+    let (_, actions) = parse_actions(config.action_config.clone())?;
+    let mut names = HashSet::new();
+    for table in &config.tables {
+        let name = table_name(table);
+        if !names.insert(name.to_string()) {
+            return Err(format!("Table {} is defined more than once", name));
+        }
+    }
+    for table in &config.tables {
+        let options = match table {
+            TableConfig::Source(source_config) => StorageOptions::new(
+                source_config.block_size,
+                source_config.compression,
+                source_config.compression_dictionary,
+                source_config.storage.unwrap_or_default(),
+            ),
+            TableConfig::Derived(derived_config) => StorageOptions::new(
+                derived_config.block_size,
+                derived_config.compression,
+                derived_config.compression_dictionary,
+                derived_config.storage.unwrap_or_default(),
+            ),
+        };
+        options.map_err(|e| format!("Table {}: {}", table_name(table), e))?;
+        if let TableConfig::Derived(derived_config) = table {
+            let errors = errors_table(&derived_config.name);
+            if names.contains(&errors) {
+                return Err(format!("Table {} is reserved for the rows {} fails to transform", errors, derived_config.name));
+            }
+        }
+    }
+
+    let mut summaries = vec![];
+    for table in &config.tables {
+        let summary = match table {
+            TableConfig::Source(source_config) => TableSummary {
+                name: source_config.name.clone(),
+                kind: "source".to_string(),
+                inputs: vec![],
+                outputs: vec![],
+            },
+            TableConfig::Derived(derived_config) => {
+                let (_, inputs) = parse_transform_definition(derived_config.transform_definition.clone(), &actions)
+                    .map_err(|e| format!("Table {}: {}", derived_config.name, e))?;
+                TableSummary {
+                    name: derived_config.name.clone(),
+                    kind: transform_kind(&derived_config.transform_definition).to_string(),
+                    inputs,
+                    outputs: vec![],
+                }
+            }
+        };
+        summaries.push(summary);
+    }
+
+    TableGraph::new(summaries)
+}
+
+pub fn format_summary(graph: &TableGraph) -> String {
+    let mut output = String::new();
+    for summary in graph.tables() {
+        output.push_str(&format!("{} ({})\n", summary.name, summary.kind));
+        if !summary.inputs.is_empty() {
+            output.push_str(&format!("  reads from: {}\n", summary.inputs.join(", ")));
+        }
+        if !summary.outputs.is_empty() {
+            output.push_str(&format!("  feeds: {}\n", summary.outputs.join(", ")));
+        }
+    }
+    output
+}
+
+fn transform_kind(transform: &TransformType) -> &str {
+    match transform {
+        TransformType::FunctionTransform(_) => "function",
+        TransformType::FilterTransform(_) => "filter",
+        TransformType::UnionTransform(_) => "union",
+        TransformType::AggregationTransform(_) => "aggregation",
+        TransformType::ActionTransform(_) => "action",
+    }
+}
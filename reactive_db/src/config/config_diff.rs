@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use super::config_reader::{SourceTableConfig, TableConfig, TransformTableConfig};
+
+// What has to happen to the running tables to match a new config
+#[derive(Debug, Default)]
+pub struct ConfigDiff {
+    pub added: Vec<TableConfig>,
+    pub removed: Vec<String>,
+    // Source tables that gained columns, every existing column is unchanged
+    pub extended_sources: Vec<SourceTableConfig>,
+    // Derived tables whose transform changed, they are recomputed from scratch
+    pub changed_derived: Vec<TransformTableConfig>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.extended_sources.is_empty() && self.changed_derived.is_empty()
+    }
+}
+
+// Source tables keep their rows across a reload, so their existing columns can't be removed or retyped
+pub fn diff_tables(old: &[TableConfig], new: &[TableConfig]) -> Result<ConfigDiff, String> {
+    let old_tables: HashMap<&str, &TableConfig> = old.iter().map(|table| (table_name(table), table)).collect();
+    let new_names: Vec<&str> = new.iter().map(table_name).collect();
+    let mut diff = ConfigDiff::default();
+    for table in new {
+        let old_table = match old_tables.get(table_name(table)) {
+            Some(old_table) => *old_table,
+            None => {
+                diff.added.push(table.clone());
+                continue;
+            }
+        };
+        match (old_table, table) {
+            (TableConfig::Source(old_source), TableConfig::Source(new_source)) => {
+                for (column, data_type) in &old_source.columns {
+                    match new_source.columns.get(column) {
+                        Some(new_type) if new_type == data_type => {}
+                        Some(new_type) => Err(format!(
+                            "Column {} of source table {} can't change from {:?} to {:?}",
+                            column, old_source.name, data_type, new_type
+                        ))?,
+                        None => Err(format!(
+                            "Column {} of source table {} can't be removed",
+                            column, old_source.name
+                        ))?,
+                    }
+                }
+                // The table's rows are only in its files, so they can't be rewritten with other settings on a reload
+                if (old_source.block_size, old_source.compression, old_source.compression_dictionary, old_source.storage)
+                    != (new_source.block_size, new_source.compression, new_source.compression_dictionary, new_source.storage)
+                {
+                    Err(format!(
+                        "Storage options of source table {} can't change on a reload, restart the server to change them",
+                        old_source.name
+                    ))?
+                }
+                if new_source.columns.len() > old_source.columns.len() {
+                    diff.extended_sources.push(new_source.clone());
+                }
+            }
+            (TableConfig::Derived(old_derived), TableConfig::Derived(new_derived)) => {
+                if old_derived != new_derived {
+                    diff.changed_derived.push(new_derived.clone());
+                }
+            }
+            (TableConfig::Source(source), TableConfig::Derived(_)) => Err(format!(
+                "Source table {} can't become a derived table, remove it first",
+                source.name
+            ))?,
+            (TableConfig::Derived(derived), TableConfig::Source(_)) => Err(format!(
+                "Derived table {} can't become a source table, remove it first",
+                derived.name
+            ))?,
+        }
+    }
+    for table in old {
+        if !new_names.contains(&table_name(table)) {
+            diff.removed.push(table_name(table).to_string());
+        }
+    }
+    Ok(diff)
+}
+
+pub fn table_name(table: &TableConfig) -> &str {
+    match table {
+        TableConfig::Source(config) => &config.name,
+        TableConfig::Derived(config) => &config.name,
+    }
+}
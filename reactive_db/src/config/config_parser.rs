@@ -1,23 +1,49 @@
 use std::collections::HashMap;
 
-use crate::{actions::Action, table::{table_trait::Table, types::{Column, TableType}}, types::DataType};
+use crate::{actions::{Action, workspace::Workspace}, table::{table_trait::Table, types::{Column, TableType}}, types::DataType};
 use crate::hooks::transforms::Transform;
+use crate::storage::storage_engine::{StorageKind, StorageOptions};
 use crate::table::storage_manager_table::StorageManagerTable;
 
-use super::{config_reader::{TransformTableConfig, TransformType}, expression_parser::Statement};
+use super::{config_reader::{ActionEnvConfig, Importable, TransformTableConfig, TransformType}, expression_parser::Statement};
 
+// Tables that don't set their own storage use default_storage
 pub fn parse_transform_config(
     config: TransformTableConfig,
     storage_path: String,
+    default_storage: StorageKind,
     actions: &HashMap<String, Action>
 ) -> Result<(StorageManagerTable, Transform), String> {
     let name = config.name;
+    let storage = config.storage.unwrap_or(default_storage);
+    let options = StorageOptions::new(config.block_size, config.compression, config.compression_dictionary, storage)?;
+    let (transform, mut input_tables) = parse_transform_definition(config.transform_definition, actions)?;
     let mut columns = vec![];
     columns.push(Column::new("_entryId".to_string(), DataType::ID));
+    match transform {
+        Transform::Function(_) | Transform::Filter(_) => {
+            columns.push(Column::new("_sourceEntryId".to_string(), DataType::ID));
+        }
+        _ => {}
+    }
+    let table = StorageManagerTable::new(name, columns, TableType::Derived(transform.clone()), storage_path, options);
+    match table {
+        Ok(mut t) => {
+            t.get_input_tables().append(&mut input_tables);
+            Ok((t, transform))
+        }
+        Err(e) => Err(format!("{:?}", e)),
+    }
+}
+
+// Parses a transform without touching storage, also giving the tables it reads from
+pub fn parse_transform_definition(
+    definition: TransformType,
+    actions: &HashMap<String, Action>
+) -> Result<(Transform, Vec<String>), String> {
     let mut input_tables = vec![];
-    let transform = match config.transform_definition {
+    let transform = match definition {
         TransformType::FunctionTransform(config) => {
-            columns.push(Column::new("_sourceEntryId".to_string(), DataType::ID));
             let mut statements = vec![];
             input_tables.push(config.source_table);
             for raw_statement in config.functions {
@@ -26,7 +52,6 @@ pub fn parse_transform_config(
             Transform::Function(statements)
         }
         TransformType::FilterTransform(config) => {
-            columns.push(Column::new("_sourceEntryId".to_string(), DataType::ID));
             let statement = Statement::new_comparison(config.filter)?;
             input_tables.push(config.source_table);
             Transform::Filter(statement)
@@ -47,22 +72,29 @@ pub fn parse_transform_config(
         }
         TransformType::ActionTransform(action_config) => {
             input_tables.push(action_config.source_table);
-            let action = actions.get(&action_config.name);
-            let action = match action {
-                Some(action) => {
-                    action.to_owned()
-                }
-                None => {panic!("Error: No action with name {} found", action_config.name)}
-            };
-            Transform::Action(action)
+            match actions.get(&action_config.name) {
+                Some(action) => Transform::Action(action.to_owned()),
+                None => Err(format!("No action with name {} found", action_config.name))?,
+            }
         }
     };
-    let table = StorageManagerTable::new(name, columns, TableType::Derived(transform.clone()), storage_path);
-    match table {
-        Ok(mut t) => {
-            t.get_input_tables().append(&mut input_tables);
-            Ok((t, transform))
+    Ok((transform, input_tables))
+}
+
+// Loads the workspace and actions a config refers to, imports have already been inlined by `read_config_file`
+pub fn parse_actions(action_config: Option<Importable<ActionEnvConfig>>) -> Result<(Workspace, HashMap<String, Action>), String> {
+    let mut actions: HashMap<String, Action> = HashMap::new();
+    let mut workspace = Workspace::new("./".to_string());
+    match action_config {
+        Some(Importable::Import(path)) => Err(format!("Action config {} not processed correctly!", path))?,
+        Some(Importable::Inline(unwrapped_action_cfg)) => {
+            workspace = Workspace::new(unwrapped_action_cfg.workspace_dir);
+            for action in unwrapped_action_cfg.actions {
+                let action_obj = Action::new(action.file, action.function);
+                actions.insert(action.name, action_obj);
+            }
         }
-        Err(e) => Err(format!("{:?}", e)),
+        None => {}
     }
-}
\ No newline at end of file
+    Ok((workspace, actions))
+}
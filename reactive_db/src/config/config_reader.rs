@@ -1,3 +1,5 @@
+use crate::storage::compression::Compression;
+use crate::storage::storage_engine::StorageKind;
 use crate::types::DataType;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Result;
@@ -9,26 +11,142 @@ use std::io;
 pub struct DbConfig {
     pub tables: Vec<TableConfig>,
     pub storage_destination: String,
-    pub action_config: Option<Importable<ActionEnvConfig>>
+    pub action_config: Option<Importable<ActionEnvConfig>>,
+    pub network: Option<NetworkConfig>,
+    pub auth: Option<AuthConfig>,
+    // Threads serving reads next to the thread applying writes
+    pub reader_threads: Option<usize>,
+    // Memory shared by the page caches of every table and index file
+    pub buffer_pool_bytes: Option<usize>,
+    // Writes are only synced to disk at shutdown and after admin requests without it
+    pub durability: Option<DurabilityConfig>,
+    // Where tables that don't set their own storage keep their rows, in files unless set
+    pub storage: Option<StorageKind>,
+    pub logging: Option<LoggingConfig>,
+    pub metrics: Option<MetricsConfig>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DurabilityConfig {
+    pub mode: DurabilityMode,
+    // How long periodic mode holds writes back before syncing them together, in milliseconds
+    pub interval_ms: Option<u64>,
+}
+
+// How far an insert or delete has to get before the client is told it's done
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DurabilityMode {
+    // Answered once the write is in the buffer pool
+    None,
+    // Every table and index file the write touched is synced before it's answered
+    PerCommit,
+    // Writes are answered in groups after the sync following them
+    Periodic,
+}
+
+// Serves Prometheus metrics on /metrics when present
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    // Defaults to 127.0.0.1
+    pub bind: Option<String>,
+    pub port: u16,
+}
+
+// Without it the server logs info and above as text to stderr
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggingConfig {
+    // A level such as debug, or a filter such as reactive_db::database=debug,info. RUST_LOG takes precedence
+    pub level: Option<String>,
+    pub format: Option<LogFormat>,
+    // Appended to instead of writing to stderr
+    pub file: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkConfig {
+    pub max_frame_size: Option<usize>,
+    pub tls: Option<TlsConfig>,
+}
+
+// PEM files for the TCP listener, connections are plaintext without them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    // When set clients have to present a certificate signed by one of these authorities
+    pub client_ca_path: Option<String>,
+}
+
+// When present every connection is checked against these users and roles
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthConfig {
+    pub users: Vec<UserConfig>,
+    pub roles: Vec<RoleConfig>,
+    // Roles given to connections that send no credentials, they get no access when unset
+    pub anonymous_roles: Option<Vec<String>>,
+}
+
+// Secrets are stored as salted argon2 hashes, as printed by the hash-secret command
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserConfig {
+    pub name: String,
+    pub password_hash: Option<String>,
+    pub api_token_hash: Option<String>,
+    pub roles: Vec<String>,
+}
+
+// Table names map to the rights granted on them, "*" applies to every table
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleConfig {
+    pub name: String,
+    pub tables: BTreeMap<String, Vec<Permission>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Permission {
+    Read,
+    Listen,
+    Insert,
+    Delete,
+    // Server wide operations such as reloading the config, granted through the `*` table
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TableConfig {
     Derived(TransformTableConfig),
     Source(SourceTableConfig),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SourceTableConfig {
     pub name: String,
     pub columns: BTreeMap<String, DataType>,
+    // Page size and compression of files created for the table, existing files keep theirs
+    pub block_size: Option<u32>,
+    pub compression: Option<Compression>,
+    pub compression_dictionary: Option<bool>,
+    // File or Memory, the database's storage setting when unset
+    pub storage: Option<StorageKind>,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TransformTableConfig {
     pub name: String,
     pub transform_definition: TransformType,
+    pub block_size: Option<u32>,
+    pub compression: Option<Compression>,
+    pub compression_dictionary: Option<bool>,
+    pub storage: Option<StorageKind>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TransformType {
     FunctionTransform(FunctionTransformConfig),
     FilterTransform(FilterTransformConfig),
@@ -37,48 +155,48 @@ pub enum TransformType {
     ActionTransform(ActionTransformConfig)
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FunctionTransformConfig {
     pub source_table: String,
     pub functions: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct UnionTransformConfig {
     pub tables_and_foreign_keys: Vec<(String, String)>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FilterTransformConfig {
     pub source_table: String,
     pub filter: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct AggregationTransformConfig {
     pub source_table: String,
     pub aggregated_column: String,
     pub functions: Vec<String>,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ActionEnvConfig {
     pub workspace_dir: String,
     pub actions: Vec<ActionConfig>
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ActionConfig {
     pub name: String,
     pub file: String,
     pub function: String,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ActionTransformConfig {
     pub name: String,
     pub source_table: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Importable<T> {
     Import(String),
     Inline(T)
@@ -1,4 +1,9 @@
+mod tests;
+
 pub mod config_reader;
 pub mod expression_parser;
 pub mod config_parser;
+pub mod config_check;
+pub mod config_diff;
+pub mod table_graph;
 pub mod types;
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+pub struct TableSummary {
+    pub name: String,
+    pub kind: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+// The tables of a config and which feed which, kept in an order where every table comes after its inputs
+pub struct TableGraph {
+    tables: Vec<TableSummary>,
+}
+
+impl TableGraph {
+    // Tables are given in config order with their inputs filled in, which is also the order ties are broken in
+    pub fn new(mut tables: Vec<TableSummary>) -> Result<TableGraph, String> {
+        let positions: HashMap<String, usize> = tables.iter().enumerate().map(|(i, table)| (table.name.clone(), i)).collect();
+        for table in &tables {
+            for input in &table.inputs {
+                if !positions.contains_key(input) {
+                    return Err(format!("Table {} reads from {} which does not exist", table.name, input));
+                }
+            }
+        }
+        let mut outputs: HashMap<String, Vec<String>> = HashMap::new();
+        for table in &tables {
+            for input in &table.inputs {
+                outputs.entry(input.clone()).or_default().push(table.name.clone());
+            }
+        }
+        for table in &mut tables {
+            table.outputs = outputs.remove(&table.name).unwrap_or_default();
+        }
+        if let Some(cycle) = find_cycle(&tables, &positions) {
+            return Err(format!("Tables read from each other in a cycle: {}", cycle.join(" -> ")));
+        }
+
+        // Repeatedly take the first table in config order whose inputs have all been taken
+        let mut waiting_on: Vec<usize> = tables.iter().map(|table| table.inputs.len()).collect();
+        let mut taken = vec![false; tables.len()];
+        let mut order = vec![];
+        while order.len() < tables.len() {
+            let next = (0..tables.len()).find(|&i| !taken[i] && waiting_on[i] == 0).unwrap();
+            taken[next] = true;
+            for output in &tables[next].outputs {
+                waiting_on[positions[output]] -= 1;
+            }
+            order.push(next);
+        }
+        let mut slots: Vec<Option<TableSummary>> = tables.into_iter().map(Some).collect();
+        let tables = order.into_iter().map(|i| slots[i].take().unwrap()).collect();
+        Ok(TableGraph { tables })
+    }
+
+    // Every table, inputs first
+    pub fn tables(&self) -> &[TableSummary] {
+        &self.tables
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TableSummary> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    // The given tables and every table that reads from them, directly or not, inputs first
+    pub fn downstream(&self, roots: &[String]) -> Vec<String> {
+        let mut reached: Vec<&str> = roots.iter().map(|root| root.as_str()).collect();
+        for table in &self.tables {
+            if table.inputs.iter().any(|input| reached.contains(&input.as_str())) && !reached.contains(&table.name.as_str()) {
+                reached.push(&table.name);
+            }
+        }
+        self.order(&reached)
+    }
+
+    // Puts a set of tables in dependency order
+    pub fn order(&self, names: &[&str]) -> Vec<String> {
+        self.tables.iter().filter(|table| names.contains(&table.name.as_str())).map(|table| table.name.clone()).collect()
+    }
+}
+
+// Depth first search that returns the path of the first cycle it runs into, starting and ending on the same table
+fn find_cycle(tables: &[TableSummary], positions: &HashMap<String, usize>) -> Option<Vec<String>> {
+    // 0 unvisited, 1 on the current path, 2 done
+    let mut state = vec![0u8; tables.len()];
+    let mut path = vec![];
+    for start in 0..tables.len() {
+        if state[start] == 0 {
+            if let Some(cycle) = visit(start, tables, positions, &mut state, &mut path) {
+                return Some(cycle);
+            }
+        }
+    }
+    None
+}
+
+fn visit(
+    current: usize,
+    tables: &[TableSummary],
+    positions: &HashMap<String, usize>,
+    state: &mut Vec<u8>,
+    path: &mut Vec<usize>,
+) -> Option<Vec<String>> {
+    state[current] = 1;
+    path.push(current);
+    for output in &tables[current].outputs {
+        let next = positions[output];
+        if state[next] == 1 {
+            let start = path.iter().position(|&i| i == next).unwrap();
+            let mut cycle: Vec<String> = path[start..].iter().map(|&i| tables[i].name.clone()).collect();
+            cycle.push(tables[next].name.clone());
+            return Some(cycle);
+        }
+        if state[next] == 0 {
+            if let Some(cycle) = visit(next, tables, positions, state, path) {
+                return Some(cycle);
+            }
+        }
+    }
+    path.pop();
+    state[current] = 2;
+    None
+}
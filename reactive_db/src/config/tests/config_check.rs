@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::config_check::check_config;
+    use crate::config::config_reader::{read_config_file, DbConfig, DurabilityMode};
+
+    fn parse(raw: &str) -> DbConfig {
+        serde_yaml::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn describes_the_table_graph() {
+        let config = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        let graph = check_config(&config).unwrap();
+        let test_table = graph.tables().iter().find(|s| s.name == "testTable").unwrap();
+        assert_eq!(test_table.kind, "source");
+        assert!(test_table.outputs.contains(&"derived".to_string()));
+        assert!(test_table.outputs.contains(&"filterTest".to_string()));
+        let union = graph.tables().iter().find(|s| s.name == "unionTest").unwrap();
+        assert_eq!(union.kind, "union");
+        assert_eq!(union.inputs, vec!["users".to_string(), "grades".to_string()]);
+    }
+
+    #[test]
+    fn names_missing_inputs() {
+        let config = parse("
+storage_destination: db/unused
+tables:
+  - Derived:
+      name: adults
+      transform_definition:
+        FilterTransform:
+          source_table: people
+          filter: age > 17
+");
+        let error = check_config(&config).err().unwrap();
+        assert!(error.contains("adults"));
+        assert!(error.contains("people"));
+    }
+
+    #[test]
+    fn rejects_duplicate_and_unparsable_tables() {
+        let duplicate = parse("
+storage_destination: db/unused
+tables:
+  - Source:
+      name: people
+      columns:
+        age: Integer
+  - Source:
+      name: people
+      columns:
+        name: Str
+");
+        assert!(check_config(&duplicate).err().unwrap().contains("more than once"));
+        let missing_action = parse("
+storage_destination: db/unused
+tables:
+  - Source:
+      name: people
+      columns:
+        age: Integer
+  - Derived:
+      name: scored
+      transform_definition:
+        ActionTransform:
+          name: score
+          source_table: people
+");
+        assert!(check_config(&missing_action).err().unwrap().contains("score"));
+    }
+
+    #[test]
+    fn reads_durability_modes() {
+        let config = parse("
+storage_destination: db/unused
+tables: []
+durability:
+  mode: per-commit
+");
+        assert_eq!(config.durability.unwrap().mode, DurabilityMode::PerCommit);
+        let config = parse("
+storage_destination: db/unused
+tables: []
+durability:
+  mode: periodic
+  interval_ms: 20
+");
+        let durability = config.durability.unwrap();
+        assert_eq!(durability.mode, DurabilityMode::Periodic);
+        assert_eq!(durability.interval_ms, Some(20));
+    }
+}
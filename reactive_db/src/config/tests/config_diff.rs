@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::config_diff::diff_tables;
+    use crate::config::config_reader::TableConfig;
+
+    fn tables(raw: &str) -> Vec<TableConfig> {
+        serde_yaml::from_str(raw).unwrap()
+    }
+
+    const BASE: &str = "
+- Source:
+    name: people
+    columns:
+      name: Str
+      age: Integer
+- Derived:
+    name: adults
+    transform_definition:
+      FilterTransform:
+        source_table: people
+        filter: age > 17
+- Derived:
+    name: named
+    transform_definition:
+      FunctionTransform:
+        source_table: people
+        functions:
+          - label ~ name
+";
+
+    #[test]
+    fn finds_added_removed_and_changed_tables() {
+        let new = tables("
+- Source:
+    name: people
+    columns:
+      name: Str
+      age: Integer
+      email: Str
+- Derived:
+    name: adults
+    transform_definition:
+      FilterTransform:
+        source_table: people
+        filter: age > 20
+- Derived:
+    name: seniors
+    transform_definition:
+      FilterTransform:
+        source_table: people
+        filter: age > 64
+");
+        let diff = diff_tables(&tables(BASE), &new).unwrap();
+        assert_eq!(diff.removed, vec!["named".to_string()]);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.extended_sources[0].name, "people");
+        assert_eq!(diff.changed_derived[0].name, "adults");
+        assert!(diff_tables(&tables(BASE), &tables(BASE)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_incompatible_source_changes() {
+        let retyped = tables("
+- Source:
+    name: people
+    columns:
+      name: Str
+      age: Str
+");
+        let error = diff_tables(&tables(BASE), &retyped).err().unwrap();
+        assert!(error.contains("age") && error.contains("people"));
+        let removed_column = tables("
+- Source:
+    name: people
+    columns:
+      name: Str
+");
+        assert!(diff_tables(&tables(BASE), &removed_column).err().unwrap().contains("age"));
+        let now_derived = tables("
+- Derived:
+    name: people
+    transform_definition:
+      FilterTransform:
+        source_table: adults
+        filter: age > 17
+");
+        assert!(diff_tables(&tables(BASE), &now_derived).is_err());
+    }
+
+    #[test]
+    fn rejects_source_storage_changes() {
+        for option in ["block_size: 8192", "compression: Lz4", "storage: Memory"].iter() {
+            let changed = tables(&format!("
+- Source:
+    name: people
+    columns:
+      name: Str
+      age: Integer
+    {}
+", option));
+            let error = diff_tables(&tables(BASE), &changed).err().unwrap();
+            assert!(error.contains("Storage options") && error.contains("people"), "{}", option);
+        }
+    }
+}
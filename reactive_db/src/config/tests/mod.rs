@@ -0,0 +1,3 @@
+mod config_check;
+mod config_diff;
+mod table_graph;
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::table_graph::{TableGraph, TableSummary};
+
+    fn table(name: &str, inputs: &[&str]) -> TableSummary {
+        TableSummary {
+            name: name.to_string(),
+            kind: if inputs.is_empty() { "source" } else { "filter" }.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            outputs: vec![],
+        }
+    }
+
+    #[test]
+    fn orders_tables_after_their_inputs() {
+        let graph = TableGraph::new(vec![
+            table("seniors", &["adults"]),
+            table("adults", &["people"]),
+            table("people", &[]),
+            table("everyone", &["people", "seniors"]),
+            table("pets", &[]),
+        ]).unwrap();
+        let order: Vec<&str> = graph.tables().iter().map(|table| table.name.as_str()).collect();
+        assert_eq!(order, vec!["people", "adults", "seniors", "everyone", "pets"]);
+        assert_eq!(graph.get("people").unwrap().outputs, vec!["adults".to_string(), "everyone".to_string()]);
+        assert_eq!(graph.downstream(&["adults".to_string()]), vec!["adults", "seniors", "everyone"]);
+        assert_eq!(graph.order(&["everyone", "people"]), vec!["people", "everyone"]);
+    }
+
+    #[test]
+    fn reports_cycles_with_their_path() {
+        let error = TableGraph::new(vec![
+            table("people", &[]),
+            table("a", &["people", "c"]),
+            table("b", &["a"]),
+            table("c", &["b"]),
+        ]).err().unwrap();
+        assert!(error.contains("a -> b -> c -> a"), "{}", error);
+        let error = TableGraph::new(vec![table("itself", &["itself"])]).err().unwrap();
+        assert!(error.contains("itself -> itself"), "{}", error);
+    }
+
+    #[test]
+    fn names_missing_inputs() {
+        let error = TableGraph::new(vec![table("adults", &["people"])]).err().unwrap();
+        assert_eq!(error, "Table adults reads from people which does not exist");
+    }
+}
@@ -1,5 +1,33 @@
+use std::time::Duration;
+
 pub const BTREE_NODE_SIZE: u32 = 20;
 pub const ROW_ID_COLUMN_NAME: &str = "_entryId";
 pub const SOURCE_ENTRY_ID: &str = "_sourceEntryId";
+pub const SOURCE_ENTRY_IDS: &str = "_sourceEntryIds";
 pub const UNION_MATCHING_KEY: &str = "matchingKey";
 pub const AGGREGATION_KEY: &str = "aggregatedColumn";
+pub use reactivedb_rust_client::framing::DEFAULT_MAX_FRAME_SIZE;
+pub const PROTOCOL_VERSION: u32 = 2;
+// Clients that negotiated at least this version are sent Close before the server closes their connection
+pub const CLOSE_PROTOCOL_VERSION: u32 = 2;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_READER_THREADS: usize = 4;
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+pub const BACKFILL_PROGRESS_INTERVAL: usize = 1000;
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const DURATION_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+pub const DEFAULT_METRICS_BIND: &str = "127.0.0.1";
+pub const ERRORS_TABLE_SUFFIX: &str = "_errors";
+pub const FAILED_ENTRY: &str = "entry";
+pub const FAILED_SOURCE_TABLE: &str = "sourceTable";
+pub const DEFAULT_BLOCK_SIZE: u32 = 4096;
+pub const MIN_BLOCK_SIZE: u32 = 512;
+pub const MAX_BLOCK_SIZE: u32 = 65536;
+pub const ZSTD_LEVEL: i32 = 3;
+// Rows seen before a compression dictionary is trained, and the most it can hold
+pub const DICTIONARY_SAMPLES: usize = 256;
+pub const DICTIONARY_SIZE: usize = 16 * 1024;
+pub const DEFAULT_BUFFER_POOL_BYTES: usize = 64 * 1024 * 1024;
+pub const LOCK_FILE: &str = ".lock";
+pub const DEFAULT_SYNC_INTERVAL_MS: u64 = 10;
+pub const SNAPSHOT_MANIFEST: &str = "snapshot.json";
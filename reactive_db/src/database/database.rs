@@ -1,112 +1,168 @@
 use uuid::Uuid;
 use crate::{actions::workspace::Workspace, table::{multi_source_table::MultiSourceTable, table_trait::Table}};
 use crate::constants;
+use crate::metrics::{self, ROWS_DELETED, ROWS_INSERTED};
+use crate::storage::buffer_pool::{self, PageCopy};
 use crate::types::CommitedEdit;
 use crate::table::storage_manager_table::StorageManagerTable;
 use crate::hooks::hook::{Hook, Event};
 use crate::types::{Entry, DBEdit, EditType};
 use crate::EntryValue;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 
 use super::db_trait::{DB, HookMap};
 
+// Each table is locked on its own so readers only wait on the table they query
+pub type TableHandle = Arc<Mutex<MultiSourceTable>>;
+pub type TableMap = Arc<RwLock<HashMap<String, TableHandle>>>;
+
 pub struct Database{
-    pub tables: HashMap<String, MultiSourceTable>,
+    pub tables: TableMap,
     transaction_manager: TransactionManager
 }
 
-impl DB for Database {
-    fn delete_all(
-        &mut self,
-        table: &str,
-        column: String,
-        key: EntryValue,
-        hooks: &mut HookMap,
-        workspace: &Workspace
-    ) -> Result<Vec<CommitedEdit>, String> {
-        let transaction_id = self.transaction_manager.start_transaction();
-        self.hidden_delete_all(table, column, key, hooks, workspace, transaction_id, true)
+// Read only view of the tables that can be handed to other threads.
+// A read holds its table's lock for its whole duration, so it sees the table either before or after any single write
+// to it. Tables are locked one at a time, not for a whole transaction, so there is no consistent view across tables:
+// a read can see rows a transform cascade has written to a derived table before the cascade finishes, and those rows
+// are gone again if the transaction is rolled back.
+#[derive(Clone)]
+pub struct DatabaseReader {
+    tables: TableMap,
+}
+
+impl DatabaseReader {
+    pub fn get_table(&self, table: &str) -> Result<TableHandle, String> {
+        match self.tables.read().unwrap().get(table) {
+            Some(t) => Ok(t.clone()),
+            None => Err(format!("Unable to find table {}", table)),
+        }
     }
 
-    // TODO Abstract similar functionality to delete above
-    fn insert_entry(
-        &mut self,
-        table: &str,
-        entry: Entry,
-        source_table: Option<&str>,
-        hooks: &mut HookMap,
-        workspace: &Workspace
-    ) -> Result<Vec<CommitedEdit>, String> {
-        let transaction_id = self.transaction_manager.start_transaction();
-        self.hidden_insert(table, entry, source_table, hooks, workspace, transaction_id, true)
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
     }
 
-    fn find_one(
-        &mut self,
+    pub fn scan(&self, table: &str) -> Result<Vec<Entry>, String> {
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
+        match table_obj.scan() {
+            Ok(r) => Ok(r),
+            Err(e) => Err(format!("Error when scanning table {}: {}", table, e)),
+        }
+    }
+
+    pub fn verify(&self, table: &str) -> Result<Vec<String>, String> {
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
+        match table_obj.verify() {
+            Ok(r) => Ok(r),
+            Err(e) => Err(format!("Error when verifying table {}: {}", table, e)),
+        }
+    }
+
+    pub fn find_one(
+        &self,
         table: &str,
         column: String,
         key: EntryValue
     ) -> Result<Option<Entry>, String> {
-        let table_obj = match self.tables.get_mut(table) {
-            Some(t) => t,
-            None => Err(format!("Unable to find table {}", table))?,
-        };
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
         match table_obj.find_one(column, &key) {
             Ok(r) => Ok(r),
             Err(e) => Err(format!("Error when searching for entry {}", e)),
         }
     }
 
-    fn less_than_search(
-        &mut self,
-        table: &String,
+    pub fn less_than_search(
+        &self,
+        table: &str,
         column: String,
         key: EntryValue,
     ) -> Result<Vec<Entry>, String> {
-        let table_obj = match self.tables.get_mut(table) {
-            Some(t) => t,
-            None => Err(format!("Unable to find table {}", table))?,
-        };
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
         match table_obj.less_than(column, key, false) {
             Ok(r) => Ok(r),
             Err(e) => Err(format!("Error when searching for entry {}", e)),
         }
     }
 
-    fn get_all(
-        &mut self,
-        table: &String,
+    pub fn get_all(
+        &self,
+        table: &str,
         column: String,
         key: EntryValue,
     ) -> Result<Vec<Entry>, String> {
-        let table_obj = match self.tables.get_mut(table) {
-            Some(t) => t,
-            None => Err(format!("Unable to find table {}", table))?,
-        };
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
         match table_obj.get_all(column, key) {
             Ok(r) => Ok(r),
             Err(e) => Err(format!("Error when searching for entry {:?}", e)),
         }
     }
 
-    fn greater_than_search(
-        &mut self,
+    pub fn greater_than_search(
+        &self,
         table: &str,
         column: String,
         key: EntryValue,
     ) -> Result<Vec<Entry>, String> {
-        let table_obj = match self.tables.get_mut(table) {
-            Some(t) => t,
-            None => Err(format!("Unable to find table {}", table))?,
-        };
+        let table_obj = self.get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
         match table_obj.greater_than(column, key) {
             Ok(r) => Ok(r),
             Err(e) => Err(format!("Error when searching for entry {:?}", e)),
         }
     }
+}
+
+impl DB for Database {
+    fn delete_all(
+        &mut self,
+        table: &str,
+        column: String,
+        key: EntryValue,
+        hooks: &mut HookMap,
+        workspace: &Workspace
+    ) -> Result<Vec<CommitedEdit>, String> {
+        let transaction_id = self.transaction_manager.start_transaction();
+        let result = self.hidden_delete_all(table, column, key, hooks, workspace, transaction_id, true);
+        self.end_transaction(transaction_id, result.is_ok(), hooks, workspace);
+        result
+    }
+
+    // TODO Abstract similar functionality to delete above
+    fn insert_entry(
+        &mut self,
+        table: &str,
+        entry: Entry,
+        source_table: Option<&str>,
+        hooks: &mut HookMap,
+        workspace: &Workspace
+    ) -> Result<Vec<CommitedEdit>, String> {
+        let transaction_id = self.transaction_manager.start_transaction();
+        let result = self.hidden_insert(table, entry, source_table, hooks, workspace, transaction_id, true);
+        self.end_transaction(transaction_id, result.is_ok(), hooks, workspace);
+        result
+    }
+
+    fn get_all(
+        &mut self,
+        table: &String,
+        column: String,
+        key: EntryValue,
+    ) -> Result<Vec<Entry>, String> {
+        self.reader().get_all(table, column, key)
+    }
 
     fn get_output_tables(&mut self, table_name: &str) -> Vec<String> {
-        let table = self.tables.get_mut(table_name).unwrap();
+        let table = self.reader().get_table(table_name).unwrap();
+        let mut table = table.lock().unwrap();
         table.get_output_tables().clone()
     }
 }
@@ -116,12 +172,136 @@ impl Database {
     pub fn new(
         tables: HashMap<String, MultiSourceTable>,
     ) -> Database {
+        let tables = tables.into_iter().map(|(name, table)| (name, Arc::new(Mutex::new(table)))).collect();
         return Database {
-            tables,
+            tables: Arc::new(RwLock::new(tables)),
             transaction_manager: TransactionManager::new()
         }
     }
 
+    pub fn reader(&self) -> DatabaseReader {
+        DatabaseReader { tables: self.tables.clone() }
+    }
+
+    pub fn add_table(&self, name: String, table: MultiSourceTable) {
+        self.tables.write().unwrap().insert(name, Arc::new(Mutex::new(table)));
+    }
+
+    // Adds a table another database holds, both write to the same table
+    pub fn share_table(&self, name: String, table: TableHandle) {
+        self.tables.write().unwrap().insert(name, table);
+    }
+
+    // Puts the tables in place of the ones with the same names and takes the removed ones out, all under one lock so
+    // readers see either the old tables or the new ones. Returns the tables that were replaced or removed
+    pub fn swap_tables(&self, tables: Vec<(String, TableHandle)>, removed: &[String]) -> HashMap<String, TableHandle> {
+        let mut map = self.tables.write().unwrap();
+        let mut replaced = HashMap::new();
+        for (name, table) in tables {
+            if let Some(old) = map.insert(name.clone(), table) {
+                replaced.insert(name, old);
+            }
+        }
+        for name in removed {
+            if let Some(old) = map.remove(name) {
+                replaced.insert(name.clone(), old);
+            }
+        }
+        replaced
+    }
+
+    // Rebuilds every table's output list from the input lists
+    pub fn wire_outputs(&self) -> Result<(), String> {
+        let tables = self.tables.read().unwrap();
+        let mut input_refs = vec![];
+        for (name, table) in tables.iter() {
+            let mut table = table.lock().unwrap();
+            table.get_output_tables().clear();
+            for input_table_name in table.get_input_tables() {
+                input_refs.push((input_table_name.clone(), name.clone()));
+            }
+        }
+        for (source_table, dest_table) in input_refs {
+            let table_to_mod = match tables.get(&source_table) {
+                Some(t) => t,
+                None => Err(format!("Table {} reads from {} which does not exist", dest_table, source_table))?,
+            };
+            table_to_mod.lock().unwrap().get_output_tables().push(dest_table);
+        }
+        Ok(())
+    }
+
+    // Rebuilds the output lists of the named tables only, leaving those of the tables shared with another database as
+    // they are. Every table reading from a named table has to be named too
+    pub fn wire_outputs_of(&self, names: &[String]) -> Result<(), String> {
+        let tables = self.tables.read().unwrap();
+        let mut input_refs = vec![];
+        for name in names {
+            let table = match tables.get(name) {
+                Some(t) => t,
+                None => Err(format!("Unable to find table {}", name))?,
+            };
+            let mut table = table.lock().unwrap();
+            table.get_output_tables().clear();
+            for input_table_name in table.get_input_tables() {
+                if names.contains(input_table_name) {
+                    input_refs.push((input_table_name.clone(), name.clone()));
+                }
+            }
+        }
+        for (source_table, dest_table) in input_refs {
+            tables[&source_table].lock().unwrap().get_output_tables().push(dest_table);
+        }
+        Ok(())
+    }
+
+    // Undoes whatever a failed write left half applied, returning how many writes that was
+    pub fn rollback_open_transactions(&mut self, hooks: &mut HookMap, workspace: &Workspace) -> usize {
+        let open_transactions = self.transaction_manager.open_transactions();
+        for transaction_id in &open_transactions {
+            self.walk_back_edits(hooks, workspace, *transaction_id);
+        }
+        open_transactions.len()
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        for (name, table) in self.tables.read().unwrap().iter() {
+            if let Err(e) = table.lock().unwrap().flush() {
+                return Err(format!("Unable to flush table {}: {}", name, e));
+            }
+        }
+        Ok(())
+    }
+
+    // Starts copying every table's files into the directory, either all of them start or none
+    pub fn start_copy(&self, destination: &str) -> Result<Vec<(String, PageCopy)>, String> {
+        let mut copies = vec![];
+        for (name, table) in self.tables.read().unwrap().iter() {
+            match table.lock().unwrap().start_copy(destination) {
+                Ok(table_copies) => copies.extend(table_copies),
+                Err(e) => {
+                    for (_, copy) in &copies {
+                        buffer_pool::global().cancel_copy(copy);
+                    }
+                    return Err(format!("Unable to copy table {}: {}", name, e));
+                }
+            }
+        }
+        Ok(copies)
+    }
+
+    pub fn rebuild_indexes(&self, table: &str) -> Result<usize, String> {
+        let table_obj = self.reader().get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
+        table_obj.rebuild_indexes().map_err(|e| format!("Unable to rebuild the indexes of table {}: {}", table, e))
+    }
+
+    pub fn compact(&self, table: &str) -> Result<(u64, u64), String> {
+        let table_obj = self.reader().get_table(table)?;
+        let mut table_obj = table_obj.lock().unwrap();
+        table_obj.compact().map_err(|e| format!("Unable to compact table {}: {}", table, e))
+    }
+
     fn hidden_insert(
         &mut self,
         table: &str,
@@ -151,7 +331,6 @@ impl Database {
 
         let mut entries_to_insert = vec![];
         for current_table_edit in current_table_edits {
-            self.transaction_manager.add_pending_edit(transaction_id, current_table_edit.clone());
             entries_to_insert.push( match current_table_edit.edit_params {
                 EditType::Insert(entry) => entry,
                 EditType::Delete(_,_) => panic!("Recieved Delete During Insert"),
@@ -164,15 +343,17 @@ impl Database {
             });
         }
 
-        match self.tables.get_mut(table) {
-            Some(t) => {
+        match self.reader().get_table(table) {
+            Ok(t) => {
                 for entry_to_insert in entries_to_insert {
-                    match t.insert(entry_to_insert) {
+                    let inserted = t.lock().unwrap().insert(entry_to_insert);
+                    match inserted {
                         Ok(inserted_entry_results) => match inserted_entry_results {
                             Some(inserted_entry) =>{
                                 let entry_id = inserted_entry.get(constants::ROW_ID_COLUMN_NAME).unwrap().clone();
                                 let invert_edit = DBEdit::new(table.to_owned(), EditType::Delete(constants::ROW_ID_COLUMN_NAME.to_string(), entry_id));
                                 self.transaction_manager.edit_complete(transaction_id, vec![invert_edit]);
+                                metrics::global().increment(ROWS_INSERTED, &[("table", table)], 1);
                                 commited_edits.push(CommitedEdit::new(table.to_owned(), inserted_entry))
                             }
                             None => {}
@@ -184,7 +365,7 @@ impl Database {
                     }
                 }
             }
-            None => Err(format!("Unable to find table {}", table))?,
+            Err(e) => Err(e)?,
         };
         if hooks_enabled {
             let new_edits = self.execute_hooks(table, Event::PostInsert(None), None, Some(commited_edits.clone()), hooks, workspace);
@@ -220,15 +401,14 @@ impl Database {
             current_table_edits.push(edit);
         }
 
-        let table_obj = match self.tables.get_mut(table) {
-            Some(t) => t,
-            None => {
+        let table_obj = match self.reader().get_table(table) {
+            Ok(t) => t,
+            Err(e) => {
                 self.walk_back_edits(hooks, workspace, transaction_id);
-                return Err(format!("Unable to find table {}", table));
+                return Err(e);
             },
         };
         for current_table_delete in current_table_edits {
-            self.transaction_manager.add_pending_edit(transaction_id, current_table_delete.clone());
             let (column_to_match, value_to_match) = match current_table_delete.edit_params {
                 EditType::Delete(column, val) => (column, val),
                 EditType::Insert(_) => {
@@ -241,8 +421,10 @@ impl Database {
                 },
             };
             let mut invert_edits:Vec<DBEdit> = vec![];
-            match table_obj.delete(column_to_match, &value_to_match) {
+            let deleted = table_obj.lock().unwrap().delete(column_to_match, &value_to_match);
+            match deleted {
                 Ok(deleted) => {
+                    metrics::global().increment(ROWS_DELETED, &[("table", table)], deleted.len() as u64);
                     invert_edits = deleted.clone().iter().map(|deleted_entry| {
                         DBEdit::new(table.to_owned(), EditType::Insert(deleted_entry.to_owned()))
                     }).collect();
@@ -314,6 +496,15 @@ impl Database {
         return unwrapped_final_edits;
     }
 
+    // A write stays open until the edits it set off downstream are applied too, so a failure anywhere undoes all of it
+    fn end_transaction(&mut self, transaction_id: Uuid, succeeded: bool, hooks: &mut HookMap, workspace: &Workspace) {
+        if succeeded {
+            self.transaction_manager.finish_transaction(transaction_id);
+        } else {
+            self.walk_back_edits(hooks, workspace, transaction_id);
+        }
+    }
+
     fn walk_back_edits(
         &mut self, 
         hooks: &mut HookMap,
@@ -342,54 +533,38 @@ fn split_vec<T, F>(values: Vec<T>, predicate: F)->(Vec<T>, Vec<T>)
         return (t_list, f_list);
 } 
 
+// Keeps the edits that undo what each open transaction has applied so far
 struct TransactionManager {
-    edits_left: HashMap<Uuid, (Vec<DBEdit>, usize)>,
     invert_edits: HashMap<Uuid, Vec<DBEdit>>
 }
 
-// TODO Make this part of the graceful shutdown
 impl TransactionManager {
     pub fn new() -> TransactionManager {
         TransactionManager {
-            edits_left: HashMap::new(),
             invert_edits: HashMap::new()
         }
     }
 
-    pub fn add_pending_edit(&mut self, transaction_id: Uuid, edit: DBEdit) {
-        if let Some((edit_list, number_uncommited)) = self.edits_left.get_mut(&transaction_id){
-            edit_list.push(edit);
-            *number_uncommited += 1;
-        }
-    }
-
     pub fn edit_complete(&mut self, transaction_id: Uuid, mut invert_edit: Vec<DBEdit>) {
-        let mut delete = false;
-        if let Some((_, number_uncommited)) = self.edits_left.get_mut(&transaction_id){
-            *number_uncommited -= 1;
-            if *number_uncommited == 0 {
-                delete = true;
-            }
-        }
-        if delete {
-            self.edits_left.remove(&transaction_id);
-            self.invert_edits.remove(&transaction_id);
-        }
-        else {
-            if let Some(invert_edits) = self.invert_edits.get_mut(&transaction_id){
-                invert_edits.append(&mut invert_edit);
-            }
+        if let Some(invert_edits) = self.invert_edits.get_mut(&transaction_id){
+            invert_edits.append(&mut invert_edit);
         }
     }
 
     pub fn get_invert_of_transaction(&mut self, transaction_id: Uuid) -> Option<Vec<DBEdit>> {
-        self.edits_left.remove(&transaction_id);
         self.invert_edits.remove(&transaction_id)
     }
 
+    pub fn finish_transaction(&mut self, transaction_id: Uuid) {
+        self.invert_edits.remove(&transaction_id);
+    }
+
+    pub fn open_transactions(&self) -> Vec<Uuid> {
+        self.invert_edits.keys().cloned().collect()
+    }
+
     pub fn start_transaction(&mut self) -> Uuid {
         let id = Uuid::new_v4();
-        self.edits_left.insert(id.clone(), (vec![], 0));
         self.invert_edits.insert(id.clone(), vec![]);
         id
     }
@@ -1,171 +1,546 @@
 use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::mem;
 
 use tokio::sync::mpsc::Sender;
+use tracing::warn;
 
-use crate::{actions::{Action, workspace::Workspace}, config::{config_parser::parse_transform_config, config_reader::{DbConfig, Importable, TableConfig}}, table::{multi_source_table::MultiSourceTable, table_trait::Table, types::{Column, TableType}}};
-use super::{db_trait::DB, database::Database};
+use crate::{actions::{Action, workspace::Workspace}, config::{config_check::check_config, table_graph::TableGraph, config_diff::{diff_tables, table_name, ConfigDiff}, config_parser::{parse_actions, parse_transform_config}, config_reader::{DbConfig, SourceTableConfig, TableConfig}}, table::{multi_source_table::MultiSourceTable, table_trait::Table, types::{Column, TableType}}};
+use super::{db_trait::{DB, HookMap}, database::{Database, DatabaseReader}, describe::TableDescription};
 use crate::hooks::{hook::Hook, listener_hook::{ListenerHook, NewListenerObj}, transforms::TransformHook};
+use crate::storage::{buffer_pool::PageCopy, directory_lock::DirectoryLock, storage_engine::{StorageKind, StorageOptions}};
 use crate::table::storage_manager_table::StorageManagerTable;
-use crate::types::{CommitedEdit, DataType, Entry, EntryValue};
-use crate::hooks::transforms::Transform;
+use crate::constants::{BACKFILL_PROGRESS_INTERVAL, FAILED_ENTRY, FAILED_SOURCE_TABLE, ROW_ID_COLUMN_NAME, SOURCE_ENTRY_ID};
+use crate::metrics::{self, LISTENERS};
+use crate::types::{create_custom_io_error, CommitedEdit, DataType, Entry, EntryValue};
+use crate::hooks::transforms::{errors_table, Transform};
 
 
 pub struct DatabaseManager{
     db: Database,
     hooks: HashMap<String, Vec<Box<dyn Hook>>>,
     add_listener_senders: HashMap<String, Sender<NewListenerObj>>, // Should this be abstracted?
-    workspace: Workspace
+    workspace: Workspace,
+    actions: HashMap<String, Action>,
+    storage_path: String,
+    // Read once at startup like the data directory
+    default_storage: StorageKind,
+    config: DbConfig,
+    // Taken when the first table with files is opened and released when the manager is dropped
+    lock: Option<DirectoryLock>,
+}
+
+// One line of the report a reload sends back
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableChange {
+    pub table: String,
+    pub change: String,
+    pub rows: usize,
+}
+
+impl TableChange {
+    pub fn to_entry(&self) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("table".to_string(), EntryValue::Str(self.table.clone()));
+        entry.insert("change".to_string(), EntryValue::Str(self.change.clone()));
+        entry.insert("rows".to_string(), EntryValue::Integer(self.rows as isize));
+        entry
+    }
+}
+
+// Called with a table and how many input rows have been replayed into it so far
+pub type Progress<'a> = &'a mut dyn FnMut(&str, usize);
+
+// Tables built to replace running ones. They are filled in a database of their own, which shares the tables they read
+// from with the running one, and swapped in once they are full
+struct Staged {
+    db: Database,
+    hooks: HookMap,
+    built: Vec<String>,
+    removed: Vec<String>,
+    row_counts: Vec<(String, usize)>,
+}
+
+// Replacement tables keep their files here until they are filled
+fn staging_directory(storage_path: &str) -> String {
+    format!("{}/staging", storage_path)
 }
 
 impl DatabaseManager {
     pub fn from_config(config: DbConfig, storage_path: String) -> Result<DatabaseManager, String> {
-        let mut tables: HashMap<String, MultiSourceTable> = HashMap::new();
-        let mut hooks: HashMap<String, Vec<Box<dyn Hook>>> = HashMap::new();
-        let mut add_listener_senders = HashMap::new();
-        let mut actions: HashMap<String, Action> = HashMap::new();
-
-        let mut workspace = Workspace::new("./".to_string());
-        match config.action_config {
-            Some(actions_cfg) => {
-                match actions_cfg {
-                    Importable::Import(_) => {panic!("Action config not processed correctly!")}
-                    Importable::Inline(unwrapped_action_cfg) => {
-                        workspace = Workspace::new(unwrapped_action_cfg.workspace_dir);
-                        for action in unwrapped_action_cfg.actions {
-                            let action_obj = Action::new(action.file, action.function);
-                            actions.insert(action.name, action_obj);
-                        }
-                    }
-                }
+        // A cycle would make inserts recurse forever, so refuse to start with one
+        check_config(&config)?;
+        let (workspace, actions) = parse_actions(config.action_config.clone())?;
+        let mut dbm = DatabaseManager {
+            db: Database::new(HashMap::new()),
+            hooks: HashMap::new(),
+            add_listener_senders: HashMap::new(),
+            workspace,
+            actions,
+            storage_path,
+            default_storage: config.storage.unwrap_or_default(),
+            config: config.clone(),
+            lock: None,
+        };
+        for table in config.tables {
+            dbm.add_table(table)?;
+        }
+        dbm.db.wire_outputs()?;
+        Ok(dbm)
+    }
+
+    // Brings the running tables in line with a new config. New derived tables and ones whose transform changed,
+    // along with everything downstream of those, are filled by replaying the rows of their inputs.
+    // Nothing changes if any of that fails.
+    pub fn reload(&mut self, config: DbConfig, progress: Progress) -> Result<Vec<TableChange>, String> {
+        let graph = check_config(&config)?;
+        // Both decide how the running tables store their rows, which a reload doesn't rewrite
+        if config.storage != self.config.storage {
+            Err("The default storage can't change on a reload, restart the server to change it".to_string())?
+        }
+        if config.durability != self.config.durability {
+            Err("Durability can't change on a reload, restart the server to change it".to_string())?
+        }
+        let diff = diff_tables(&self.config.tables, &config.tables)?;
+        let (workspace, actions) = parse_actions(config.action_config.clone())?;
+        // Tables are built and filled with the new actions, the old ones are put back if that fails
+        let workspace = mem::replace(&mut self.workspace, workspace);
+        let actions = mem::replace(&mut self.actions, actions);
+        match self.apply_diff(&config, diff, &graph, progress) {
+            Ok(changes) => {
+                self.config = config;
+                Ok(changes)
+            }
+            Err(e) => {
+                self.workspace = workspace;
+                self.actions = actions;
+                Err(e)
             }
-            None => {}
         }
+    }
 
-        for table in config.tables {
-            match table {
-                TableConfig::Source(source_config) => {
-                    let name = source_config.name;
-                    let mut columns = vec![];
-                    for (name, data_type) in source_config.columns {
-                        columns.push(Column::new(name, data_type))
+    fn apply_diff(&mut self, config: &DbConfig, diff: ConfigDiff, graph: &TableGraph, progress: Progress) -> Result<Vec<TableChange>, String> {
+        if diff.is_empty() {
+            return Ok(vec![]);
+        }
+        let added: Vec<String> = diff.added.iter().map(|table| table_name(table).to_string()).collect();
+        let changed: Vec<String> = diff.changed_derived.iter().map(|table| table.name.clone()).collect();
+        let recomputed: Vec<String> = graph.downstream(&changed).into_iter().filter(|name| !added.contains(name)).collect();
+        let built: Vec<TableConfig> = config.tables.iter()
+            .filter(|table| {
+                let name = table_name(table).to_string();
+                recomputed.contains(&name) || added.contains(&name)
+            })
+            .cloned()
+            .collect();
+        let filled: Vec<&str> = recomputed.iter().chain(added.iter()).map(|name| name.as_str()).collect();
+        let staged = self.stage_tables(built, &diff.removed, &graph.order(&filled), graph, progress)?;
+
+        let mut changes: Vec<TableChange> = diff.removed.iter()
+            .map(|name| TableChange { table: name.clone(), change: "dropped".to_string(), rows: 0 })
+            .collect();
+        match self.extend_sources(&diff.extended_sources) {
+            Ok(extended) => changes.extend(extended),
+            Err(e) => {
+                self.discard_staged(staged);
+                return Err(e);
+            }
+        }
+        for (name, rows) in self.swap_staged(staged)? {
+            let change = if added.contains(&name) { "created" } else { "recomputed" };
+            changes.push(TableChange { table: name, change: change.to_string(), rows });
+        }
+        Ok(changes)
+    }
+
+    // Columns are added to the open tables rather than reopening them, so rows already stored, and the rows of tables
+    // kept in memory, are kept as they are. Columns added before a failure are skipped by the next reload.
+    fn extend_sources(&mut self, sources: &[SourceTableConfig]) -> Result<Vec<TableChange>, String> {
+        let mut changes = vec![];
+        for source in sources {
+            self.add_columns(source)?;
+            let rows = self.reader().scan(&source.name)?.len();
+            changes.push(TableChange { table: source.name.clone(), change: "extended".to_string(), rows });
+        }
+        Ok(changes)
+    }
+
+    // Recomputes a derived table and everything downstream of it from their inputs. The new tables are filled under
+    // the staging directory and swapped in once they are full, the running ones serve reads until then and are kept as
+    // they were if that fails
+    pub fn rebuild(&mut self, table: &str, progress: Progress) -> Result<Vec<TableChange>, String> {
+        match self.config.tables.iter().find(|config| table_name(config) == table) {
+            Some(TableConfig::Derived(_)) => {}
+            Some(TableConfig::Source(_)) => Err(format!("{} is a source table, only derived tables can be rebuilt", table))?,
+            None => Err(format!("Unable to find table {}", table))?,
+        }
+        let graph = check_config(&self.config)?;
+        let rebuilt = graph.downstream(&[table.to_string()]);
+        let tables: Vec<TableConfig> = rebuilt.iter()
+            .map(|name| self.config.tables.iter().find(|config| table_name(config) == name).unwrap().clone())
+            .collect();
+        let staged = self.stage_tables(tables, &[], &rebuilt, &graph, progress)?;
+        let changes = self.swap_staged(staged)?
+            .into_iter()
+            .map(|(name, rows)| TableChange { table: name, change: "rebuilt".to_string(), rows })
+            .collect();
+        Ok(changes)
+    }
+
+    // Inserts the rows a derived table's transform failed on into it again, rows that fail again are kept for another try
+    pub fn retry_failed(&mut self, table: &str) -> Result<Vec<TableChange>, String> {
+        match self.config.tables.iter().find(|config| table_name(config) == table) {
+            Some(TableConfig::Derived(_)) => {}
+            Some(TableConfig::Source(_)) => Err(format!("{} is a source table, only derived tables have failed rows", table))?,
+            None => Err(format!("Unable to find table {}", table))?,
+        }
+        let errors = errors_table(table);
+        let failures = self.reader().scan(&errors)?;
+        for failure in &failures {
+            let entry = match failure.get(FAILED_ENTRY) {
+                Some(EntryValue::Map(entry)) => entry.clone(),
+                _ => Err(format!("Failed row in {} has no {}", errors, FAILED_ENTRY))?,
+            };
+            let source_table = match failure.get(FAILED_SOURCE_TABLE) {
+                Some(EntryValue::Str(source_table)) => Some(source_table.as_str()),
+                _ => None,
+            };
+            let id = failure.get(ROW_ID_COLUMN_NAME).unwrap().clone();
+            // The failed row is only let go once it is in again, a row that fails again has a new one by then
+            self.db.insert_entry(table, entry, source_table, &mut self.hooks, &self.workspace)?;
+            self.db.delete_all(&errors, ROW_ID_COLUMN_NAME.to_string(), id, &mut self.hooks, &self.workspace)?;
+        }
+        let failing = self.reader().scan(&errors)?.len();
+        Ok(vec![
+            TableChange { table: table.to_string(), change: "retried".to_string(), rows: failures.len().saturating_sub(failing) },
+            TableChange { table: errors, change: "still failing".to_string(), rows: failing },
+        ])
+    }
+
+    // Describes the tables as they are wired at runtime, inputs first
+    pub fn describe_graph(&self) -> Result<Vec<TableDescription>, String> {
+        let graph = check_config(&self.config)?;
+        let reader = self.reader();
+        let mut descriptions = vec![];
+        for summary in graph.tables() {
+            let table = reader.get_table(&summary.name)?;
+            let mut table = table.lock().unwrap();
+            let transform = match self.config.tables.iter().find(|config| table_name(config) == summary.name) {
+                Some(TableConfig::Derived(config)) => Some(
+                    serde_json::to_string(&config.transform_definition).map_err(|e| e.to_string())?
+                ),
+                _ => None,
+            };
+            let table_type = match table.get_table_type() {
+                TableType::Source => "Source",
+                TableType::Derived(_) => "Derived",
+            };
+            descriptions.push(TableDescription {
+                name: summary.name.clone(),
+                table_type: table_type.to_string(),
+                transform,
+                inputs: table.get_input_tables().clone(),
+                outputs: graph.order(&table.get_output_tables().iter().map(|name| name.as_str()).collect::<Vec<_>>()),
+                columns: table.get_columns().into_iter().map(|column| (column.name, column.data_type)).collect(),
+            });
+        }
+        Ok(descriptions)
+    }
+
+    // Builds the new tables in the staging directory and fills the ones named in fill. The running tables stay in place
+    // for readers and writers until swap_staged, so nothing has to be put back if that fails.
+    fn stage_tables(&mut self, tables: Vec<TableConfig>, removed: &[String], fill: &[String], graph: &TableGraph, progress: Progress) -> Result<Staged, String> {
+        let mut staged = Staged { db: Database::new(HashMap::new()), hooks: HashMap::new(), built: vec![], removed: vec![], row_counts: vec![] };
+        let reader = self.reader();
+        for name in removed {
+            if reader.get_table(&errors_table(name)).is_ok() {
+                staged.removed.push(errors_table(name));
+            }
+            staged.removed.push(name.clone());
+        }
+        match self.build_staged(tables, fill, graph, progress, &mut staged) {
+            Ok(row_counts) => {
+                staged.row_counts = row_counts;
+                Ok(staged)
+            }
+            Err(e) => {
+                self.discard_staged(staged);
+                Err(e)
+            }
+        }
+    }
+
+    fn build_staged(&mut self, tables: Vec<TableConfig>, fill: &[String], graph: &TableGraph, progress: Progress, staged: &mut Staged) -> Result<Vec<(String, usize)>, String> {
+        let staging = staging_directory(&self.storage_path);
+        if tables.iter().any(|table| self.storage_kind(table) == StorageKind::File) {
+            self.remove_staging();
+            fs::create_dir_all(&staging).map_err(|e| format!("Unable to create {}: {}", staging, e))?;
+        }
+        for table in tables {
+            for (name, new_table, transform) in self.build_table(table, &staging)? {
+                // Listeners stay with the running table until the swap, so they don't get the replayed rows
+                staged.hooks.insert(name.clone(), vec![Box::new(TransformHook::new(transform, name.clone()))]);
+                staged.db.add_table(name.clone(), MultiSourceTable::InHouse(new_table));
+                staged.built.push(name);
+            }
+        }
+        // Transforms look up the tables they read from by name, so the running inputs are shared with the staging database
+        let reader = self.reader();
+        for name in &staged.built {
+            let inputs = staged.db.reader().get_table(name)?.lock().unwrap().get_input_tables().clone();
+            for input in inputs.into_iter().filter(|input| !staged.built.contains(input)) {
+                let table = reader.get_table(&input)?;
+                staged.db.share_table(input, table);
+            }
+        }
+        staged.db.wire_outputs_of(&staged.built)?;
+        self.fill_tables(staged, fill, graph, progress)
+    }
+
+    // Drops the staged tables, the running ones were never touched
+    fn discard_staged(&self, staged: Staged) {
+        drop(staged);
+        self.remove_staging();
+    }
+
+    // Moves the staged tables' files over the files of the tables they replace, then swaps the tables in and hands them
+    // the replaced tables' listeners. Tables that aren't replaced are deleted and their listeners told so.
+    fn swap_staged(&mut self, staged: Staged) -> Result<Vec<(String, usize)>, String> {
+        let Staged { db: staging_db, hooks: mut staged_hooks, built, removed, row_counts } = staged;
+        let reader = self.reader();
+        let mut new_tables = vec![];
+        for name in &built {
+            let replaced_files = match reader.get_table(name) {
+                Ok(table) => table.lock().unwrap().storage_files(),
+                Err(_) => vec![],
+            };
+            // The running table keeps reading the files it has open until it is swapped out
+            let table = staging_db.reader().get_table(name)?;
+            table.lock().unwrap()
+                .move_storage(&self.storage_path, &replaced_files)
+                .map_err(|e| format!("Unable to move table {} into place: {}", name, e))?;
+            new_tables.push((name.clone(), table));
+        }
+        let mut replaced = self.db.swap_tables(new_tables, &removed);
+        self.db.wire_outputs()?;
+        for name in &built {
+            let mut hooks = staged_hooks.remove(name).unwrap();
+            match (replaced.remove(name), self.hooks.remove(name)) {
+                // The first hook of every table is its TransformHook, see register_table
+                (Some(_), Some(old_hooks)) => hooks.extend(old_hooks.into_iter().skip(1)),
+                _ => hooks.push(self.listener_hook(name)),
+            }
+            self.hooks.insert(name.clone(), hooks);
+        }
+        for name in &removed {
+            for mut hook in self.hooks.remove(name).unwrap_or_default() {
+                hook.close();
+            }
+            self.add_listener_senders.remove(name);
+            if let Some(table) = replaced.remove(name) {
+                table.lock().unwrap().delete_storage().map_err(|e| format!("Unable to remove table {}: {}", name, e))?;
+            }
+        }
+        self.remove_staging();
+        Ok(row_counts)
+    }
+
+    fn remove_staging(&self) {
+        match fs::remove_dir_all(staging_directory(&self.storage_path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => warn!(error = %e, "Unable to remove the staging directory"),
+            _ => {}
+        }
+    }
+
+    // Replays the rows of every input that isn't being filled itself, inputs that are pass their rows on as they get them.
+    // Tables are given in dependency order, returns the final row count of each.
+    fn fill_tables(&self, staged: &mut Staged, names: &[String], graph: &TableGraph, progress: Progress) -> Result<Vec<(String, usize)>, String> {
+        for name in names {
+            let summary = graph.get(name).unwrap();
+            let mut replayed = 0;
+            for input in &summary.inputs {
+                if names.contains(input) {
+                    continue;
+                }
+                for entry in self.reader().scan(input)? {
+                    staged.db.insert_entry(name, entry, Some(input), &mut staged.hooks, &self.workspace)?;
+                    replayed += 1;
+                    if replayed % BACKFILL_PROGRESS_INTERVAL == 0 {
+                        progress(name, replayed);
                     }
-                    columns.push(Column::new("_entryId".to_string(), DataType::ID));
-                    let new_table = match StorageManagerTable::new(
-                        name.clone(),
-                        columns,
-                        TableType::Source,
-                        storage_path.clone(),
-                    ) {
-                        Ok(t) => Ok(t),
-                        Err(e) => Err(format!("{:?}", e)),
-                    }?;
-                    let mut hook_list:Vec<Box<dyn Hook>> = vec![Box::new(TransformHook::new(Transform::None, name.clone()))];
-                    let (sender, listener_hook) = ListenerHook::new(name.clone());
-                    add_listener_senders.insert(name.clone(), sender);
-                    hook_list.push(Box::new(listener_hook));
-                    hooks.insert(name.clone(), hook_list);
-                    tables.insert(name, MultiSourceTable::InHouse(new_table));
                 }
-                TableConfig::Derived(config) => {
-                    let (table, transform) = parse_transform_config(config, storage_path.clone(), &actions)?;
-                    let table_name = table.name.clone();
+            }
+            progress(name, replayed);
+        }
+        let mut row_counts = vec![];
+        for name in names {
+            row_counts.push((name.clone(), staged.db.reader().scan(name)?.len()));
+        }
+        Ok(row_counts)
+    }
+
+    fn add_table(&mut self, table: TableConfig) -> Result<(), String> {
+        let path = self.storage_path.clone();
+        for (name, new_table, transform) in self.build_table(table, &path)? {
+            self.register_table(name, new_table, transform);
+        }
+        Ok(())
+    }
 
-                    let mut hook_list: Vec<Box<dyn Hook>> = vec![Box::new(TransformHook::new(transform, table_name.clone()))];
-                    let (sender, listener_hook) = ListenerHook::new(table_name.clone());
-                    add_listener_senders.insert(table_name.clone(), sender);
-                    hook_list.push(Box::new(listener_hook));
+    fn storage_kind(&self, table: &TableConfig) -> StorageKind {
+        let storage = match table {
+            TableConfig::Source(source_config) => source_config.storage,
+            TableConfig::Derived(derived_config) => derived_config.storage,
+        };
+        storage.unwrap_or(self.default_storage)
+    }
 
-                    tables.insert(table_name.clone(), MultiSourceTable::InHouse(table));
-                    hooks.insert(table_name.clone(), hook_list);
+    // Opens the table with its files in the directory, a derived table comes with the table its failed rows go to
+    fn build_table(&mut self, table: TableConfig, path: &str) -> Result<Vec<(String, StorageManagerTable, Transform)>, String> {
+        let storage = self.storage_kind(&table);
+        if storage == StorageKind::File {
+            self.lock_storage()?;
+        }
+        match table {
+            TableConfig::Source(source_config) => {
+                let name = source_config.name;
+                let options = StorageOptions::new(
+                    source_config.block_size,
+                    source_config.compression,
+                    source_config.compression_dictionary,
+                    storage,
+                )?;
+                let mut columns = vec![];
+                for (name, data_type) in source_config.columns {
+                    columns.push(Column::new(name, data_type))
                 }
+                columns.push(Column::new("_entryId".to_string(), DataType::ID));
+                let new_table = match StorageManagerTable::new(
+                    name.clone(),
+                    columns,
+                    TableType::Source,
+                    path.to_string(),
+                    options,
+                ) {
+                    Ok(t) => Ok(t),
+                    Err(e) => Err(format!("{:?}", e)),
+                }?;
+                Ok(vec![(name, new_table, Transform::None)])
+            }
+            TableConfig::Derived(config) => {
+                let (table, transform) = parse_transform_config(config, path.to_string(), self.default_storage, &self.actions)?;
+                let errors_table = self.build_errors_table(&table.name, storage, path)?;
+                Ok(vec![errors_table, (table.name.clone(), table, transform)])
             }
         }
-        let mut input_refs = vec![];
-        for (name, table) in &mut tables {
-            for input_table_name in table.get_input_tables() {
-                input_refs.push((input_table_name.clone(), name.clone()));
+    }
+
+    fn add_columns(&mut self, source: &SourceTableConfig) -> Result<(), String> {
+        let table = self.reader().get_table(&source.name)?;
+        let mut table = table.lock().unwrap();
+        let existing: Vec<String> = table.get_columns().into_iter().map(|column| column.name).collect();
+        for (name, data_type) in &source.columns {
+            if !existing.contains(name) {
+                table
+                    .add_column(Column::new(name.clone(), data_type.clone()))
+                    .map_err(|e| format!("Unable to add column {} to table {}: {}", name, source.name, e))?;
             }
         }
-        for (source_table, dest_table) in input_refs {
-            let table_to_mod = match tables.get_mut(&source_table) {
-                Some(t) => t,
-                None => Err("Specified input table does not exist".to_string())?,
-            };
-            table_to_mod.get_output_tables().push(dest_table.clone());
+        Ok(())
+    }
+
+    // A second server or command can't open the data directory while this is held
+    fn lock_storage(&mut self) -> Result<(), String> {
+        if self.lock.is_none() {
+            self.lock = Some(DirectoryLock::acquire(&self.storage_path).map_err(|e| e.to_string())?);
         }
+        Ok(())
+    }
+
+    // Rows the transform of a derived table fails on are written here by its TransformHook, kept wherever
+    // the derived table keeps its rows
+    fn build_errors_table(&self, table: &str, storage: StorageKind, path: &str) -> Result<(String, StorageManagerTable, Transform), String> {
+        let name = errors_table(table);
+        let columns = vec![
+            Column::new(ROW_ID_COLUMN_NAME.to_string(), DataType::ID),
+            Column::new(SOURCE_ENTRY_ID.to_string(), DataType::ID),
+            Column::new(FAILED_SOURCE_TABLE.to_string(), DataType::Str),
+            Column::new(FAILED_ENTRY.to_string(), DataType::Map(vec![])),
+            Column::new("transform".to_string(), DataType::Str),
+            Column::new("error".to_string(), DataType::Str),
+            Column::new("failedAt".to_string(), DataType::Integer),
+        ];
+        let options = StorageOptions { kind: storage, ..StorageOptions::default() };
+        let new_table = StorageManagerTable::new(name.clone(), columns, TableType::Source, path.to_string(), options)
+            .map_err(|e| format!("{:?}", e))?;
+        Ok((name, new_table, Transform::None))
+    }
 
-        let db = Database::new(tables);
-        return Ok(DatabaseManager {
-            db,
-            hooks,
-            add_listener_senders,
-            workspace
-        });
+    fn register_table(&mut self, name: String, new_table: StorageManagerTable, transform: Transform) {
+        let hook_list: Vec<Box<dyn Hook>> = vec![Box::new(TransformHook::new(transform, name.clone())), self.listener_hook(&name)];
+        self.hooks.insert(name.clone(), hook_list);
+        self.db.add_table(name, MultiSourceTable::InHouse(new_table));
     }
 
+    fn listener_hook(&mut self, table: &str) -> Box<dyn Hook> {
+        let (sender, listener_hook) = ListenerHook::new(table.to_string());
+        self.add_listener_senders.insert(table.to_string(), sender);
+        Box::new(listener_hook)
+    }
 
     pub fn delete_all(
-        self,
+        &mut self,
         table: &str,
         column: String,
         key: EntryValue,
-    ) -> (DatabaseManager, Result<Vec<CommitedEdit>, String>){
-        let mut hooks = self.hooks;
-        let mut db = self.db;
-        let add_listener_senders = self.add_listener_senders;
-        let workspace = self.workspace;
-        let result = db.delete_all(table, column, key, &mut hooks, &workspace);
-        (DatabaseManager {db, hooks, add_listener_senders, workspace}, result)
+    ) -> Result<Vec<CommitedEdit>, String> {
+        self.db.delete_all(table, column, key, &mut self.hooks, &self.workspace)
     }
 
     pub fn insert_entry(
-        self,
-        table: &String,
+        &mut self,
+        table: &str,
         entry: Entry,
         source_table: Option<&str>,
-    ) -> (DatabaseManager, Result<Vec<CommitedEdit>, String>) {
-        let mut hooks = self.hooks;
-        let mut db = self.db;
-        let add_listener_senders = self.add_listener_senders;
-        let workspace = self.workspace;
-        let result = db.insert_entry(table, entry, source_table, &mut hooks, &workspace);
-        (DatabaseManager {db, hooks, add_listener_senders, workspace}, result)
+    ) -> Result<Vec<CommitedEdit>, String> {
+        self.db.insert_entry(table, entry, source_table, &mut self.hooks, &self.workspace)
     }
 
-    pub fn less_than_search(
-        &mut self,
-        table: &String,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Vec<Entry>, String> {
-        return self.db.less_than_search(table, column, key);
+    // Handle for serving reads from other threads while this manager keeps applying writes
+    pub fn reader(&self) -> DatabaseReader {
+        self.db.reader()
     }
 
-    pub fn get_all(
-        &mut self,
-        table: &String,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Vec<Entry>, String> {
-        return self.db.get_all(table, column, key);
+    // Called once no more requests will arrive, leaves storage consistent and on disk.
+    // Returns how many writes were left half applied and had to be rolled back
+    pub fn shutdown(&mut self) -> io::Result<usize> {
+        let rolled_back = self.db.rollback_open_transactions(&mut self.hooks, &self.workspace);
+        if rolled_back > 0 {
+            warn!(writes = rolled_back, "Rolled back writes left half applied");
+        }
+        self.sync()?;
+        Ok(rolled_back)
     }
 
-    pub fn greater_than_search(
-        &mut self,
-        table: &str,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Vec<Entry>, String> {
-        return self.db.greater_than_search(table, column, key);
+    // Writes every table's changed pages back and waits for them to reach the disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.db.flush().map_err(|e| create_custom_io_error(&e))
     }
 
-    pub fn find_one(
-        &mut self,
-        table: &str,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Option<Entry>, String> {
-        return self.db.find_one(table, column, key);
+    // Every file as it is between this write and the next, see Snapshot
+    pub fn start_copy(&mut self, destination: &str) -> Result<Vec<(String, PageCopy)>, String> {
+        self.db.start_copy(destination)
+    }
+
+    // Returns how many rows the new indexes hold
+    pub fn rebuild_indexes(&mut self, table: &str) -> Result<usize, String> {
+        self.db.rebuild_indexes(table)
+    }
+
+    // Returns the bytes the table's files took before and after
+    pub fn compact(&mut self, table: &str) -> Result<(u64, u64), String> {
+        self.db.compact(table)
     }
 
     #[allow(dead_code)]
@@ -180,10 +555,13 @@ impl DatabaseManager {
     pub fn add_listener(&mut self, new_listener_obj:NewListenerObj, table: &str)-> Result<(), String>{
         match self.add_listener_senders.get_mut(table) {
             Some(sender) => match sender.blocking_send(new_listener_obj) {
-                Ok(_) => Ok(()),
+                Ok(_) => {
+                    metrics::global().add_to_gauge(LISTENERS, &[("table", table)], 1);
+                    Ok(())
+                },
                 Err(e) => Err(format!("Error adding listener: {:?}", e.to_string()))
             },
             None => Err(format!("Unable to find listener channel for table {:?}", table))
         }
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,54 @@
-use crate::{config::{config_reader::DbConfig}, hooks::listener_hook::NewListenerObj};
+use crate::{auth::{AccessControl, Principal}, config::{config_reader::{DbConfig, Permission, read_config_file}}, constants::{DEFAULT_BUFFER_POOL_BYTES, DEFAULT_READER_THREADS}, hooks::listener_hook::NewListenerObj, types::Entry};
 use crate::networking::types::{
-    DBRequest, DBResponse, Query, RequestResponse, ToClientMessage,
+    DBRequest, DBResponse, ProtocolError, Query, QueryRequest, RequestResponse, ToClientMessage,
 };
 
-use std::{collections::HashMap};
+use std::{collections::HashMap, sync::{Arc, Mutex, mpsc, atomic::{AtomicUsize, Ordering}}, thread::{self, JoinHandle}, time::Instant};
 use futures::FutureExt;
+use tracing::{debug, info, info_span, warn, Span};
 use tokio::sync::mpsc::{Receiver, Sender};
 use uuid::Uuid;
 use std::fs;
 use std::io;
 
-use super::database_manager::DatabaseManager;
+use crate::metrics::{self, REQUESTS, REQUEST_DURATION, REQUEST_FAILURES};
+use crate::storage::buffer_pool;
 
+use super::database::DatabaseReader;
+use super::database_manager::{DatabaseManager, TableChange};
+use super::describe::TableDescription;
+use super::durability::Commits;
+use super::lineage::{LineageRow, lineage};
+use super::snapshot::{Snapshot, SnapshotFile};
+
+type ReadJob = (QueryRequest, Sender<ToClientMessage>);
+type WriteJob = (DBRequest, Uuid, Sender<ToClientMessage>, InFlight);
+
+// One of a connection's requests on the write thread, counted until the write thread is done with it
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(count: &Arc<AtomicUsize>) -> InFlight {
+        count.fetch_add(1, Ordering::SeqCst);
+        InFlight(count.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Writes and new listeners are applied on this thread in the order they arrive.
+// Reads are handed to a pool of reader threads so they don't wait behind transform cascades, unless the
+// connection still has requests on this thread, then they're served here after them so they see those writes.
 pub fn start_db_thread(
-    mut request_reciever: Receiver<(DBRequest, Uuid)>,
-    mut response_channel_reciever: Receiver<(Sender<ToClientMessage>, Uuid)>,
+    request_reciever: Receiver<(DBRequest, Uuid)>,
+    response_channel_reciever: Receiver<(Sender<ToClientMessage>, Uuid, Principal)>,
     config: DbConfig,
+    config_file: String,
+    access_control: Arc<AccessControl>,
 ) -> std::io::Result<()> {
     let destination = config.storage_destination.clone();
     match fs::create_dir(destination.clone()) {
@@ -27,90 +60,76 @@ pub fn start_db_thread(
             }
         }
     };
+    let reader_threads = config.reader_threads.unwrap_or(DEFAULT_READER_THREADS);
+    let mut commits = Commits::new(config.durability.clone());
+    // Threads finishing snapshots that were started between writes
+    let mut snapshots: Vec<JoinHandle<()>> = vec![];
+    buffer_pool::global().set_budget(config.buffer_pool_bytes.unwrap_or(DEFAULT_BUFFER_POOL_BYTES));
     let mut dbm = DatabaseManager::from_config(config, destination).unwrap();
+    metrics::global().watch_tables(dbm.reader());
 
-    let mut response_channels: HashMap<Uuid, Sender<ToClientMessage>> = HashMap::new();
+    let (read_sender, read_reciever) = mpsc::channel();
+    let readers = start_reader_pool(dbm.reader(), read_reciever, reader_threads);
+    let (write_sender, write_reciever) = mpsc::channel();
+    let dispatcher = thread::spawn(move || {
+        dispatch_requests(request_reciever, response_channel_reciever, access_control, read_sender, write_sender)
+    });
 
-    loop {
-        let (request, client_id) = match request_reciever.blocking_recv() {
-            Some(v) => v,
-            _ => continue,
-        };
-        loop {
-            let (new_channel, new_client_id) = match response_channel_reciever.recv().now_or_never() {
-                Some(v) => match v {
-                    Some(value) => value,
-                    //None => panic!("Poll Closed End Server")
-                    _ => break,
-                },
-                _ => break,
-            };
-            response_channels.insert(new_client_id, new_channel);
-        }
-        let response_channel = response_channels.get(&client_id).unwrap();
+    while let Some((request, client_id, response_channel, _in_flight)) = commits.next(&mut dbm, &write_reciever) {
         match request {
             DBRequest::Query(query_request) => {
-                let query = query_request.query;
                 let id = query_request.request_id;
-                match query {
-                    Query::FindOne(request) => {
-                        let found_one = dbm.find_one(&request.table, request.column, request.key);
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::OneResult(found_one),
-                        });
-                        let _ = response_channel.blocking_send(response);
+                let span = request_span(&query_request, Some(client_id));
+                let _entered = span.enter();
+                let query = query_name(&query_request.query);
+                let started = Instant::now();
+                let write = matches!(query_request.query, Query::InsertData(_) | Query::DeleteData(_));
+                let response = match query_request.query {
+                    Query::InsertData(request) => {
+                        debug!(entry = ?request.entry, "Inserting");
+                        let results = dbm.insert_entry(&request.table, request.entry, None);
+                        DBResponse::ManyResults(results.map(|edits|{
+                            edits.iter().map(|edit|{edit.entry.clone()}).collect()
+                        }))
                     }
-                    Query::LessThan(request) => {
-                        let found_many =
-                            dbm.less_than_search(&request.table, request.column, request.key);
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::ManyResults(found_many),
-                        });
-                        let _ = response_channel.blocking_send(response);
+                    Query::DeleteData(request) => {
+                        let results = dbm.delete_all(&request.table, request.column, request.key);
+                        DBResponse::ManyResults(results.map(|edits|{
+                            edits.iter().map(|edit|{edit.entry.clone()}).collect()
+                        }))
                     }
-                    Query::GreaterThan(request) => {
-                        let found_many =
-                            dbm.greater_than_search(&request.table, request.column, request.key);
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::ManyResults(found_many),
-                        });
-                        let _ = response_channel.blocking_send(response);
+                    Query::ReloadConfig => DBResponse::ManyResults(reload_config(&mut dbm, &config_file)),
+                    Query::DescribeGraph => DBResponse::ManyResults(dbm.describe_graph().map(|tables| {
+                        tables.iter().map(TableDescription::to_entry).collect()
+                    })),
+                    Query::RebuildTable(table) => {
+                        let changes = dbm.rebuild(&table, &mut log_progress).and_then(|changes| synced(&mut dbm, changes));
+                        DBResponse::ManyResults(changes.map(|changes| report(&changes)))
                     }
-                    Query::InsertData(request) => {
-                        println!("Insert Request {:?}", request);
-                        let (temp_dbm, results) = dbm.insert_entry(&request.table, request.entry, None,);
-                        dbm = temp_dbm;
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::ManyResults(results.map(|edits|{
-                                edits.iter().map(|edit|{edit.entry.clone()}).collect()
-                            })),
-                        });
-                        let _ = response_channel.blocking_send(response);
+                    Query::RetryFailed(table) => {
+                        let changes = dbm.retry_failed(&table).and_then(|changes| synced(&mut dbm, changes));
+                        DBResponse::ManyResults(changes.map(|changes| report(&changes)))
                     }
-                    Query::DeleteData(request) => {
-                        let (temp_dbm, results) = dbm.delete_all(&request.table, request.column, request.key);
-                        dbm = temp_dbm;
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::ManyResults(results.map(|edits|{
-                                edits.iter().map(|edit|{edit.entry.clone()}).collect()
-                            })),
-                        });
-                        let _ = response_channel.blocking_send(response);
+                    Query::Snapshot(destination) => match Snapshot::start(&mut dbm, &destination) {
+                        Ok(snapshot) => {
+                            commits.sync(&mut dbm);
+                            snapshots.retain(|snapshot| !snapshot.is_finished());
+                            snapshots.push(finish_snapshot(snapshot, destination, id, span.clone(), started, response_channel));
+                            continue;
+                        }
+                        Err(e) => DBResponse::ManyResults(Err(e)),
                     },
-                    Query::GetAll(request) => {
-                        let results = dbm.get_all(&request.table, request.column, request.key);
-                        let response = ToClientMessage::RequestResponse(RequestResponse {
-                            request_id: id,
-                            response: DBResponse::ManyResults(results),
-                        });
-                        let _ = response_channel.blocking_send(response);
-                    }
+                    query => execute_read(&dbm.reader(), query),
+                };
+                finish_request(query, started, &response);
+                let response = RequestResponse { request_id: id, response };
+                if write {
+                    commits.answer(&mut dbm, response_channel, response);
+                    continue;
                 }
+                // Writes still waiting for a sync are answered first so each client hears back in order
+                commits.sync(&mut dbm);
+                let _ = response_channel.blocking_send(ToClientMessage::RequestResponse(response));
             }
             DBRequest::StartListen(listen_request) => {
                 let new_listener = NewListenerObj {
@@ -119,11 +138,259 @@ pub fn start_db_thread(
                     event: listen_request.event
                 };
 
-                match dbm.add_listener(new_listener, &listen_request.table_name) {
-                    Err(e) => panic!(e),
-                    _ => {}
-                };
+                // Listening on a table that doesn't exist only fails that listen, the connection stays open
+                if let Err(e) = dbm.add_listener(new_listener, &listen_request.table_name) {
+                    warn!(connection_id = %client_id, table = %listen_request.table_name, error = %e, "Unable to add listener");
+                    let _ = response_channel.blocking_send(ToClientMessage::ProtocolError(ProtocolError {
+                        request_id: None,
+                        message: e,
+                    }));
+                }
+            }
+        };
+    }
+
+    // The dispatcher only stops once every connection is gone, so everything left in the queues has been served by now
+    for reader in readers {
+        let _ = reader.join();
+    }
+    let response_channels = dispatcher.join().unwrap_or_default();
+    for snapshot in snapshots {
+        let _ = snapshot.join();
+    }
+    dbm.shutdown()?;
+    for response_channel in response_channels.values() {
+        let _ = response_channel.blocking_send(ToClientMessage::Close("Server shutting down".to_string()));
+    }
+    Ok(())
+}
+
+// Checks every request against the client's rights and routes it to the readers or the write thread
+fn dispatch_requests(
+    mut request_reciever: Receiver<(DBRequest, Uuid)>,
+    mut response_channel_reciever: Receiver<(Sender<ToClientMessage>, Uuid, Principal)>,
+    access_control: Arc<AccessControl>,
+    read_sender: mpsc::Sender<ReadJob>,
+    write_sender: mpsc::Sender<WriteJob>,
+) -> HashMap<Uuid, Sender<ToClientMessage>> {
+    let mut response_channels: HashMap<Uuid, Sender<ToClientMessage>> = HashMap::new();
+    let mut principals: HashMap<Uuid, Principal> = HashMap::new();
+    // Requests each connection has on the write thread
+    let mut in_flight: HashMap<Uuid, Arc<AtomicUsize>> = HashMap::new();
+
+    while let Some((request, client_id)) = request_reciever.blocking_recv() {
+        while let Some(Some((new_channel, new_client_id, principal))) = response_channel_reciever.recv().now_or_never() {
+            response_channels.insert(new_client_id, new_channel);
+            principals.insert(new_client_id, principal);
+        }
+        let response_channel = response_channels.get(&client_id).unwrap();
+        let principal = principals.get(&client_id).unwrap();
+        let writes = in_flight.entry(client_id).or_default();
+        match request {
+            DBRequest::Query(query_request) => {
+                let (table, permission) = required_permission(&query_request.query);
+                if let Err(e) = access_control.check(principal, table, permission) {
+                    warn!(connection_id = %client_id, request_id = %query_request.request_id, error = %e, "Permission denied");
+                    let response = match query_request.query {
+                        Query::FindOne(_) => DBResponse::OneResult(Err(e)),
+                        _ => DBResponse::ManyResults(Err(e)),
+                    };
+                    let _ = response_channel.blocking_send(ToClientMessage::RequestResponse(RequestResponse {
+                        request_id: query_request.request_id,
+                        response,
+                    }));
+                    continue;
+                }
+                // A read behind the connection's own writes would otherwise overtake them
+                if permission == Permission::Read && writes.load(Ordering::SeqCst) == 0 {
+                    let _ = read_sender.send((query_request, response_channel.clone()));
+                } else {
+                    let job = (DBRequest::Query(query_request), client_id, response_channel.clone(), InFlight::new(writes));
+                    let _ = write_sender.send(job);
+                }
+            }
+            DBRequest::StartListen(listen_request) => {
+                if let Err(e) = access_control.check(principal, &listen_request.table_name, Permission::Listen) {
+                    warn!(connection_id = %client_id, error = %e, "Permission denied");
+                    let _ = response_channel.blocking_send(ToClientMessage::ProtocolError(ProtocolError {
+                        request_id: None,
+                        message: e,
+                    }));
+                    continue;
+                }
+                info!(connection_id = %client_id, table = %listen_request.table_name, "Listener added");
+                let _ = write_sender.send((DBRequest::StartListen(listen_request), client_id, response_channel.clone(), InFlight::new(writes)));
             }
         };
     }
+    // Connections that never sent a request still get told the server is closing
+    while let Some((new_channel, new_client_id, _)) = response_channel_reciever.blocking_recv() {
+        response_channels.insert(new_client_id, new_channel);
+    }
+    response_channels
+}
+
+// Readers stop once the dispatcher drops its sender and the queue is empty
+fn start_reader_pool(reader: DatabaseReader, read_reciever: mpsc::Receiver<ReadJob>, reader_threads: usize) -> Vec<JoinHandle<()>> {
+    let read_reciever = Arc::new(Mutex::new(read_reciever));
+    (0..reader_threads.max(1)).map(|_| {
+        let reader = reader.clone();
+        let read_reciever = read_reciever.clone();
+        thread::spawn(move || loop {
+            let job = read_reciever.lock().unwrap().recv();
+            let (query_request, response_channel) = match job {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let span = request_span(&query_request, None);
+            let _entered = span.enter();
+            let query = query_name(&query_request.query);
+            let started = Instant::now();
+            let response = execute_read(&reader, query_request.query);
+            finish_request(query, started, &response);
+            let _ = response_channel.blocking_send(ToClientMessage::RequestResponse(RequestResponse {
+                request_id: query_request.request_id,
+                response,
+            }));
+        })
+    }).collect()
+}
+
+fn execute_read(reader: &DatabaseReader, query: Query) -> DBResponse {
+    match query {
+        Query::FindOne(request) => {
+            DBResponse::OneResult(reader.find_one(&request.table, request.column, request.key))
+        }
+        Query::LessThan(request) => {
+            DBResponse::ManyResults(reader.less_than_search(&request.table, request.column, request.key))
+        }
+        Query::GreaterThan(request) => {
+            DBResponse::ManyResults(reader.greater_than_search(&request.table, request.column, request.key))
+        }
+        Query::GetAll(request) => {
+            DBResponse::ManyResults(reader.get_all(&request.table, request.column, request.key))
+        }
+        Query::Lineage(request) => {
+            DBResponse::ManyResults(lineage(reader, &request.table, request.entry_id).map(|rows| {
+                rows.iter().map(LineageRow::to_entry).collect()
+            }))
+        }
+        Query::InsertData(_)
+        | Query::DeleteData(_)
+        | Query::ReloadConfig
+        | Query::RebuildTable(_)
+        | Query::RetryFailed(_)
+        | Query::DescribeGraph
+        | Query::Snapshot(_) => {
+            DBResponse::ManyResults(Err("Writes can't be served by a reader".to_string()))
+        }
+    }
+}
+
+// Closing the span logs how long the request took
+fn request_span(query_request: &QueryRequest, client_id: Option<Uuid>) -> Span {
+    let (table, _) = required_permission(&query_request.query);
+    info_span!(
+        "request",
+        request_id = %query_request.request_id,
+        connection_id = client_id.map(tracing::field::display),
+        query = query_name(&query_request.query),
+        table,
+    )
+}
+
+fn finish_request(query: &'static str, started: Instant, response: &DBResponse) {
+    let labels = [("query", query)];
+    metrics::global().observe(REQUEST_DURATION, &labels, started.elapsed());
+    metrics::global().increment(REQUESTS, &labels, 1);
+    let error = match response {
+        DBResponse::ManyResults(Err(e)) => e,
+        DBResponse::OneResult(Err(e)) => e,
+        DBResponse::NoResult(Err(e)) => e,
+        _ => return,
+    };
+    metrics::global().increment(REQUEST_FAILURES, &labels, 1);
+    warn!(error = %error, "Request failed");
+}
+
+fn query_name(query: &Query) -> &'static str {
+    match query {
+        Query::FindOne(_) => "FindOne",
+        Query::LessThan(_) => "LessThan",
+        Query::GreaterThan(_) => "GreaterThan",
+        Query::GetAll(_) => "GetAll",
+        Query::InsertData(_) => "InsertData",
+        Query::DeleteData(_) => "DeleteData",
+        Query::ReloadConfig => "ReloadConfig",
+        Query::DescribeGraph => "DescribeGraph",
+        Query::RebuildTable(_) => "RebuildTable",
+        Query::RetryFailed(_) => "RetryFailed",
+        Query::Lineage(_) => "Lineage",
+        Query::Snapshot(_) => "Snapshot",
+    }
+}
+
+fn required_permission(query: &Query) -> (&str, Permission) {
+    match query {
+        Query::FindOne(request) => (&request.table, Permission::Read),
+        Query::LessThan(request) => (&request.table, Permission::Read),
+        Query::GreaterThan(request) => (&request.table, Permission::Read),
+        Query::GetAll(request) => (&request.table, Permission::Read),
+        Query::InsertData(request) => (&request.table, Permission::Insert),
+        Query::DeleteData(request) => (&request.table, Permission::Delete),
+        Query::ReloadConfig => ("*", Permission::Admin),
+        Query::DescribeGraph => ("*", Permission::Admin),
+        Query::RebuildTable(table) => (table, Permission::Admin),
+        Query::RetryFailed(table) => (table, Permission::Admin),
+        // Lineage follows rows into any table
+        Query::Lineage(_) => ("*", Permission::Read),
+        Query::Snapshot(_) => ("*", Permission::Admin),
+    }
+}
+
+// The pages are copied on their own thread so writes go on, the client hears back once the snapshot is complete
+fn finish_snapshot(
+    snapshot: Snapshot,
+    destination: String,
+    request_id: Uuid,
+    span: Span,
+    started: Instant,
+    response_channel: Sender<ToClientMessage>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let _entered = span.enter();
+        let files = snapshot.finish();
+        if let Ok(files) = &files {
+            let bytes: u64 = files.iter().map(|file| file.bytes).sum();
+            info!(destination = %destination, files = files.len(), bytes, "Snapshot taken");
+        }
+        let response = DBResponse::ManyResults(files.map(|files| files.iter().map(SnapshotFile::to_entry).collect()));
+        finish_request("Snapshot", started, &response);
+        let _ = response_channel.blocking_send(ToClientMessage::RequestResponse(RequestResponse { request_id, response }));
+    })
+}
+
+fn reload_config(dbm: &mut DatabaseManager, config_file: &str) -> Result<Vec<Entry>, String> {
+    let config = read_config_file(config_file.to_string()).map_err(|e| format!("Unable to read {}: {}", config_file, e))?;
+    let changes = dbm.reload(config, &mut log_progress).map_err(|e| format!("Config not reloaded: {}", e))?;
+    let changes = synced(dbm, changes)?;
+    info!(config_file, changes = changes.len(), "Reloaded config");
+    Ok(report(&changes))
+}
+
+// Admin requests can rewrite whole tables, so they're on disk before the client hears back
+fn synced(dbm: &mut DatabaseManager, changes: Vec<TableChange>) -> Result<Vec<TableChange>, String> {
+    dbm.sync().map_err(|e| format!("Unable to sync tables: {}", e))?;
+    Ok(changes)
+}
+
+fn report(changes: &[TableChange]) -> Vec<Entry> {
+    for change in changes {
+        info!(table = %change.table, change = %change.change, rows = change.rows, "Table changed");
+    }
+    changes.iter().map(TableChange::to_entry).collect()
+}
+
+fn log_progress(table: &str, replayed: usize) {
+    info!(table, replayed, "Backfilling");
 }
@@ -22,20 +22,6 @@ pub trait DB {
         workspace: &Workspace
     ) -> Result<Vec<CommitedEdit>, String>;
 
-    fn find_one(
-        &mut self,
-        table: &str,
-        column: String,
-        key: EntryValue
-    ) -> Result<Option<Entry>, String>;
-
-    fn less_than_search(
-        &mut self,
-        table: &String,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Vec<Entry>, String>;
-
     fn get_all(
         &mut self,
         table: &String,
@@ -43,13 +29,6 @@ pub trait DB {
         key: EntryValue,
     ) -> Result<Vec<Entry>, String>;
 
-    fn greater_than_search(
-        &mut self,
-        table: &str,
-        column: String,
-        key: EntryValue,
-    ) -> Result<Vec<Entry>, String>;
-
     fn get_output_tables(&mut self, table_name: &str) -> Vec<String>;
 }
 
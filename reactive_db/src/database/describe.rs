@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::types::{DataType, Entry, EntryValue};
+
+// How a running table is wired up, as answered to `DescribeGraph`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDescription {
+    pub name: String,
+    pub table_type: String,
+    // The transform definition from the config as JSON, empty for source tables
+    pub transform: Option<String>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub columns: BTreeMap<String, DataType>,
+}
+
+impl TableDescription {
+    pub fn to_entry(&self) -> Entry {
+        let names = |names: &Vec<String>| EntryValue::Array(names.iter().map(|name| EntryValue::Str(name.clone())).collect());
+        let mut entry = Entry::new();
+        entry.insert("table".to_string(), EntryValue::Str(self.name.clone()));
+        entry.insert("type".to_string(), EntryValue::Str(self.table_type.clone()));
+        if let Some(transform) = &self.transform {
+            entry.insert("transform".to_string(), EntryValue::Str(transform.clone()));
+        }
+        entry.insert("inputs".to_string(), names(&self.inputs));
+        entry.insert("outputs".to_string(), names(&self.outputs));
+        let columns = self.columns.iter()
+            .map(|(name, data_type)| (name.clone(), EntryValue::Str(format!("{:?}", data_type))))
+            .collect();
+        entry.insert("columns".to_string(), EntryValue::Map(columns));
+        entry
+    }
+}
+
+// Graphviz source with an edge from every table to each table it feeds
+pub fn to_dot(tables: &[TableDescription]) -> String {
+    let mut dot = String::from("digraph reactive_db {\n    rankdir=LR;\n");
+    for table in tables {
+        let shape = if table.table_type == "Source" { "box" } else { "ellipse" };
+        dot.push_str(&format!("    \"{}\" [shape={}];\n", escape(&table.name), shape));
+    }
+    for table in tables {
+        for output in &table.outputs {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", escape(&table.name), escape(output)));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn format_descriptions(tables: &[TableDescription]) -> String {
+    let mut output = String::new();
+    for table in tables {
+        output.push_str(&format!("{} ({})\n", table.name, table.table_type));
+        if let Some(transform) = &table.transform {
+            output.push_str(&format!("  transform: {}\n", transform));
+        }
+        if !table.inputs.is_empty() {
+            output.push_str(&format!("  reads from: {}\n", table.inputs.join(", ")));
+        }
+        if !table.outputs.is_empty() {
+            output.push_str(&format!("  feeds: {}\n", table.outputs.join(", ")));
+        }
+        for (name, data_type) in &table.columns {
+            output.push_str(&format!("  {}: {:?}\n", name, data_type));
+        }
+    }
+    output
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,101 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, warn};
+
+use crate::config::config_reader::{DurabilityConfig, DurabilityMode};
+use crate::constants::DEFAULT_SYNC_INTERVAL_MS;
+use crate::networking::types::{DBResponse, RequestResponse, ToClientMessage};
+
+use super::database_manager::DatabaseManager;
+
+// Holds back the answers to writes until they're as durable as the config asks for.
+// There is no write log, a sync covers the table and index files and nothing else
+pub struct Commits {
+    mode: DurabilityMode,
+    interval: Duration,
+    // Answers waiting for the next periodic sync
+    waiting: Vec<(Sender<ToClientMessage>, RequestResponse)>,
+    sync_at: Option<Instant>,
+    // Syncs made so far to answer writes
+    pub syncs: usize,
+}
+
+impl Commits {
+    pub fn new(config: Option<DurabilityConfig>) -> Commits {
+        let (mode, interval_ms) = match config {
+            Some(config) => (config.mode, config.interval_ms.unwrap_or(DEFAULT_SYNC_INTERVAL_MS)),
+            None => (DurabilityMode::None, DEFAULT_SYNC_INTERVAL_MS),
+        };
+        Commits {
+            mode,
+            interval: Duration::from_millis(interval_ms),
+            waiting: vec![],
+            sync_at: None,
+            syncs: 0,
+        }
+    }
+
+    pub fn answer(&mut self, dbm: &mut DatabaseManager, channel: Sender<ToClientMessage>, mut response: RequestResponse) {
+        match self.mode {
+            DurabilityMode::None => {}
+            DurabilityMode::PerCommit => {
+                self.syncs += 1;
+                if let Err(e) = dbm.sync() {
+                    response.response = not_synced(&e.to_string());
+                }
+            }
+            DurabilityMode::Periodic => {
+                self.waiting.push((channel, response));
+                let interval = self.interval;
+                self.sync_at.get_or_insert_with(|| Instant::now() + interval);
+                return;
+            }
+        }
+        let _ = channel.blocking_send(ToClientMessage::RequestResponse(response));
+    }
+
+    // Waits for the next write, syncing and answering the waiting ones whenever their interval is up
+    pub fn next<T>(&mut self, dbm: &mut DatabaseManager, reciever: &Receiver<T>) -> Option<T> {
+        loop {
+            let sync_at = match self.sync_at {
+                Some(sync_at) => sync_at,
+                None => return reciever.recv().ok(),
+            };
+            match reciever.recv_timeout(sync_at.saturating_duration_since(Instant::now())) {
+                Ok(job) => return Some(job),
+                Err(RecvTimeoutError::Timeout) => self.sync(dbm),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.sync(dbm);
+                    return None;
+                }
+            }
+        }
+    }
+
+    // Syncs the waiting writes and sends their answers
+    pub fn sync(&mut self, dbm: &mut DatabaseManager) {
+        self.sync_at = None;
+        if self.waiting.is_empty() {
+            return;
+        }
+        let synced = dbm.sync();
+        self.syncs += 1;
+        match &synced {
+            Ok(()) => debug!(writes = self.waiting.len(), syncs = self.syncs, "Synced writes"),
+            Err(e) => warn!(error = %e, writes = self.waiting.len(), "Failed to sync writes"),
+        }
+        for (channel, mut response) in self.waiting.drain(..) {
+            if let Err(e) = &synced {
+                response.response = not_synced(&e.to_string());
+            }
+            let _ = channel.blocking_send(ToClientMessage::RequestResponse(response));
+        }
+    }
+}
+
+// The write is applied either way, the client only learns it may not survive a crash
+fn not_synced(error: &str) -> DBResponse {
+    DBResponse::ManyResults(Err(format!("Write applied but not synced to disk: {}", error)))
+}
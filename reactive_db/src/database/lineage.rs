@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::constants::{ROW_ID_COLUMN_NAME, SOURCE_ENTRY_ID, SOURCE_ENTRY_IDS};
+use crate::table::table_trait::Table;
+use crate::types::{Entry, EntryValue};
+
+use super::database::DatabaseReader;
+
+// Where a row sits relative to the one lineage was asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Origin,
+    Upstream,
+    Downstream,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineageRow {
+    pub table: String,
+    pub direction: Direction,
+    pub entry: Entry,
+}
+
+impl LineageRow {
+    pub fn to_entry(&self) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("table".to_string(), EntryValue::Str(self.table.clone()));
+        entry.insert("direction".to_string(), EntryValue::Str(format!("{:?}", self.direction)));
+        entry.insert("entry".to_string(), EntryValue::Map(self.entry.clone()));
+        entry
+    }
+}
+
+// The row itself, every row it was derived from back to the source tables,
+// then every row derived from it down to the last output table
+pub fn lineage(reader: &DatabaseReader, table: &str, entry_id: EntryValue) -> Result<Vec<LineageRow>, String> {
+    let origin = match reader.find_one(table, ROW_ID_COLUMN_NAME.to_string(), entry_id.clone())? {
+        Some(entry) => entry,
+        None => return Err(format!("No entry {:?} in table {}", entry_id, table)),
+    };
+    let mut rows = vec![LineageRow { table: table.to_string(), direction: Direction::Origin, entry: origin.clone() }];
+
+    let mut seen = HashSet::new();
+    seen.insert((table.to_string(), entry_id.clone()));
+    let mut queue = VecDeque::new();
+    queue.push_back((table.to_string(), origin.clone()));
+    while let Some((table, entry)) = queue.pop_front() {
+        let (inputs, _) = links(reader, &table)?;
+        for source_id in source_ids(&entry) {
+            for input in &inputs {
+                if !seen.insert((input.clone(), source_id.clone())) {
+                    continue;
+                }
+                if let Some(source) = reader.find_one(input, ROW_ID_COLUMN_NAME.to_string(), source_id.clone())? {
+                    rows.push(LineageRow { table: input.clone(), direction: Direction::Upstream, entry: source.clone() });
+                    queue.push_back((input.clone(), source));
+                }
+            }
+        }
+    }
+
+    let mut scanned = ScannedSources::new();
+    queue.push_back((table.to_string(), origin));
+    while let Some((table, entry)) = queue.pop_front() {
+        let id = match entry.get(ROW_ID_COLUMN_NAME) {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let (_, outputs) = links(reader, &table)?;
+        for output in outputs {
+            for derived in derived_from(reader, &output, &id, &mut scanned)? {
+                let derived_id = match derived.get(ROW_ID_COLUMN_NAME) {
+                    Some(derived_id) => derived_id.clone(),
+                    None => continue,
+                };
+                if !seen.insert((output.clone(), derived_id)) {
+                    continue;
+                }
+                rows.push(LineageRow { table: output.clone(), direction: Direction::Downstream, entry: derived.clone() });
+                queue.push_back((output.clone(), derived));
+            }
+        }
+    }
+    Ok(rows)
+}
+
+// Ids of the rows this one was computed from, a union or aggregation row lists all of them
+fn source_ids(entry: &Entry) -> Vec<EntryValue> {
+    let mut ids = vec![];
+    if let Some(id) = entry.get(SOURCE_ENTRY_ID) {
+        ids.push(id.clone());
+    }
+    if let Some(EntryValue::Array(values)) = entry.get(SOURCE_ENTRY_IDS) {
+        for id in values {
+            if !ids.contains(id) {
+                ids.push(id.clone());
+            }
+        }
+    }
+    ids
+}
+
+fn links(reader: &DatabaseReader, table: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let table_obj = reader.get_table(table)?;
+    let mut table_obj = table_obj.lock().unwrap();
+    Ok((table_obj.get_input_tables().clone(), table_obj.get_output_tables().clone()))
+}
+
+// Rows of a table by every source id they list, for rows that can't be found through the index
+type ScannedSources = HashMap<String, HashMap<EntryValue, Vec<Entry>>>;
+
+// Single source rows are found through the _sourceEntryId index when the table has one. Rows listing several sources,
+// and tables without the index, need a scan, which is done once per table and kept for the rows after it
+fn derived_from(reader: &DatabaseReader, table: &str, id: &EntryValue, scanned: &mut ScannedSources) -> Result<Vec<Entry>, String> {
+    let table_obj = reader.get_table(table)?;
+    let mut table_obj = table_obj.lock().unwrap();
+    let columns = table_obj.get_columns();
+    let indexed = columns.iter().any(|column| column.name == SOURCE_ENTRY_ID && column.indexed);
+    let mut rows = vec![];
+    if indexed {
+        rows = table_obj.get_all(SOURCE_ENTRY_ID.to_string(), id.clone())
+            .map_err(|e| format!("Error when searching table {}: {}", table, e))?;
+    }
+    if indexed && !columns.iter().any(|column| column.name == SOURCE_ENTRY_IDS) {
+        return Ok(rows);
+    }
+    if !scanned.contains_key(table) {
+        let mut by_source: HashMap<EntryValue, Vec<Entry>> = HashMap::new();
+        for entry in table_obj.scan().map_err(|e| format!("Error when scanning table {}: {}", table, e))? {
+            for source_id in source_ids(&entry) {
+                by_source.entry(source_id).or_default().push(entry.clone());
+            }
+        }
+        scanned.insert(table.to_string(), by_source);
+    }
+    for entry in scanned[table].get(id).into_iter().flatten() {
+        if !rows.contains(entry) {
+            rows.push(entry.clone());
+        }
+    }
+    Ok(rows)
+}
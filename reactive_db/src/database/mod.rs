@@ -1,4 +1,7 @@
 pub mod database_manager;
 pub mod database;
 pub mod db_thread;
-pub mod db_trait;
\ No newline at end of file
+pub mod db_trait;pub mod describe;
+pub mod durability;
+pub mod lineage;
+pub mod snapshot;
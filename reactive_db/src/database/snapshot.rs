@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::SNAPSHOT_MANIFEST;
+use crate::storage::buffer_pool::{self, PageCopy};
+use crate::types::{Entry, EntryValue};
+
+use super::database_manager::DatabaseManager;
+
+const MANIFEST_VERSION: u32 = 1;
+
+// Written once every file of a snapshot is on disk, a snapshot without one is incomplete
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    // Milliseconds since the epoch
+    pub taken_at: u64,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotFile {
+    pub name: String,
+    pub bytes: u64,
+}
+
+impl SnapshotFile {
+    pub fn to_entry(&self) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("file".to_string(), EntryValue::Str(self.name.clone()));
+        entry.insert("bytes".to_string(), EntryValue::Integer(self.bytes as isize));
+        entry
+    }
+}
+
+// Every table and index file as it was between two writes. Starting one only waits for changed pages to be
+// written back, the pages are then copied while writes go on and any page a write is about to overwrite is
+// copied first.
+pub struct Snapshot {
+    destination: String,
+    taken_at: u64,
+    copies: Vec<(String, PageCopy)>,
+}
+
+impl Snapshot {
+    // Has to run on the write thread so no write is half applied
+    pub fn start(dbm: &mut DatabaseManager, destination: &str) -> Result<Snapshot, String> {
+        if Path::new(destination).exists() {
+            return Err(format!("{} already exists, a snapshot needs a new directory", destination));
+        }
+        fs::create_dir_all(destination).map_err(|e| format!("Unable to create {}: {}", destination, e))?;
+        let taken_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0);
+        match dbm.start_copy(destination) {
+            Ok(copies) => Ok(Snapshot { destination: destination.to_string(), taken_at, copies }),
+            Err(e) => {
+                let _ = fs::remove_dir_all(destination);
+                Err(e)
+            }
+        }
+    }
+
+    // Copies whatever hasn't been copied yet and writes the manifest, can run on any thread
+    pub fn finish(self) -> Result<Vec<SnapshotFile>, String> {
+        let finished = self.copy_files();
+        if finished.is_err() {
+            for (_, copy) in &self.copies {
+                buffer_pool::global().cancel_copy(copy);
+            }
+        }
+        finished.map_err(|e| format!("Snapshot into {} is incomplete: {}", self.destination, e))
+    }
+
+    fn copy_files(&self) -> io::Result<Vec<SnapshotFile>> {
+        let mut files = vec![];
+        for (name, copy) in &self.copies {
+            buffer_pool::global().finish_copy(copy)?;
+            let bytes = fs::metadata(Path::new(&self.destination).join(name))?.len();
+            files.push(SnapshotFile { name: name.clone(), bytes });
+        }
+        let manifest = Manifest { version: MANIFEST_VERSION, taken_at: self.taken_at, files: files.clone() };
+        let manifest_file = Path::new(&self.destination).join(SNAPSHOT_MANIFEST);
+        let writing = Path::new(&self.destination).join(format!("{}.writing", SNAPSHOT_MANIFEST));
+        let mut file = File::create(&writing)?;
+        file.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+        file.sync_all()?;
+        fs::rename(&writing, manifest_file)?;
+        File::open(&self.destination)?.sync_all()?;
+        Ok(files)
+    }
+}
+
+pub fn read_manifest(directory: &str) -> Result<Manifest, String> {
+    let path = Path::new(directory).join(SNAPSHOT_MANIFEST);
+    let file = File::open(&path).map_err(|e| format!("{} is not a complete snapshot, unable to open {}: {}", directory, SNAPSHOT_MANIFEST, e))?;
+    let manifest: Manifest = serde_json::from_reader(file).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(format!("Unsupported snapshot version {}", manifest.version));
+    }
+    Ok(manifest)
+}
@@ -13,6 +13,8 @@ pub trait Hook {
     fn execute(&mut self, event: Event, proposed_edits:Option<Vec<DBEdit>>, commited_edits: Option<Vec<CommitedEdit>>, db: &mut dyn DB, workspace: Workspace) -> Option<Vec<DBEdit>>;
     fn get_events(&self) -> Vec<Event>;
     fn get_table(&self) -> String;
+    // Called when the table is dropped, no events follow
+    fn close(&mut self) {}
 }
 
 pub trait ObserverHook {
@@ -1,11 +1,12 @@
 
 
 use crate::{actions::workspace::Workspace, database::db_trait::DB, types::CommitedEdit};
-use crate::networking::types::{ListenResponse, DBResponse, ToClientMessage, ListenEvent};
+use crate::networking::types::{ListenResponse, DBResponse, ToClientMessage, ListenEvent, ProtocolError};
 use crate::hooks::hook::Event;
 use crate::Entry;
 use crate::types::DBEdit;
 use crate::hooks::hook::Hook;
+use crate::metrics::{self, LISTENERS};
 use futures::FutureExt;
 use uuid::Uuid;
 use std::collections::HashMap;
@@ -51,6 +52,17 @@ impl ListenerHook {
             self.response_channels.insert(new_listener.uuid, new_listener.channel);
         };
     }
+
+    fn remove_listener(&mut self, listener: Uuid) {
+        let mut removed = 0;
+        for event_listeners in self.listeners.values_mut() {
+            let before = event_listeners.len();
+            event_listeners.retain(|id| *id != listener);
+            removed += before - event_listeners.len();
+        }
+        self.response_channels.remove(&listener);
+        metrics::global().add_to_gauge(LISTENERS, &[("table", &self.table)], -(removed as i64));
+    }
 }
 
 impl Hook for ListenerHook {
@@ -71,6 +83,7 @@ impl Hook for ListenerHook {
             _ => {return proposed_edits}
         };
 
+        let mut closed = vec![];
         for listener in listener_list {
             let listener_channel = self.response_channels.get(listener).unwrap();
             let msg = ToClientMessage::Event(ListenResponse {
@@ -78,12 +91,30 @@ impl Hook for ListenerHook {
                 event: levent.clone(),
                 value: DBResponse::ManyResults(Ok(entries.clone())),
             });
-            listener_channel.blocking_send(msg);
+            if listener_channel.blocking_send(msg).is_err() {
+                closed.push(*listener);
+            }
+        }
+        // The connection is gone, it won't read anything else
+        for listener in closed {
+            self.remove_listener(listener);
         }
         return proposed_edits;
     }
     fn get_events(&self) -> Vec<Event>{return vec![Event::PostInsert(None), Event::PostDelete]}
     fn get_table(&self) -> String{self.table.clone()}
+    fn close(&mut self) {
+        self.update_listeners();
+        let listeners: Vec<Uuid> = self.response_channels.keys().cloned().collect();
+        for listener in listeners {
+            let msg = ToClientMessage::ProtocolError(ProtocolError {
+                request_id: None,
+                message: format!("Table {} was dropped", self.table),
+            });
+            let _ = self.response_channels[&listener].blocking_send(msg);
+            self.remove_listener(listener);
+        }
+    }
 }
 
 pub struct NewListenerObj {
@@ -1,5 +1,5 @@
 mod transform_hook;
 mod transform;
 
-pub use self::transform_hook::TransformHook;
+pub use self::transform_hook::{errors_table, TransformHook};
 pub use self::transform::Transform;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use crate::{actions::{Action, workspace::Workspace}, config::expression_parser::{ExpressionValue, Statement}, constants::AGGREGATION_KEY, database::{db_trait::DB}, types::{DBEdit, EditType}};
 use crate::constants::ROW_ID_COLUMN_NAME;
 use crate::constants::SOURCE_ENTRY_ID;
+use crate::constants::SOURCE_ENTRY_IDS;
 use crate::constants::UNION_MATCHING_KEY;
 use crate::Entry;
 use crate::EntryValue;
@@ -20,6 +21,17 @@ pub enum Transform {
 }
 
 impl Transform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Filter(_) => "Filter",
+            Transform::Union(_) => "Union",
+            Transform::Function(_) => "Function",
+            Transform::Aggregate(_) => "Aggregation",
+            Transform::Action(_) => "Action",
+            Transform::None => "None",
+        }
+    }
+
     pub fn execute(
         &self,
         transaction: Entry,
@@ -27,28 +39,15 @@ impl Transform {
         db: &mut dyn DB,
         source_table: Option<&String>,
         workspace: Workspace
-    ) -> Option<DBEdit> {
+    ) -> Result<Option<DBEdit>, String> {
         match self {
             Transform::Function(statments) => {
-                match Transform::function_transform(statments, transaction) {
-                    Ok(entry) => Some(DBEdit::new(table_name.clone(), EditType::Insert(entry))),
-                    Err(something) => {
-                        print!("{}: {}", table_name, something);
-                        return None;
-                    }
-                }
+                let entry = Transform::function_transform(statments, transaction)?;
+                Ok(Some(DBEdit::new(table_name.clone(), EditType::Insert(entry))))
             }
             Transform::Filter(statement) => {
-                match Transform::filter_transform(statement, transaction) {
-                    Ok(wrapped_entry) => match wrapped_entry {
-                        Some(entry) => Some(DBEdit::new(table_name.clone(), EditType::Insert(entry))),
-                        None => None
-                    },
-                    Err(something) => {
-                        print!("{}: {}", table_name, something);
-                        return None;
-                    }
-                }
+                let wrapped_entry = Transform::filter_transform(statement, transaction)?;
+                Ok(wrapped_entry.map(|entry| DBEdit::new(table_name.clone(), EditType::Insert(entry))))
             }
 
             Transform::Union(columns) => match Transform::union_transform(
@@ -69,15 +68,15 @@ impl Transform {
                         }
                     }
                     //TODO maybe make some kindof unmatchable type so that this doesnt mess things up
-                    return Some(DBEdit::new(
+                    Ok(Some(DBEdit::new(
                         table_name.clone(),
                         EditType::Update(entry,
                             UNION_MATCHING_KEY.to_string(),
                             foreign_value.unwrap_or(&EntryValue::Str("Does Not Match Anything abcdefg".to_string())).clone()
                         )
-                    ))
+                    )))
                 },
-                None => None
+                None => Ok(None)
             },
 
             Transform::Aggregate((statements, aggregation_column)) => match Transform::aggregate_transform(
@@ -88,38 +87,27 @@ impl Transform {
                 aggregation_column, 
                 db
             ){
-                Ok(entry) => Some(
+                Ok(entry) => Ok(Some(
                     DBEdit::new(
                         table_name.clone(), 
                         EditType::Update(
                             entry, AGGREGATION_KEY.to_string(),
                             transaction.get(aggregation_column).unwrap().clone()
-                        ))),
-                Err(something) => {
-                    print!("{}: {}", table_name, something);
-                    return None;
-                }
+                        )))),
+                Err(something) => Err(something),
             },
-            Transform::None => Some(DBEdit::new(table_name.clone(), EditType::Insert(transaction))),
+            Transform::None => Ok(Some(DBEdit::new(table_name.clone(), EditType::Insert(transaction)))),
             Transform::Action(action) => {
-                let workspace_path = match workspace.get_absolute_workspace_path() {
-                    Ok(p) => p,
-                    Err(e) => {
-                        println!("WARNING!!! Error when getting absolute workspace path: {}", e);
-                        return None
-                    }
-                };
+                let workspace_path = workspace.get_absolute_workspace_path()
+                    .map_err(|e| format!("Unable to find the action workspace: {}", e))?;
                 let result:Result<Entry, String> = action.serde_run_no_client(transaction.clone(), workspace_path);
                 match result {
                     Ok(mut result) => {
                         let source_uuid = transaction.get(&ROW_ID_COLUMN_NAME.to_string()).unwrap();
                         result.insert(SOURCE_ENTRY_ID.to_string(), source_uuid.clone());
-                        Some(DBEdit::new(table_name.clone(), EditType::Insert(result)))
-                    }
-                    Err(e) => {
-                        println!("Warning following error during action transform: {}", e);
-                        None
+                        Ok(Some(DBEdit::new(table_name.clone(), EditType::Insert(result))))
                     }
+                    Err(e) => Err(format!("Action failed: {}", e)),
                 }
             }
         }
@@ -192,6 +180,7 @@ impl Transform {
             }
         }
 
+        let source_uuid = transaction.get(ROW_ID_COLUMN_NAME).cloned();
         match db.get_all(
             table_name,
             UNION_MATCHING_KEY.to_string(),
@@ -201,31 +190,47 @@ impl Transform {
                 let old_entries: Vec<Entry> = commited_edits;
                 if old_entries.len() > 0 {
                     let mut old_entry = old_entries[0].clone();
+                    let mut source_ids = match old_entry.remove(SOURCE_ENTRY_IDS) {
+                        Some(EntryValue::Array(ids)) => ids,
+                        _ => vec![],
+                    };
                     for (key, value) in transaction {
                         old_entry.insert(key, value);
                     }
+                    // Every row merged into this one contributed to it
+                    if let Some(uuid) = source_uuid {
+                        if !source_ids.contains(&uuid) {
+                            source_ids.push(uuid);
+                        }
+                    }
+                    old_entry.insert(SOURCE_ENTRY_IDS.to_string(), EntryValue::Array(source_ids));
                     Some(old_entry)
                 } else {
-                    let mut new_entry = transaction.clone();
-                    new_entry.remove(foreign_key.unwrap());
-                    new_entry.insert(
-                        UNION_MATCHING_KEY.to_string(),
-                        foreign_value.unwrap().clone(),
-                    );
-                    Some(new_entry)
+                    Some(Self::new_union_entry(transaction.clone(), foreign_key.unwrap(), foreign_value.unwrap(), source_uuid))
                 }
             }
-            Err(_) => {
-                let mut new_entry = transaction.clone();
-                new_entry.remove(foreign_key.unwrap());
-                new_entry.insert(
-                    UNION_MATCHING_KEY.to_string(),
-                    foreign_value.unwrap().clone(),
-                );
-                Some(new_entry)
-            }
+            Err(_) => Some(Self::new_union_entry(transaction.clone(), foreign_key.unwrap(), foreign_value.unwrap(), source_uuid)),
         }
     }
+
+    fn new_union_entry(
+        transaction: Entry,
+        foreign_key: &String,
+        foreign_value: &EntryValue,
+        source_uuid: Option<EntryValue>,
+    ) -> Entry {
+        let mut new_entry = transaction;
+        new_entry.remove(foreign_key);
+        new_entry.insert(
+            UNION_MATCHING_KEY.to_string(),
+            foreign_value.clone(),
+        );
+        new_entry.insert(
+            SOURCE_ENTRY_IDS.to_string(),
+            EntryValue::Array(source_uuid.into_iter().collect()),
+        );
+        new_entry
+    }
     fn aggregate_transform(
         statements: &Vec<Statement>,
         mut transaction: Entry,
@@ -240,6 +245,11 @@ impl Transform {
         let aggregation_key = transaction.get(aggregation_column).unwrap();
         map.insert(SOURCE_ENTRY_ID.to_string(), source_uuid.clone());
         map.insert(AGGREGATION_KEY.to_string(), aggregation_key.clone());
+        let source_ids = source_transactions
+            .iter()
+            .filter_map(|source_transaction| source_transaction.get(ROW_ID_COLUMN_NAME).cloned())
+            .collect();
+        map.insert(SOURCE_ENTRY_IDS.to_string(), EntryValue::Array(source_ids));
         let mut n = 0;
         for mut source_transaction in source_transactions.drain(..) {
             for statement in statements {
@@ -1,9 +1,17 @@
 use crate::{actions::workspace::Workspace, database::{db_trait::DB}, types::CommitedEdit};
-use crate::types::{DBEdit, EditType};
-use crate::constants::SOURCE_ENTRY_ID;
+use crate::types::{DBEdit, EditType, Entry, EntryValue};
+use crate::constants::{ERRORS_TABLE_SUFFIX, FAILED_ENTRY, FAILED_SOURCE_TABLE, SOURCE_ENTRY_ID};
 use crate::constants::ROW_ID_COLUMN_NAME;
 use super::Transform;
 use crate::hooks::hook::{Event, Hook};
+use crate::metrics::{self, TRANSFORM_DURATION, TRANSFORM_EXECUTIONS, TRANSFORM_FAILURES};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug_span, error};
+
+// Where a derived table keeps the rows its transform failed on
+pub fn errors_table(table: &str) -> String {
+    format!("{}{}", table, ERRORS_TABLE_SUFFIX)
+}
 
 pub struct TransformHook {
     transform: Transform,
@@ -33,12 +41,29 @@ impl Hook for TransformHook {
                         _ => panic!("Recieved Update During Insert"),
 
                     };
-                    let transformed_entry = self.transform.execute(entry, &self.table, db, source_table.as_ref(), workspace.clone());
+                    let failed_entry = entry.clone();
+                    let span = debug_span!("transform", table = %self.table, source = source_table.as_deref(), transform = self.transform.name());
+                    let started = Instant::now();
+                    let transformed_entry = span.in_scope(|| {
+                        self.transform.execute(entry, &self.table, db, source_table.as_ref(), workspace.clone())
+                    });
+                    let labels = [("table", self.table.as_str()), ("transform", self.transform.name())];
+                    // Source tables pass rows straight through, only count real transforms
+                    if self.transform != Transform::None {
+                        metrics::global().observe(TRANSFORM_DURATION, &labels, started.elapsed());
+                        metrics::global().increment(TRANSFORM_EXECUTIONS, &labels, 1);
+                    }
                     match transformed_entry {
-                        Some(edit) => {
+                        Ok(Some(edit)) => {
                             output.push(edit);
                         },
-                        None => {}
+                        Ok(None) => {}
+                        Err(e) => {
+                            metrics::global().increment(TRANSFORM_FAILURES, &labels, 1);
+                            error!(table = %self.table, source = source_table.as_deref(), error = %e, "Transform failed");
+                            let failure = failure_row(failed_entry, source_table.as_deref(), self.transform.name(), &e);
+                            output.push(DBEdit::new(errors_table(&self.table), EditType::Insert(failure)));
+                        }
                     };
                 }
             },
@@ -63,7 +88,20 @@ impl Hook for TransformHook {
                 }
             }
             Event::PreDelete => {
-                return proposed_edits;
+                let mut edits = proposed_edits.unwrap_or_default();
+                // A failure is forgotten once the row it was for is gone
+                if self.transform != Transform::None {
+                    let mut failures = vec![];
+                    for edit in &edits {
+                        if let EditType::Delete(column, id) = &edit.edit_params {
+                            if column == SOURCE_ENTRY_ID {
+                                failures.push(DBEdit::new(errors_table(&self.table), EditType::Delete(SOURCE_ENTRY_ID.to_string(), id.clone())));
+                            }
+                        }
+                    }
+                    edits.append(&mut failures);
+                }
+                return Some(edits);
             }
         }
 
@@ -71,4 +109,20 @@ impl Hook for TransformHook {
     }
     fn get_events(&self) -> Vec<Event> { vec![Event::PreInsert(None), Event::PostDelete] }
     fn get_table(&self) -> String { self.table.clone() }
-}
\ No newline at end of file
+}
+// Keeps the row as it reached the transform along with where it came from, so RetryFailed can insert it again
+fn failure_row(entry: Entry, source_table: Option<&str>, transform: &str, error: &str) -> Entry {
+    let mut failure = Entry::new();
+    if let Some(id) = entry.get(ROW_ID_COLUMN_NAME) {
+        failure.insert(SOURCE_ENTRY_ID.to_string(), id.clone());
+    }
+    if let Some(source_table) = source_table {
+        failure.insert(FAILED_SOURCE_TABLE.to_string(), EntryValue::Str(source_table.to_string()));
+    }
+    failure.insert(FAILED_ENTRY.to_string(), EntryValue::Map(entry));
+    failure.insert("transform".to_string(), EntryValue::Str(transform.to_string()));
+    failure.insert("error".to_string(), EntryValue::Str(error.to_string()));
+    let failed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0);
+    failure.insert("failedAt".to_string(), EntryValue::Integer(failed_at as isize));
+    failure
+}
@@ -0,0 +1,44 @@
+use crate::config::config_reader::{LogFormat, LoggingConfig};
+use crate::constants::DEFAULT_LOG_LEVEL;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+// Request and transform spans are logged as they close, with how long they took
+pub fn subscriber(config: Option<&LoggingConfig>) -> Result<Box<dyn Subscriber + Send + Sync>, String> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => {
+            let level = config.and_then(|config| config.level.clone()).unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+            EnvFilter::try_new(&level).map_err(|e| format!("Invalid log level {}: {}", level, e))?
+        }
+    };
+    let file = match config.and_then(|config| config.file.as_ref()) {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Unable to open log file {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    let format = config.and_then(|config| config.format).unwrap_or(LogFormat::Text);
+    Ok(match (format, file) {
+        (LogFormat::Text, None) => Box::new(builder.with_ansi(io::stderr().is_terminal()).with_writer(io::stderr).finish()),
+        (LogFormat::Text, Some(file)) => Box::new(builder.with_ansi(false).with_writer(Mutex::new(file)).finish()),
+        (LogFormat::Json, None) => Box::new(builder.json().with_writer(io::stderr).finish()),
+        (LogFormat::Json, Some(file)) => Box::new(builder.json().with_writer(Mutex::new(file)).finish()),
+    })
+}
+
+pub fn init(config: Option<&LoggingConfig>) -> Result<(), String> {
+    tracing::subscriber::set_global_default(subscriber(config)?)
+        .map_err(|e| format!("Unable to set up logging: {}", e))
+}
@@ -1,4 +1,6 @@
+mod auth;
 mod btree;
+mod commands;
 mod config;
 mod constants;
 mod server;
@@ -8,6 +10,8 @@ mod types;
 mod networking;
 mod utilities;
 mod hooks;
+mod logging;
+mod metrics;
 mod storage;
 mod actions;
 mod database;
@@ -18,11 +22,213 @@ use crate::btree::node::IndexValue;
 use crate::config::expression_parser::Expression;
 
 use crate::types::{Entry, EntryValue};
-use std::env;
+use crate::config::config_reader::read_config_file;
+use clap::{Parser, Subcommand};
+use std::fs::File;
 use std::io;
+use std::process;
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    server::start_server(args[1].clone(), args[2].clone()).unwrap();
-    return Ok(());
+#[derive(Parser)]
+#[command(name = "reactive_db", about = "A database that transforms your data as it arrives")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Start the server
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        #[arg(long, default_value_t = 1108)]
+        port: u16,
+        #[arg(long)]
+        config: String,
+        /// Overrides storage_destination from the config
+        #[arg(long)]
+        data_dir: Option<String>,
+    },
+    /// Validate a config and print the graph of derived tables
+    CheckConfig {
+        config: String,
+    },
+    /// Print every table with its transform, inputs, outputs and columns as the database sees them
+    Describe {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// Print Graphviz DOT instead
+        #[arg(long)]
+        dot: bool,
+    },
+    /// Export every table as JSON, the server must not be running against the data directory
+    Dump {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// Defaults to stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Import a dump into an empty data directory, derived tables are recomputed
+    Restore {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        #[arg(long)]
+        input: String,
+    },
+    /// Copy a snapshot taken by a running server into an empty data directory
+    RestoreSnapshot {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// The directory the snapshot was written to
+        #[arg(long)]
+        input: String,
+    },
+    /// Empty a derived table and recompute it and everything downstream from its inputs
+    Rebuild {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        table: String,
+    },
+    /// Rewrite tables into densely packed files to reclaim the space deleted rows left behind
+    Compact {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// Defaults to every table
+        table: Option<String>,
+    },
+    /// Print the salted hash of a secret read from stdin, for password_hash or api_token_hash in the auth config
+    HashSecret,
+    /// Check that indexes and stored rows agree
+    Verify {
+        #[arg(long)]
+        config: String,
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// Rebuild the indexes of tables with problems from their stored rows
+        #[arg(long)]
+        repair: bool,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Serve { bind, port, config, data_dir } => {
+            server::start_server(bind, port, config, data_dir).map_err(|e| e.to_string())
+        }
+        Command::CheckConfig { config } => {
+            let config = read_config_file(config).map_err(|e| e.to_string())?;
+            let graph = config::config_check::check_config(&config)?;
+            print!("{}", config::config_check::format_summary(&graph));
+            println!("Config is valid");
+            Ok(())
+        }
+        Command::Describe { config, data_dir, dot } => {
+            let tables = commands::describe::describe(load_config(config, data_dir)?)?;
+            if dot {
+                print!("{}", database::describe::to_dot(&tables));
+            } else {
+                print!("{}", database::describe::format_descriptions(&tables));
+            }
+            Ok(())
+        }
+        Command::Dump { config, data_dir, output } => {
+            let config = load_config(config, data_dir)?;
+            match output {
+                Some(path) => {
+                    let mut file = File::create(&path).map_err(|e| format!("Unable to create {}: {}", path, e))?;
+                    commands::dump::dump(config, &mut file)
+                }
+                None => commands::dump::dump(config, &mut io::stdout()),
+            }
+        }
+        Command::Restore { config, data_dir, input } => {
+            let config = load_config(config, data_dir)?;
+            let mut file = File::open(&input).map_err(|e| format!("Unable to open {}: {}", input, e))?;
+            let restored = commands::dump::restore(config, &mut file)?;
+            println!("Restored {} entries", restored);
+            Ok(())
+        }
+        Command::RestoreSnapshot { config, data_dir, input } => {
+            let restored = commands::snapshot::restore_snapshot(load_config(config, data_dir)?, &input)?;
+            println!("Restored {} files", restored);
+            Ok(())
+        }
+        Command::Rebuild { config, data_dir, table } => {
+            for change in commands::rebuild::rebuild(load_config(config, data_dir)?, &table)? {
+                println!("{} {} ({} rows)", change.table, change.change, change.rows);
+            }
+            Ok(())
+        }
+        Command::Compact { config, data_dir, table } => {
+            let compactions = commands::compact::compact(load_config(config, data_dir)?, table.as_deref())?;
+            for compaction in &compactions {
+                println!(
+                    "{}: {} bytes -> {} bytes ({} reclaimed)",
+                    compaction.table, compaction.bytes_before, compaction.bytes_after, compaction.reclaimed()
+                );
+            }
+            let reclaimed: u64 = compactions.iter().map(|compaction| compaction.reclaimed()).sum();
+            println!("Reclaimed {} bytes", reclaimed);
+            Ok(())
+        }
+        Command::HashSecret => {
+            let mut secret = String::new();
+            io::stdin().read_line(&mut secret).map_err(|e| format!("Unable to read secret: {}", e))?;
+            println!("{}", auth::hash_secret(secret.trim_end_matches(&['\r', '\n'][..]))?);
+            Ok(())
+        }
+        Command::Verify { config, data_dir, repair } => {
+            let config = load_config(config, data_dir)?;
+            let mut problems = commands::verify::verify(config.clone())?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if repair && !problems.is_empty() {
+                for table in commands::verify::repair(config.clone())? {
+                    println!("Rebuilt the indexes of {}", table);
+                }
+                problems = commands::verify::verify(config)?;
+                for problem in &problems {
+                    println!("Still after repair: {}", problem);
+                }
+            }
+            if !problems.is_empty() {
+                return Err(format!("Found {} problems", problems.len()));
+            }
+            println!("No problems found");
+            Ok(())
+        }
+    }
+}
+
+// Commands that open the database log the same way the server does
+fn load_config(config: String, data_dir: Option<String>) -> Result<config::config_reader::DbConfig, String> {
+    let mut config = read_config_file(config).map_err(|e| e.to_string())?;
+    if let Some(data_dir) = data_dir {
+        config.storage_destination = data_dir;
+    }
+    logging::init(config.logging.as_ref())?;
+    Ok(config)
 }
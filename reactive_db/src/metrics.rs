@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::constants::DURATION_BUCKETS;
+use crate::database::database::DatabaseReader;
+use crate::storage::buffer_pool;
+use crate::storage::storage_engine::StorageStats;
+use crate::table::table_trait::Table;
+
+pub const REQUESTS: &str = "reactivedb_requests_total";
+pub const REQUEST_FAILURES: &str = "reactivedb_request_failures_total";
+pub const REQUEST_DURATION: &str = "reactivedb_request_duration_seconds";
+pub const ROWS_INSERTED: &str = "reactivedb_rows_inserted_total";
+pub const ROWS_DELETED: &str = "reactivedb_rows_deleted_total";
+pub const TRANSFORM_EXECUTIONS: &str = "reactivedb_transform_executions_total";
+pub const TRANSFORM_FAILURES: &str = "reactivedb_transform_failures_total";
+pub const TRANSFORM_DURATION: &str = "reactivedb_transform_duration_seconds";
+pub const LISTENERS: &str = "reactivedb_listeners";
+pub const STORAGE_BYTES: &str = "reactivedb_storage_bytes";
+pub const BLOCK_CACHE_HITS: &str = "reactivedb_block_cache_hits_total";
+pub const BLOCK_CACHE_MISSES: &str = "reactivedb_block_cache_misses_total";
+pub const BLOCK_CACHE_HIT_RATIO: &str = "reactivedb_block_cache_hit_ratio";
+pub const BUFFER_POOL_BYTES: &str = "reactivedb_buffer_pool_bytes";
+pub const BUFFER_POOL_BUDGET: &str = "reactivedb_buffer_pool_budget_bytes";
+pub const BUFFER_POOL_DIRTY_PAGES: &str = "reactivedb_buffer_pool_dirty_pages";
+pub const BUFFER_POOL_EVICTIONS: &str = "reactivedb_buffer_pool_evictions_total";
+
+// Everything the server reports, in the order it's written out
+const DEFINITIONS: &[(&str, &str, &str)] = &[
+    (REQUESTS, "counter", "Requests served by query type"),
+    (REQUEST_FAILURES, "counter", "Requests answered with an error by query type"),
+    (REQUEST_DURATION, "histogram", "Time taken to serve a request by query type"),
+    (ROWS_INSERTED, "counter", "Rows written to each table, including rows written by transforms"),
+    (ROWS_DELETED, "counter", "Rows deleted from each table, including rows deleted by transforms"),
+    (TRANSFORM_EXECUTIONS, "counter", "Rows run through each derived table's transform"),
+    (TRANSFORM_FAILURES, "counter", "Transform executions that returned an error"),
+    (TRANSFORM_DURATION, "histogram", "Time taken to transform a row"),
+    (LISTENERS, "gauge", "Connected listeners on each table"),
+    (STORAGE_BYTES, "gauge", "Size of each table's row and index files"),
+    (BLOCK_CACHE_HITS, "counter", "Block reads served from each table's block caches"),
+    (BLOCK_CACHE_MISSES, "counter", "Block reads each table had to make from disk"),
+    (BLOCK_CACHE_HIT_RATIO, "gauge", "Share of each table's block reads served from cache"),
+    (BUFFER_POOL_BYTES, "gauge", "Memory held by pages in the shared buffer pool"),
+    (BUFFER_POOL_BUDGET, "gauge", "Memory the shared buffer pool may hold before evicting pages"),
+    (BUFFER_POOL_DIRTY_PAGES, "gauge", "Pages in the buffer pool not yet written back to their file"),
+    (BUFFER_POOL_EVICTIONS, "counter", "Pages evicted from the buffer pool to stay within its budget"),
+];
+
+type Labels = Vec<(&'static str, String)>;
+type Key = (&'static str, Labels);
+
+struct Histogram {
+    // Observations at or below each of DURATION_BUCKETS, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+// Counters and histograms are recorded where things happen, storage figures are read from the tables on every scrape
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<Key, u64>>,
+    gauges: Mutex<BTreeMap<Key, i64>>,
+    histograms: Mutex<BTreeMap<Key, Histogram>>,
+    tables: Mutex<Option<DatabaseReader>>,
+}
+
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+fn key(name: &'static str, labels: &[(&'static str, &str)]) -> Key {
+    (name, labels.iter().map(|(label, value)| (*label, value.to_string())).collect())
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        *self.counters.lock().unwrap().entry(key(name, labels)).or_insert(0) += by;
+    }
+
+    pub fn add_to_gauge(&self, name: &'static str, labels: &[(&'static str, &str)], delta: i64) {
+        *self.gauges.lock().unwrap().entry(key(name, labels)).or_insert(0) += delta;
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry(key(name, labels)).or_insert_with(|| Histogram {
+            buckets: vec![0; DURATION_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        });
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    pub fn watch_tables(&self, reader: DatabaseReader) {
+        *self.tables.lock().unwrap() = Some(reader);
+    }
+
+    // The Prometheus text exposition format
+    pub fn render(&self) -> String {
+        // The metric each sample belongs to, the sample with its labels and its value
+        let mut samples: Vec<(&str, String, f64)> = vec![];
+        for ((name, labels), value) in self.counters.lock().unwrap().iter() {
+            samples.push((name, sample_name(name, labels, None), *value as f64));
+        }
+        for ((name, labels), value) in self.gauges.lock().unwrap().iter() {
+            samples.push((name, sample_name(name, labels, None), *value as f64));
+        }
+        for ((name, labels), histogram) in self.histograms.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                samples.push((name, sample_name(&format!("{}_bucket", name), labels, Some(&bound.to_string())), cumulative as f64));
+            }
+            samples.push((name, sample_name(&format!("{}_bucket", name), labels, Some("+Inf")), histogram.count as f64));
+            samples.push((name, sample_name(&format!("{}_sum", name), labels, None), histogram.sum));
+            samples.push((name, sample_name(&format!("{}_count", name), labels, None), histogram.count as f64));
+        }
+        for (table, stats) in self.table_stats() {
+            let labels = vec![("table", table)];
+            samples.push((STORAGE_BYTES, sample_name(STORAGE_BYTES, &labels, None), stats.bytes as f64));
+            samples.push((BLOCK_CACHE_HITS, sample_name(BLOCK_CACHE_HITS, &labels, None), stats.cache_hits as f64));
+            samples.push((BLOCK_CACHE_MISSES, sample_name(BLOCK_CACHE_MISSES, &labels, None), stats.cache_misses as f64));
+            let lookups = stats.cache_hits + stats.cache_misses;
+            if lookups > 0 {
+                samples.push((BLOCK_CACHE_HIT_RATIO, sample_name(BLOCK_CACHE_HIT_RATIO, &labels, None), stats.cache_hits as f64 / lookups as f64));
+            }
+        }
+        let pool = buffer_pool::global().stats();
+        samples.push((BUFFER_POOL_BYTES, BUFFER_POOL_BYTES.to_string(), pool.bytes as f64));
+        samples.push((BUFFER_POOL_BUDGET, BUFFER_POOL_BUDGET.to_string(), pool.budget as f64));
+        samples.push((BUFFER_POOL_DIRTY_PAGES, BUFFER_POOL_DIRTY_PAGES.to_string(), pool.dirty_pages as f64));
+        samples.push((BUFFER_POOL_EVICTIONS, BUFFER_POOL_EVICTIONS.to_string(), pool.evictions as f64));
+
+        let mut output = String::new();
+        for (name, metric_type, help) in DEFINITIONS {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+            for (metric, sample, value) in &samples {
+                if metric == name {
+                    let _ = writeln!(output, "{} {}", sample, value);
+                }
+            }
+        }
+        output
+    }
+
+    fn table_stats(&self) -> Vec<(String, StorageStats)> {
+        let reader = match self.tables.lock().unwrap().clone() {
+            Some(reader) => reader,
+            None => return vec![],
+        };
+        let mut stats = vec![];
+        for table in reader.table_names() {
+            // Tables removed by a reload since table_names are skipped
+            if let Ok(table_obj) = reader.get_table(&table) {
+                if let Ok(table_stats) = table_obj.lock().unwrap().storage_stats() {
+                    stats.push((table, table_stats));
+                }
+            }
+        }
+        stats
+    }
+}
+
+fn sample_name(name: &str, labels: &[(&'static str, String)], le: Option<&str>) -> String {
+    let mut parts: Vec<String> = labels.iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        parts.push(format!("le=\"{}\"", le));
+    }
+    if parts.is_empty() {
+        return name.to_string();
+    }
+    format!("{}{{{}}}", name, parts.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
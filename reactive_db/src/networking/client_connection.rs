@@ -1,77 +1,217 @@
-use crate::networking::types::{DBRequest, ToClientMessage};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::io::Cursor;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::io::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::{Receiver, Sender};
+use crate::auth::{AccessControl, Principal};
+use crate::constants::{CLOSE_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::networking::encoding::Encoding;
+use crate::networking::framing::{read_frame, write_frame, Frame};
+use crate::networking::types::{AcceptedHandshake, DBRequest, Handshake, HandshakeResponse, ProtocolError, ToClientMessage};
+use serde::Deserialize;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::watch;
+use tracing::{debug, error, info_span, warn, Instrument};
 use uuid::Uuid;
 
-pub fn start_client_thread(
+// What every connection is served with, each one gets its own clone
+#[derive(Clone)]
+pub struct ConnectionSettings {
+    pub max_frame_size: usize,
+    pub access_control: Arc<AccessControl>,
+    // Changes once the server starts shutting down
+    pub shutdown: watch::Receiver<bool>,
+}
+
+pub fn start_client_thread<S>(
     id: Uuid,
     db_request_channel: Sender<(DBRequest, Uuid)>,
-    db_result_channel: Receiver<ToClientMessage>,
-    stream: TcpStream,
-) {
-    let (read_stream, write_stream) = tokio::io::split(stream);
-    tokio::spawn(async move {
-        handle_results(write_stream, db_result_channel).await;
-    });
+    db_response_channel_sender: Sender<(Sender<ToClientMessage>, Uuid, Principal)>,
+    mut stream: S,
+    settings: ConnectionSettings,
+    connection_done: Sender<()>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let span = info_span!("connection", connection_id = %id);
+    let ConnectionSettings { max_frame_size, access_control, mut shutdown } = settings;
     tokio::spawn(async move {
-        handle_incoming_messages(read_stream, id, db_request_channel)
-            .await
-            .unwrap();
-    });
+        let negotiated = tokio::select! {
+            negotiated = negotiate(&mut stream, max_frame_size, &access_control) => negotiated,
+            _ = shutdown.changed() => return,
+        };
+        let (accepted, principal, legacy_request) = match negotiated {
+            Ok(negotiated) => negotiated,
+            Err(e) => {
+                warn!(error = %e, "Handshake failed");
+                return;
+            }
+        };
+        // The db thread has to know where to respond and who is asking before the first request arrives
+        let (db_result_sender, db_result_channel) = channel(30);
+        if db_response_channel_sender.send((db_result_sender.clone(), id, principal)).await.is_err() {
+            return;
+        }
+        drop(db_response_channel_sender);
+        let (read_stream, write_stream) = tokio::io::split(stream);
+        tokio::spawn(async move {
+            handle_results(write_stream, db_result_channel, accepted).await;
+            drop(connection_done);
+        });
+        if let Some(request) = legacy_request {
+            if db_request_channel.send((request, id)).await.is_err() {
+                return;
+            }
+        }
+        // On shutdown stop reading so the db thread can finish what it already has
+        let incoming = handle_incoming_messages(read_stream, id, db_request_channel, db_result_sender, max_frame_size, accepted.encoding);
+        tokio::select! {
+            result = incoming => {
+                if let Err(e) = result {
+                    warn!(error = %e, "Connection closed with an error");
+                }
+            }
+            _ = shutdown.changed() => {}
+        };
+    }.instrument(span));
+}
+
+// Reads the handshake, authenticates the client and agrees on a protocol version and encoding.
+// Clients that predate the handshake send a JSON request first, those are served anonymously as JSON with that request
+// returned, under protocol version 0.
+pub(crate) async fn negotiate<S>(
+    stream: &mut S,
+    max_frame_size: usize,
+    access_control: &AccessControl,
+) -> io::Result<(AcceptedHandshake, Principal, Option<DBRequest>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let message_buffer = match read_frame(stream, max_frame_size).await? {
+        Frame::Message(message_buffer) => message_buffer,
+        Frame::TooLarge(message_size) => {
+            return reject(stream, format!("Handshake frame of {} bytes exceeds the maximum of {} bytes", message_size, max_frame_size)).await;
+        }
+        Frame::Closed => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed before handshake")),
+    };
+    let handshake: Handshake = match Encoding::Json.decode(message_buffer.as_slice()) {
+        Ok(handshake) => handshake,
+        Err(_) => {
+            return match Encoding::Json.decode::<DBRequest>(message_buffer.as_slice()) {
+                Ok(request) => match access_control.authenticate(None) {
+                    Ok(principal) => Ok((AcceptedHandshake { protocol_version: 0, encoding: Encoding::Json }, principal, Some(request))),
+                    Err(e) => Err(io::Error::new(io::ErrorKind::PermissionDenied, e)),
+                },
+                Err(e) => reject(stream, format!("Expected handshake: {}", e)).await,
+            };
+        }
+    };
+    if handshake.protocol_version < MIN_PROTOCOL_VERSION {
+        let message = format!(
+            "Protocol version {} is not supported, server supports versions {} to {}",
+            handshake.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        );
+        return reject(stream, message).await;
+    }
+    let principal = match access_control.authenticate(handshake.credentials.as_ref()) {
+        Ok(principal) => principal,
+        Err(e) => return reject(stream, e).await,
+    };
+    let accepted = AcceptedHandshake {
+        protocol_version: handshake.protocol_version.min(PROTOCOL_VERSION),
+        encoding: handshake.encoding,
+    };
+    let response = Encoding::Json.encode(&HandshakeResponse::Accepted(accepted))?;
+    write_frame(stream, response.as_slice()).await?;
+    Ok((accepted, principal, None))
+}
+
+async fn reject<S: AsyncWrite + Unpin, T>(stream: &mut S, message: String) -> io::Result<T> {
+    let response = Encoding::Json.encode(&HandshakeResponse::Rejected(message.clone()))?;
+    write_frame(stream, response.as_slice()).await?;
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
 }
 
-async fn handle_results(
-    mut stream: WriteHalf<TcpStream>,
+// Clients older than CLOSE_PROTOCOL_VERSION can't decode Close, their connection is closed without it
+pub(crate) async fn handle_results<W: AsyncWrite + Unpin>(
+    mut stream: W,
     mut db_result_channel: Receiver<ToClientMessage>,
+    accepted: AcceptedHandshake,
 ) {
-    loop {
-        let db_result = db_result_channel.recv().await.unwrap();
-        let serialized_result = match serde_json::to_vec(&db_result) {
+    while let Some(db_result) = db_result_channel.recv().await {
+        let closing = matches!(db_result, ToClientMessage::Close(_));
+        if closing && accepted.protocol_version < CLOSE_PROTOCOL_VERSION {
+            break;
+        }
+        let serialized_result = match accepted.encoding.encode(&db_result) {
             Ok(r) => r,
-            Err(e) => panic!(e),
-        };
-        let mut buff = vec![];
-        WriteBytesExt::write_u32::<BigEndian>(&mut buff, serialized_result.len() as u32).unwrap();
-        match stream.write(buff.as_slice()).await {
-            Ok(_) =>{},
-            Err(_) => {break}
-        };
-        match stream.write(serialized_result.as_slice()).await {
-            Ok(_) =>{},
-            Err(_) => {break}
+            Err(e) => {
+                error!(error = %e, "Unable to serialize response");
+                continue;
+            }
         };
+        if write_frame(&mut stream, serialized_result.as_slice()).await.is_err() || closing {
+            break;
+        }
     }
+    let _ = stream.shutdown().await;
 }
 
-async fn handle_incoming_messages(
-    mut stream: ReadHalf<TcpStream>,
+async fn handle_incoming_messages<R: AsyncRead + Unpin>(
+    mut stream: R,
     id: Uuid,
     db_request_channel: Sender<(DBRequest, Uuid)>,
-) -> std::io::Result<()> {
+    db_result_sender: Sender<ToClientMessage>,
+    max_frame_size: usize,
+    encoding: Encoding,
+) -> io::Result<()> {
     let mut n = 0;
     loop {
-        n += 1;
-        print!("\rconnection id: {} | request count: {}", id, n);
-        let mut size_buffer = [0; 4];
-        stream.read(&mut size_buffer).await?;
-        let message_size =
-            ReadBytesExt::read_u32::<BigEndian>(&mut Cursor::new(size_buffer)).unwrap() as usize;
-        if message_size == 0 {
-            return Ok(());
-        }
-        let mut message_buffer = vec![0; message_size];
-        stream.read(&mut message_buffer).await?;
-        let json_response: serde_json::Result<DBRequest> =
-            serde_json::from_slice(message_buffer.as_slice());
-        //println!("{:?}", size_buffer);
+        let message_buffer = match read_frame(&mut stream, max_frame_size).await? {
+            Frame::Message(message_buffer) => message_buffer,
+            Frame::TooLarge(message_size) => {
+                let message = format!("Frame of {} bytes exceeds the maximum of {} bytes", message_size, max_frame_size);
+                send_protocol_error(&db_result_sender, None, message).await;
+                continue;
+            }
+            Frame::Closed => return Ok(()),
+        };
         // Request db thread for results
-        let _ = match json_response {
-            Ok(request) => db_request_channel.send((request, id)).await,
-            Err(e) => panic!(format!("{:?}, {:?}", e, message_buffer)),
+        match encoding.decode::<DBRequest>(message_buffer.as_slice()) {
+            Ok(request) => {
+                n += 1;
+                debug!(requests = n, "Request received");
+                if db_request_channel.send((request, id)).await.is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) => {
+                let request_id = find_request_id(message_buffer.as_slice(), encoding);
+                warn!(error = %e, "Malformed request");
+                send_protocol_error(&db_result_sender, request_id, format!("Malformed request: {}", e)).await;
+            }
         };
     }
 }
+
+async fn send_protocol_error(db_result_sender: &Sender<ToClientMessage>, request_id: Option<Uuid>, message: String) {
+    let _ = db_result_sender
+        .send(ToClientMessage::ProtocolError(ProtocolError { request_id, message }))
+        .await;
+}
+
+// Only the parts of a query needed to find its id, anything else in the message is ignored
+#[derive(Deserialize)]
+enum RequestIdProbe {
+    Query(QueryIdProbe),
+}
+
+#[derive(Deserialize)]
+struct QueryIdProbe {
+    request_id: Uuid,
+}
+
+// Best effort lookup of the request id so the client can match the error to its pending request
+fn find_request_id(message: &[u8], encoding: Encoding) -> Option<Uuid> {
+    match encoding.decode::<RequestIdProbe>(message).ok()? {
+        RequestIdProbe::Query(query) => Some(query.request_id),
+    }
+}
@@ -0,0 +1,33 @@
+use std::convert::Infallible;
+use std::net::TcpListener;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::watch;
+
+use crate::metrics;
+
+// Answers scrapes until the server shuts down
+pub async fn serve_metrics(listener: TcpListener, mut shutdown: watch::Receiver<bool>) -> hyper::Result<()> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(respond)) });
+    Server::from_tcp(listener)?
+        .serve(make_service)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.changed().await;
+        })
+        .await
+}
+
+async fn respond(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Not found"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    // Reading storage figures locks each table in turn, so keep it off the async threads
+    let body = tokio::task::spawn_blocking(|| metrics::global().render()).await.unwrap_or_default();
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(CONTENT_TYPE, "text/plain; version=0.0.4".parse().unwrap());
+    Ok(response)
+}
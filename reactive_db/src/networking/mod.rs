@@ -1,12 +1,23 @@
 mod network_types;
 mod routes;
+mod tests;
 
 pub mod client_connection;
+pub mod metrics_server;
+pub mod tls;
+
+// Frames are read, written and encoded the same way as the Rust client does
+pub use reactivedb_rust_client::{encoding, framing};
 
 pub mod types {
 
     pub use super::network_types::{
+        Handshake,
+        Credentials,
+        HandshakeResponse,
+        AcceptedHandshake,
         ToClientMessage,
+        ProtocolError,
         ListenResponse,
         RequestResponse,
         DBResponse,
@@ -16,7 +27,6 @@ pub mod types {
         ListenRequest,
         Query,
         GetData,
-        DeleteData,
         InsertData,
     };
 }
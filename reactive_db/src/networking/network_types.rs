@@ -1,12 +1,43 @@
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use crate::types::{Entry, EntryValue};
+use super::encoding::Encoding;
+
+// ===============Handshake types================
+// First frame sent by the client, always JSON encoded
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub encoding: Encoding,
+    pub credentials: Option<Credentials>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Credentials {
+    ApiToken(String),
+    UserPassword(String, String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum HandshakeResponse {
+    Accepted(AcceptedHandshake),
+    Rejected(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AcceptedHandshake {
+    pub protocol_version: u32,
+    pub encoding: Encoding,
+}
 
 // ===============Response types================
 #[derive(Serialize, Deserialize)]
 pub enum ToClientMessage {
     Event(ListenResponse),
     RequestResponse(RequestResponse),
+    ProtocolError(ProtocolError),
+    // Last message before the server closes the connection, carries the reason
+    Close(String),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -16,6 +47,13 @@ pub struct ListenResponse {
     pub value: DBResponse,
 }
 
+// Sent when a frame could not be turned into a request, the connection stays open afterwards
+#[derive(Serialize, Deserialize)]
+pub struct ProtocolError {
+    pub request_id: Option<Uuid>,
+    pub message: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RequestResponse {
     pub request_id: Uuid,
@@ -63,6 +101,19 @@ pub enum Query {
     GreaterThan(GetData),
     InsertData(InsertData),
     DeleteData(DeleteData),
+    // Rereads the config file and applies table changes, answered with one entry per changed table
+    ReloadConfig,
+    // Recomputes a derived table and everything downstream of it, answered like a reload
+    RebuildTable(String),
+    // Inserts the rows a derived table's transform failed on again, answered like a reload
+    RetryFailed(String),
+    // Answered with one entry per table describing how it is wired
+    DescribeGraph,
+    // Answered with the row, the rows it was derived from and the rows derived from it
+    Lineage(LineageData),
+    // Copies every table and index file into a new directory on the server, answered with one entry per file
+    // once the copy is complete
+    Snapshot(String),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -79,3 +130,9 @@ pub struct InsertData {
 }
 
 pub type DeleteData = GetData;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LineageData {
+    pub table: String,
+    pub entry_id: EntryValue,
+}
@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use reactivedb_rust_client::client::Client;
+    use reactivedb_rust_client::types::{DBRequest as ClientRequest, EntryValue as ClientValue, ListenEvent};
+    use tokio::net::TcpListener;
+
+    use crate::auth::AccessControl;
+    use crate::networking::client_connection::negotiate;
+    use crate::networking::framing::{read_frame, write_frame, Frame};
+    use crate::networking::types::{DBRequest, DBResponse, ProtocolError, RequestResponse, ToClientMessage};
+
+    #[tokio::test]
+    async fn reports_errors_not_tied_to_a_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Refuses the first listen the way the server does, without a request id
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let encoding = negotiate(&mut stream, 1024, &AccessControl::new(None)).await.unwrap().0.encoding;
+            assert!(matches!(read_frame(&mut stream, 1024).await.unwrap(), Frame::Message(_)));
+            let error = ToClientMessage::ProtocolError(ProtocolError { request_id: None, message: "Unable to find table missing".to_string() });
+            write_frame(&mut stream, encoding.encode(&error).unwrap().as_slice()).await.unwrap();
+            stream
+        });
+        let mut client = Client::new(&addr.to_string());
+        client.open_connection().await.unwrap();
+
+        let error = client
+            .subscribe_to_event_blocking("missing".to_string(), ListenEvent::Insert, Box::new(|_| Ok(())))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Unable to find table missing");
+        let errors = client.take_protocol_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unable to find table missing");
+        assert!(client.take_protocol_errors().is_empty());
+        drop(server.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn fails_requests_when_a_message_is_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Answers the first request with more than the client accepts
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let encoding = negotiate(&mut stream, 1024, &AccessControl::new(None)).await.unwrap().0.encoding;
+            let request_id = match read_frame(&mut stream, 1024).await.unwrap() {
+                Frame::Message(message) => match encoding.decode(message.as_slice()).unwrap() {
+                    DBRequest::Query(query_request) => query_request.request_id,
+                    DBRequest::StartListen(_) => panic!("Expected a query"),
+                },
+                _ => panic!("Expected a request"),
+            };
+            let response = ToClientMessage::RequestResponse(RequestResponse { request_id, response: DBResponse::NoResult(Err("x".repeat(1024))) });
+            write_frame(&mut stream, encoding.encode(&response).unwrap().as_slice()).await.unwrap();
+            stream
+        });
+        let mut client = Client::new(&addr.to_string());
+        client.set_max_frame_size(512);
+        client.open_connection().await.unwrap();
+
+        let request = ClientRequest::new_find_one("users".to_string(), "name".to_string(), ClientValue::Str("Alex".to_string()));
+        assert!(client.make_request(request).await.is_err());
+        let errors = client.take_protocol_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("exceeds the maximum frame size of 512 bytes"));
+        drop(server.await.unwrap());
+    }
+}
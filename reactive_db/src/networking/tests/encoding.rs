@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    use crate::networking::encoding::Encoding;
+    use crate::networking::types::{DBRequest, DBResponse, InsertData, Query, QueryRequest, RequestResponse, ToClientMessage};
+    use crate::types::EntryValue;
+
+    fn sample_request(request_id: Uuid) -> DBRequest {
+        let mut entry = BTreeMap::new();
+        entry.insert("name".to_string(), EntryValue::Str("Alex".to_string()));
+        entry.insert("balance".to_string(), EntryValue::Decimal(Decimal::new(1050, 2)));
+        entry.insert("tags".to_string(), EntryValue::Array(vec![EntryValue::Integer(1), EntryValue::Bool(true)]));
+        DBRequest::Query(QueryRequest {
+            request_id,
+            query: Query::InsertData(InsertData { table: "users".to_string(), entry }),
+        })
+    }
+
+    #[test]
+    fn message_pack_round_trips_requests() {
+        let request_id = Uuid::new_v4();
+        for encoding in [Encoding::Json, Encoding::MessagePack].iter() {
+            let encoded = encoding.encode(&sample_request(request_id)).unwrap();
+            let decoded: DBRequest = encoding.decode(encoded.as_slice()).unwrap();
+            match decoded {
+                DBRequest::Query(query_request) => {
+                    assert_eq!(query_request.request_id, request_id);
+                    match query_request.query {
+                        Query::InsertData(insert) => {
+                            assert_eq!(insert.entry.get("balance"), Some(&EntryValue::Decimal(Decimal::new(1050, 2))));
+                        }
+                        _ => panic!("Decoded the wrong query"),
+                    }
+                }
+                _ => panic!("Decoded the wrong request"),
+            }
+        }
+    }
+
+    #[test]
+    fn message_pack_is_smaller_than_json() {
+        let response = ToClientMessage::RequestResponse(RequestResponse {
+            request_id: Uuid::new_v4(),
+            response: DBResponse::NoResult(Ok(())),
+        });
+        let json = Encoding::Json.encode(&response).unwrap();
+        let message_pack = Encoding::MessagePack.encode(&response).unwrap();
+        assert!(message_pack.len() < json.len());
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod tests {
+    use crate::networking::framing::{read_frame, write_frame, Frame};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn reads_frame_split_across_writes() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let payload = vec![7u8; 300];
+        let writer = tokio::spawn(async move {
+            let mut framed = vec![0, 0, 1, 44];
+            framed.extend(payload);
+            for chunk in framed.chunks(5) {
+                client.write_all(chunk).await.unwrap();
+            }
+            client
+        });
+        match read_frame(&mut server, 1024).await.unwrap() {
+            Frame::Message(message) => assert_eq!(message, vec![7u8; 300]),
+            _ => panic!("Expected a full message"),
+        }
+        drop(writer.await.unwrap());
+        assert!(matches!(read_frame(&mut server, 1024).await.unwrap(), Frame::Closed));
+    }
+
+    #[tokio::test]
+    async fn skips_frames_over_limit() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_frame(&mut client, &[1u8; 100]).await.unwrap();
+        write_frame(&mut client, &[2u8; 10]).await.unwrap();
+        assert!(matches!(read_frame(&mut server, 50).await.unwrap(), Frame::TooLarge(100)));
+        match read_frame(&mut server, 50).await.unwrap() {
+            Frame::Message(message) => assert_eq!(message, vec![2u8; 10]),
+            _ => panic!("Expected the frame after the oversized one"),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc::channel;
+    use uuid::Uuid;
+
+    use crate::auth::AccessControl;
+    use crate::constants::PROTOCOL_VERSION;
+    use crate::networking::client_connection::{handle_results, negotiate};
+    use crate::networking::encoding::Encoding;
+    use crate::networking::framing::{read_frame, write_frame, Frame};
+    use crate::networking::types::{AcceptedHandshake, DBRequest, GetData, Handshake, HandshakeResponse, Query, QueryRequest, ToClientMessage};
+    use crate::types::EntryValue;
+
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    async fn read_response(stream: &mut TcpStream) -> HandshakeResponse {
+        match read_frame(stream, 1024).await.unwrap() {
+            Frame::Message(message) => Encoding::Json.decode(message.as_slice()).unwrap(),
+            _ => panic!("Expected a handshake response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_supported_version_and_encoding() {
+        let (mut client, mut server) = connected_pair().await;
+        let handshake = Handshake { protocol_version: PROTOCOL_VERSION + 1, encoding: Encoding::MessagePack, credentials: None };
+        write_frame(&mut client, Encoding::Json.encode(&handshake).unwrap().as_slice()).await.unwrap();
+        let (accepted, _, legacy_request) = negotiate(&mut server, 1024, &AccessControl::new(None)).await.unwrap();
+        assert_eq!(accepted.encoding, Encoding::MessagePack);
+        assert_eq!(accepted.protocol_version, PROTOCOL_VERSION);
+        assert!(legacy_request.is_none());
+        match read_response(&mut client).await {
+            HandshakeResponse::Accepted(accepted) => assert_eq!(accepted.protocol_version, PROTOCOL_VERSION),
+            HandshakeResponse::Rejected(reason) => panic!("Handshake rejected: {}", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_old_versions() {
+        let (mut client, mut server) = connected_pair().await;
+        let handshake = Handshake { protocol_version: 0, encoding: Encoding::Json, credentials: None };
+        write_frame(&mut client, Encoding::Json.encode(&handshake).unwrap().as_slice()).await.unwrap();
+        assert!(negotiate(&mut server, 1024, &AccessControl::new(None)).await.is_err());
+        assert!(matches!(read_response(&mut client).await, HandshakeResponse::Rejected(_)));
+    }
+
+    #[tokio::test]
+    async fn serves_clients_without_handshake_as_json() {
+        let (mut client, mut server) = connected_pair().await;
+        let request = DBRequest::Query(QueryRequest {
+            request_id: Uuid::new_v4(),
+            query: Query::FindOne(GetData { table: "users".to_string(), column: "name".to_string(), key: EntryValue::Str("Alex".to_string()) }),
+        });
+        write_frame(&mut client, Encoding::Json.encode(&request).unwrap().as_slice()).await.unwrap();
+        let (accepted, _, legacy_request) = negotiate(&mut server, 1024, &AccessControl::new(None)).await.unwrap();
+        assert_eq!(accepted.encoding, Encoding::Json);
+        assert_eq!(accepted.protocol_version, 0);
+        assert!(matches!(legacy_request, Some(DBRequest::Query(_))));
+    }
+
+    // What a client that negotiated the version reads once the server closes its connection
+    async fn last_frame(protocol_version: u32) -> Frame {
+        let (mut client, server) = connected_pair().await;
+        let (sender, reciever) = channel(1);
+        sender.send(ToClientMessage::Close("Server shutting down".to_string())).await.unwrap();
+        handle_results(server, reciever, AcceptedHandshake { protocol_version, encoding: Encoding::Json }).await;
+        read_frame(&mut client, 1024).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_close_only_to_clients_that_understand_it() {
+        match last_frame(PROTOCOL_VERSION).await {
+            Frame::Message(message) => assert!(matches!(Encoding::Json.decode(message.as_slice()).unwrap(), ToClientMessage::Close(_))),
+            _ => panic!("Expected a close message"),
+        }
+        assert!(matches!(last_frame(1).await, Frame::Closed));
+    }
+}
@@ -0,0 +1,5 @@
+mod client;
+mod encoding;
+mod framing;
+mod handshake;
+mod tls;
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::sync::Arc;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+    use reactivedb_rust_client::client::Client;
+    use reactivedb_rust_client::tls::TlsConfig as ClientTlsConfig;
+    use reactivedb_rust_client::types::{DBRequest as ClientRequest, DBResponse as ClientResponse, EntryValue as ClientValue};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::{PrivatePkcs8KeyDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+    use uuid::Uuid;
+
+    use crate::auth::AccessControl;
+    use crate::config::config_reader::TlsConfig;
+    use crate::constants::PROTOCOL_VERSION;
+    use crate::networking::client_connection::negotiate;
+    use crate::networking::encoding::Encoding;
+    use crate::networking::framing::{read_frame, write_frame, Frame};
+    use crate::networking::tls::create_acceptor;
+    use crate::networking::types::{DBRequest, DBResponse, Handshake, HandshakeResponse, RequestResponse, ToClientMessage};
+
+    struct Authority {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    impl Authority {
+        fn new() -> Authority {
+            let mut params = CertificateParams::new(vec![]).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.self_signed(&key).unwrap();
+            Authority { cert, key }
+        }
+
+        fn sign(&self, name: &str) -> (Certificate, KeyPair) {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_string()]).unwrap().signed_by(&key, &self.cert, &self.key).unwrap();
+            (cert, key)
+        }
+    }
+
+    // Writes the PEM files a server config points at, named after the test so tests don't share files
+    fn write_server_files(test_name: &str, authority: &Authority, client_ca: bool) -> TlsConfig {
+        let (cert, key) = authority.sign("localhost");
+        let cert_path = format!("test_results/{}_cert.pem", test_name);
+        let key_path = format!("test_results/{}_key.pem", test_name);
+        let ca_path = format!("test_results/{}_ca.pem", test_name);
+        fs::write(&cert_path, cert.pem()).unwrap();
+        fs::write(&key_path, key.serialize_pem()).unwrap();
+        fs::write(&ca_path, authority.cert.pem()).unwrap();
+        TlsConfig { cert_path, key_path, client_ca_path: if client_ca { Some(ca_path) } else { None } }
+    }
+
+    fn connector(authority: &Authority, client_identity: Option<(Certificate, KeyPair)>) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots.add(authority.cert.der().clone()).unwrap();
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match client_identity {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(vec![cert.der().clone()], PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    // Runs a TLS handshake followed by the protocol handshake, returning whether the server accepted the client
+    async fn connect(tls_config: &TlsConfig, connector: TlsConnector) -> bool {
+        let acceptor = create_acceptor(tls_config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => return false,
+            };
+            negotiate(&mut stream, 1024, &AccessControl::new(None)).await.is_ok()
+        });
+        let stream = TcpStream::connect(addr).await.unwrap();
+        if let Ok(mut stream) = connector.connect(ServerName::try_from("localhost").unwrap(), stream).await {
+            let handshake = Handshake { protocol_version: PROTOCOL_VERSION, encoding: Encoding::Json, credentials: None };
+            if write_frame(&mut stream, Encoding::Json.encode(&handshake).unwrap().as_slice()).await.is_ok() {
+                if let Ok(Frame::Message(message)) = read_frame(&mut stream, 1024).await {
+                    let response: HandshakeResponse = Encoding::Json.decode(message.as_slice()).unwrap();
+                    assert!(matches!(response, HandshakeResponse::Accepted(_)));
+                }
+            }
+        }
+        server.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_protocol_over_tls() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_plain", &authority, false);
+        assert!(connect(&tls_config, connector(&authority, None)).await);
+    }
+
+    #[tokio::test]
+    async fn rejects_clients_without_certificate_when_mutual() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_mutual_missing", &authority, true);
+        assert!(!connect(&tls_config, connector(&authority, None)).await);
+    }
+
+    #[tokio::test]
+    async fn accepts_client_certificates_signed_by_client_ca() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_mutual", &authority, true);
+        let client_identity = authority.sign("client");
+        assert!(connect(&tls_config, connector(&authority, Some(client_identity))).await);
+    }
+
+    #[tokio::test]
+    async fn rejects_client_certificates_from_other_authorities() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_mutual_foreign", &authority, true);
+        let client_identity = Authority::new().sign("client");
+        assert!(!connect(&tls_config, connector(&authority, Some(client_identity))).await);
+    }
+
+    // Answers the first request made over TLS by the Rust client, returning whether one arrived
+    async fn serve_one_request(tls_config: &TlsConfig) -> (String, tokio::task::JoinHandle<bool>) {
+        let acceptor = create_acceptor(tls_config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => return false,
+            };
+            let encoding = match negotiate(&mut stream, 1024, &AccessControl::new(None)).await {
+                Ok((accepted, _, _)) => accepted.encoding,
+                Err(_) => return false,
+            };
+            let request_id = match read_frame(&mut stream, 1024).await {
+                Ok(Frame::Message(message)) => match encoding.decode(message.as_slice()).unwrap() {
+                    DBRequest::Query(query_request) => query_request.request_id,
+                    DBRequest::StartListen(_) => return false,
+                },
+                _ => return false,
+            };
+            let response = ToClientMessage::RequestResponse(RequestResponse { request_id, response: DBResponse::OneResult(Ok(None)) });
+            write_frame(&mut stream, encoding.encode(&response).unwrap().as_slice()).await.is_ok()
+        });
+        (addr, server)
+    }
+
+    fn find_one() -> (ClientRequest, Uuid) {
+        ClientRequest::new_find_one("users".to_string(), "name".to_string(), ClientValue::Str("Alex".to_string()))
+    }
+
+    #[tokio::test]
+    async fn client_makes_requests_over_tls() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_client", &authority, false);
+        let (addr, server) = serve_one_request(&tls_config).await;
+        let mut client = Client::new(&addr);
+        client.set_tls(ClientTlsConfig::new("localhost", "test_results/tls_client_ca.pem"));
+        client.open_connection().await.unwrap();
+        assert!(matches!(client.make_request(find_one()).await.unwrap(), ClientResponse::OneResult(Ok(None))));
+        assert!(server.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn client_presents_its_certificate_for_mutual_tls() {
+        let authority = Authority::new();
+        let tls_config = write_server_files("tls_client_mutual", &authority, true);
+        let (cert, key) = authority.sign("client");
+        fs::write("test_results/tls_client_mutual_client_cert.pem", cert.pem()).unwrap();
+        fs::write("test_results/tls_client_mutual_client_key.pem", key.serialize_pem()).unwrap();
+        let (addr, server) = serve_one_request(&tls_config).await;
+        let mut client = Client::new(&addr);
+        client.set_tls(
+            ClientTlsConfig::new("localhost", "test_results/tls_client_mutual_ca.pem")
+                .with_client_certificate("test_results/tls_client_mutual_client_cert.pem", "test_results/tls_client_mutual_client_key.pem"),
+        );
+        client.open_connection().await.unwrap();
+        assert!(matches!(client.make_request(find_one()).await.unwrap(), ClientResponse::OneResult(Ok(None))));
+        assert!(server.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn client_refuses_servers_signed_by_other_authorities() {
+        let tls_config = write_server_files("tls_client_foreign", &Authority::new(), false);
+        // The client only trusts an authority that didn't sign the server certificate
+        let other = Authority::new();
+        fs::write("test_results/tls_client_foreign_other_ca.pem", other.cert.pem()).unwrap();
+        let (addr, server) = serve_one_request(&tls_config).await;
+        let mut client = Client::new(&addr);
+        client.set_tls(ClientTlsConfig::new("localhost", "test_results/tls_client_foreign_other_ca.pem"));
+        assert!(client.open_connection().await.is_err());
+        assert!(!server.await.unwrap());
+    }
+}
@@ -0,0 +1,30 @@
+use crate::config::config_reader::TlsConfig;
+// PEM files are read the same way as the Rust client does
+use reactivedb_rust_client::tls::{load_certs, load_key};
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+// Loads the certificate and key for the listener, with a client CA every client has to present a certificate it signed
+pub fn create_acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let builder = match &config.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca_path)? {
+                roots.add(cert).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+    let server_config = builder
+        .with_single_cert(load_certs(&config.cert_path)?, load_key(&config.key_path)?)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
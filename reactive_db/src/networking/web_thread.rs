@@ -2,8 +2,11 @@ use std::net::SocketAddr;
 
 use hyper::Server;
 use tokio::sync::mpsc::Sender;
+use tracing::error;
 use uuid::Uuid;
 
+use crate::auth::Principal;
+
 use super::{network_types::{DBRequest, ToClientMessage}, routes::{MakeRouterService, Route}};
 
 // Concept: Standup a web interface for users to hit both actions and auto generated apis
@@ -12,7 +15,7 @@ use super::{network_types::{DBRequest, ToClientMessage}, routes::{MakeRouterServ
 pub async fn web_thread(
     routes: Vec<Box<dyn Route>>,
     db_request_channel: Sender<(DBRequest, Uuid)>,
-    db_result_channel_sender: Sender<(Sender<ToClientMessage>, Uuid)> 
+    db_result_channel_sender: Sender<(Sender<ToClientMessage>, Uuid, Principal)> 
 ) {
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -21,6 +24,6 @@ pub async fn web_thread(
 
     // Run this server for... forever!
     if let Err(e) = server.await {
-        eprintln!("server error: {}", e);
+        error!(error = %e, "Web server stopped");
     }
 }
\ No newline at end of file
@@ -1,50 +1,132 @@
-use crate::{config::config_reader::{DbConfig, read_config_file}, networking::{client_connection}};
+use crate::{config::config_reader::{DbConfig, read_config_file}, constants::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_METRICS_BIND, SHUTDOWN_TIMEOUT}, networking::{client_connection::{self, ConnectionSettings}, metrics_server, tls}};
+use crate::auth::{AccessControl, Principal};
+use crate::networking::types::{DBRequest, DBResponse, Query, QueryRequest, RequestResponse, ToClientMessage};
+use crate::types::create_custom_io_error;
 use crate::database::db_thread;
+use crate::logging;
+use std::io;
+use std::sync::Arc;
 use std::thread;
 use tokio::net::TcpListener;
-use tokio::sync::mpsc::channel;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc::{channel, Sender}, watch};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 #[tokio::main]
-pub async fn start_server(port: String, config_file: String) -> std::io::Result<()> {
+pub async fn start_server(bind: String, port: u16, config_file: String, data_dir: Option<String>) -> std::io::Result<()> {
     let (db_request_sender, db_request_reciever) = channel(200);
 
     let (db_response_channel_sender, db_response_channel_reciever) = channel(200);
-    let config:DbConfig = read_config_file(config_file.to_string())?;
+    let mut config:DbConfig = read_config_file(config_file.to_string())?;
+    if let Some(data_dir) = data_dir {
+        config.storage_destination = data_dir;
+    }
+    logging::init(config.logging.as_ref()).map_err(|e| create_custom_io_error(&e))?;
+    let max_frame_size = config
+        .network
+        .as_ref()
+        .and_then(|network| network.max_frame_size)
+        .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+    let tls_acceptor = match config.network.as_ref().and_then(|network| network.tls.as_ref()) {
+        Some(tls_config) => Some(tls::create_acceptor(tls_config)?),
+        None => None,
+    };
+    // Bound up front so a taken port stops startup
+    let metrics_listener = match &config.metrics {
+        Some(metrics) => {
+            let bind = metrics.bind.clone().unwrap_or_else(|| DEFAULT_METRICS_BIND.to_string());
+            let listener = std::net::TcpListener::bind(format!("{}:{}", bind, metrics.port))?;
+            listener.set_nonblocking(true)?;
+            info!(bind = %bind, port = metrics.port, "Serving metrics");
+            Some(listener)
+        }
+        None => None,
+    };
+    let access_control = Arc::new(AccessControl::new(config.auth.clone()));
+    let db_access_control = access_control.clone();
+    let db_config_file = config_file.clone();
 
+    // Returns once every connection has dropped its request sender and the db thread has drained, flushed and said goodbye
     let db_thread = thread::spawn(|| {
-        match db_thread::start_db_thread(
+        db_thread::start_db_thread(
             db_request_reciever,
             db_response_channel_reciever,
             config,
-        ) {
-            Ok(()) => panic!("Server closing!"),
-            Err(e) => panic!("{:?}", e),
-        };
+            db_config_file,
+            db_access_control,
+        )
     });
-    let _db_request_clone = db_request_sender.clone();
-    let _db_response_channel_sender_clone = db_response_channel_sender.clone();
+    let (shutdown_sender, shutdown_reciever) = watch::channel(false);
+    // Every connection holds a clone until its close message is written, recv gives None once all are gone
+    let (connection_done_sender, mut connections_done) = channel::<()>(1);
+
+    if let Some(listener) = metrics_listener {
+        let metrics_shutdown = shutdown_reciever.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics_server::serve_metrics(listener, metrics_shutdown).await {
+                error!(error = %e, "Metrics endpoint stopped");
+            }
+        });
+    }
+
+    tokio::spawn(reload_on_hangup(
+        db_request_sender.clone(),
+        db_response_channel_sender.clone(),
+        shutdown_reciever.clone(),
+    ));
 
+    let listener = TcpListener::bind(format!("{}:{}", bind, port)).await?;
+    info!(bind = %bind, port, "Listening");
+    let mut accept_shutdown = shutdown_reciever.clone();
+    let settings = ConnectionSettings { max_frame_size, access_control, shutdown: shutdown_reciever.clone() };
     tokio::spawn(async move {
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await.unwrap();
         loop {
-            let (stream, _) = listener.accept().await.unwrap();
+            let (stream, _) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!(error = %e, "Unable to accept connection");
+                        continue;
+                    }
+                },
+                _ = accept_shutdown.changed() => break,
+            };
             let client_id = Uuid::new_v4();
             let thread_db_request_copy = db_request_sender.clone();
-            let (db_result_sender, db_result_reciever) = channel(30);
-            match db_response_channel_sender
-                .send((db_result_sender, client_id.clone()))
-                .await {
-                    Ok(_) => {},
-                    Err(_) => {continue}
-                };
+            let thread_db_response_channel_copy = db_response_channel_sender.clone();
+            let thread_settings = settings.clone();
+            let thread_tls_acceptor = tls_acceptor.clone();
+            let thread_connection_done = connection_done_sender.clone();
             tokio::spawn(async move {
-                client_connection::start_client_thread(
-                    client_id,
-                    thread_db_request_copy,
-                    db_result_reciever,
-                    stream,
-                );
+                match thread_tls_acceptor {
+                    Some(acceptor) => {
+                        let mut tls_shutdown = thread_settings.shutdown.clone();
+                        let accepted = tokio::select! {
+                            accepted = acceptor.accept(stream) => accepted,
+                            _ = tls_shutdown.changed() => return,
+                        };
+                        match accepted {
+                            Ok(tls_stream) => client_connection::start_client_thread(
+                                client_id,
+                                thread_db_request_copy,
+                                thread_db_response_channel_copy,
+                                tls_stream,
+                                thread_settings,
+                                thread_connection_done,
+                            ),
+                            Err(e) => warn!(connection_id = %client_id, error = %e, "TLS handshake failed"),
+                        }
+                    }
+                    None => client_connection::start_client_thread(
+                        client_id,
+                        thread_db_request_copy,
+                        thread_db_response_channel_copy,
+                        stream,
+                        thread_settings,
+                        thread_connection_done,
+                    ),
+                }
             });
         }
     });
@@ -53,8 +135,65 @@ pub async fn start_server(port: String, config_file: String) -> std::io::Result<
 
     //tokio::spawn(web_thread(routes, db_request_clone, db_response_channel_sender_clone));
 
+    let mut db_thread = tokio::task::spawn_blocking(move || db_thread.join());
+    // The db thread only finishes early when it failed to start
+    let finished_early = tokio::select! {
+        signal = shutdown_signal() => {
+            signal?;
+            None
+        }
+        finished = &mut db_thread => Some(finished),
+    };
+    info!("Shutting down");
+    let _ = shutdown_sender.send(true);
+
+    let finished = match finished_early {
+        Some(finished) => finished,
+        None => db_thread.await,
+    };
+    let db_result = match finished {
+        Ok(Ok(result)) => result,
+        _ => Err(create_custom_io_error("Database thread panicked")),
+    };
+    // Give clients a moment to receive their close message
+    let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, connections_done.recv()).await;
+    db_result
+}
 
-    db_thread.join().unwrap();
+async fn shutdown_signal() -> io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
 
-    Ok(())
+// SIGHUP sends the db thread the same reload request an admin client would
+async fn reload_on_hangup(
+    db_request_sender: Sender<(DBRequest, Uuid)>,
+    db_response_channel_sender: Sender<(Sender<ToClientMessage>, Uuid, Principal)>,
+    mut shutdown: watch::Receiver<bool>,
+) -> io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let client_id = Uuid::new_v4();
+    let (response_sender, mut response_reciever) = channel(10);
+    if db_response_channel_sender.send((response_sender, client_id, Principal::unrestricted("sighup"))).await.is_err() {
+        return Ok(());
+    }
+    // Like a connection, only hold on to the registration sender until registered so it can't hold up shutdown
+    drop(db_response_channel_sender);
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {}
+            _ = shutdown.changed() => return Ok(()),
+        }
+        info!("Reloading config on SIGHUP");
+        let request = QueryRequest { request_id: Uuid::new_v4(), query: Query::ReloadConfig };
+        if db_request_sender.send((DBRequest::Query(request), client_id)).await.is_err() {
+            return Ok(());
+        }
+        if let Some(ToClientMessage::RequestResponse(RequestResponse { response: DBResponse::ManyResults(Err(e)), .. })) = response_reciever.recv().await {
+            error!(error = %e, "Config reload failed");
+        }
+    }
 }
@@ -0,0 +1,375 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tracing::warn;
+
+use crate::constants::DEFAULT_BUFFER_POOL_BYTES;
+
+// Pages of every open storage file share one pool, the least recently used pages are dropped once it holds
+// more than its budget. Writes stay in the pool until their file ends a session, is flushed or the page is
+// evicted. Every page written to a file goes through here, so this is also where a file being copied has the
+// old contents of a page saved before they're overwritten. Pages that aren't in the pool are read from disk
+// without holding the lock, so readers only wait for each other's disk reads when they read the same page.
+pub struct BufferPool {
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    pub bytes: u64,
+    pub budget: u64,
+    pub pages: u64,
+    pub dirty_pages: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+type PageKey = (u64, u32);
+
+struct PoolState {
+    budget: usize,
+    bytes: usize,
+    // Bumped on every access, a page's last tick orders it in recency
+    tick: u64,
+    pages: HashMap<PageKey, Frame>,
+    recency: BTreeMap<u64, PageKey>,
+    files: HashMap<u64, PoolFile>,
+    next_file: u64,
+    evictions: u64,
+    // Keyed by file id, a copy outlives its file being unregistered
+    copies: HashMap<u64, FileCopy>,
+}
+
+struct Frame {
+    data: Vec<u8>,
+    last_used: u64,
+}
+
+struct PoolFile {
+    // Shared with reads of the file that are going on outside the lock
+    file: Arc<File>,
+    // Pages written since they were last written back, in the order they're in the file
+    dirty: BTreeSet<u32>,
+    // Bumped on every write, a read from disk that saw it change may have read the page before the write
+    writes: u64,
+    hits: u64,
+    misses: u64,
+}
+
+// A copy of a file as it was when the copy started. Pages are copied in order by whoever finishes the copy,
+// a page that is about to be overwritten before then is copied first.
+struct FileCopy {
+    // Has its own handle so the copy can go on after the file is closed, renamed or removed
+    source: File,
+    target: File,
+    page_size: usize,
+    // One flag per page the file had when the copy started
+    copied: Vec<bool>,
+    // No page below this one is left to copy
+    next: usize,
+    // Saving a page before it was overwritten failed, the live write went ahead anyway
+    error: Option<String>,
+}
+
+// Returned by start_copy, the copy is only complete once finish_copy succeeds
+pub struct PageCopy {
+    file_id: u64,
+}
+
+pub fn global() -> &'static BufferPool {
+    static POOL: OnceLock<BufferPool> = OnceLock::new();
+    POOL.get_or_init(|| BufferPool::new(DEFAULT_BUFFER_POOL_BYTES))
+}
+
+impl BufferPool {
+    pub fn new(budget: usize) -> BufferPool {
+        BufferPool {
+            state: Mutex::new(PoolState {
+                budget,
+                bytes: 0,
+                tick: 0,
+                pages: HashMap::new(),
+                recency: BTreeMap::new(),
+                files: HashMap::new(),
+                next_file: 0,
+                evictions: 0,
+                copies: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn set_budget(&self, budget: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.budget = budget;
+        state.evict();
+    }
+
+    // Returns the id pages of the file are kept under
+    pub fn register(&self, file: &File) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_file;
+        state.next_file += 1;
+        state.files.insert(id, PoolFile { file: Arc::new(file.try_clone()?), dirty: BTreeSet::new(), writes: 0, hits: 0, misses: 0 });
+        Ok(id)
+    }
+
+    // Writes the file's dirty pages back and drops all of its pages
+    pub fn unregister(&self, file_id: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let result = state.write_back(file_id);
+        let keys: Vec<PageKey> = state.pages.keys().filter(|(file, _)| *file == file_id).copied().collect();
+        for key in keys {
+            state.remove(key);
+        }
+        state.files.remove(&file_id);
+        result
+    }
+
+    pub fn read(&self, file_id: u64, page: u32, page_size: usize) -> io::Result<Vec<u8>> {
+        loop {
+            let (file, writes) = {
+                let mut state = self.state.lock().unwrap();
+                state.touch((file_id, page));
+                if let Some(frame) = state.pages.get(&(file_id, page)) {
+                    let data = frame.data.clone();
+                    state.file(file_id)?.hits += 1;
+                    return Ok(data);
+                }
+                let pool_file = state.file(file_id)?;
+                (pool_file.file.clone(), pool_file.writes)
+            };
+            let data = read_page(&file, page, page_size)?;
+            let mut state = self.state.lock().unwrap();
+            let pool_file = state.file(file_id)?;
+            // Written to while it was read from disk, what was read may be older than the write
+            if pool_file.writes != writes {
+                continue;
+            }
+            pool_file.misses += 1;
+            // Another read of the page may have put it in the pool first
+            let tick = state.touch((file_id, page));
+            if let Some(frame) = state.pages.get(&(file_id, page)) {
+                return Ok(frame.data.clone());
+            }
+            state.insert((file_id, page), data.clone(), tick);
+            return Ok(data);
+        }
+    }
+
+    pub fn write(&self, file_id: u64, page: u32, data: Vec<u8>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let tick = state.touch((file_id, page));
+        let pool_file = state.file(file_id)?;
+        pool_file.dirty.insert(page);
+        pool_file.writes += 1;
+        state.insert((file_id, page), data, tick);
+        Ok(())
+    }
+
+    pub fn write_back(&self, file_id: u64) -> io::Result<()> {
+        self.state.lock().unwrap().write_back(file_id)
+    }
+
+    // Starts copying the file to a new file at destination as it is now, including pages written so far that
+    // are still in the pool
+    pub fn start_copy(&self, file_id: u64, page_size: usize, destination: &str) -> io::Result<PageCopy> {
+        let mut state = self.state.lock().unwrap();
+        if state.copies.contains_key(&file_id) {
+            return Err(io::Error::other(format!("File {} is already being copied", file_id)));
+        }
+        state.write_back(file_id)?;
+        let source = state.file(file_id)?.file.try_clone()?;
+        let length = source.metadata()?.len();
+        let target = OpenOptions::new().write(true).create_new(true).open(destination)?;
+        target.set_len(length)?;
+        let pages = length.div_ceil(page_size as u64) as usize;
+        state.copies.insert(file_id, FileCopy { source, target, page_size, copied: vec![false; pages], next: 0, error: None });
+        Ok(PageCopy { file_id })
+    }
+
+    // Copies every page that hasn't been copied yet. The lock is taken for one page at a time so writes to the
+    // file carry on in between
+    pub fn finish_copy(&self, copy: &PageCopy) -> io::Result<()> {
+        while self.copy_next(copy.file_id)? {}
+        Ok(())
+    }
+
+    // Drops a copy that won't be finished, the partly written file is left for the caller to remove
+    pub fn cancel_copy(&self, copy: &PageCopy) {
+        self.state.lock().unwrap().copies.remove(&copy.file_id);
+    }
+
+    // Copies the next page that hasn't been copied yet, false once the copy is complete and on disk
+    fn copy_next(&self, file_id: u64) -> io::Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let copy = match state.copies.get_mut(&file_id) {
+            Some(copy) => copy,
+            None => return Ok(false),
+        };
+        if let Some(error) = copy.error.take() {
+            state.copies.remove(&file_id);
+            return Err(io::Error::other(error));
+        }
+        while copy.next < copy.copied.len() && copy.copied[copy.next] {
+            copy.next += 1;
+        }
+        let result = if copy.next < copy.copied.len() {
+            let page = copy.next as u32;
+            copy.copy_page(page).map(|_| true)
+        } else {
+            copy.target.sync_all().map(|_| false)
+        };
+        if !matches!(result, Ok(true)) {
+            state.copies.remove(&file_id);
+        }
+        result
+    }
+
+    // Cache hits and misses of one file
+    pub fn file_stats(&self, file_id: u64) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        match state.files.get(&file_id) {
+            Some(pool_file) => (pool_file.hits, pool_file.misses),
+            None => (0, 0),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state.lock().unwrap();
+        let (hits, misses) = state.files.values().fold((0, 0), |(hits, misses), pool_file| {
+            (hits + pool_file.hits, misses + pool_file.misses)
+        });
+        PoolStats {
+            bytes: state.bytes as u64,
+            budget: state.budget as u64,
+            pages: state.pages.len() as u64,
+            dirty_pages: state.files.values().map(|pool_file| pool_file.dirty.len() as u64).sum(),
+            hits,
+            misses,
+            evictions: state.evictions,
+        }
+    }
+}
+
+impl PoolState {
+    fn file(&mut self, file_id: u64) -> io::Result<&mut PoolFile> {
+        self.files.get_mut(&file_id).ok_or_else(|| io::Error::other(format!("File {} isn't in the buffer pool", file_id)))
+    }
+
+    // Moves the page to the most recently used end and returns its new tick
+    fn touch(&mut self, key: PageKey) -> u64 {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(frame) = self.pages.get_mut(&key) {
+            self.recency.remove(&frame.last_used);
+            frame.last_used = tick;
+            self.recency.insert(tick, key);
+        }
+        tick
+    }
+
+    fn insert(&mut self, key: PageKey, data: Vec<u8>, tick: u64) {
+        self.remove(key);
+        self.bytes += data.len();
+        self.pages.insert(key, Frame { data, last_used: tick });
+        self.recency.insert(tick, key);
+        self.evict();
+    }
+
+    fn remove(&mut self, key: PageKey) -> Option<Frame> {
+        let frame = self.pages.remove(&key)?;
+        self.recency.remove(&frame.last_used);
+        self.bytes -= frame.data.len();
+        Some(frame)
+    }
+
+    // A dirty page is only dropped once it's written back. One that can't be written stays in the pool, still
+    // dirty, and the next page in line goes instead, the error comes up when its own file is written back.
+    fn evict(&mut self) {
+        // Pages last used before this tick couldn't be written back
+        let mut from = 0;
+        while self.bytes > self.budget {
+            let (tick, key) = match self.recency.range(from..).next() {
+                Some((tick, key)) => (*tick, *key),
+                None => return,
+            };
+            from = tick + 1;
+            let dirty = self.files.get(&key.0).is_some_and(|pool_file| pool_file.dirty.contains(&key.1));
+            if dirty {
+                let data = self.pages[&key].data.clone();
+                if let Err(e) = self.write_page(key.0, key.1, &data) {
+                    warn!("Unable to write back page {} of file {} to evict it: {}", key.1, key.0, e);
+                    continue;
+                }
+                if let Some(pool_file) = self.files.get_mut(&key.0) {
+                    pool_file.dirty.remove(&key.1);
+                }
+            }
+            self.remove(key);
+            self.evictions += 1;
+        }
+    }
+
+    fn write_back(&mut self, file_id: u64) -> io::Result<()> {
+        while let Some(page) = self.file(file_id)?.dirty.iter().next().copied() {
+            let data = self.pages[&(file_id, page)].data.clone();
+            self.write_page(file_id, page, &data)?;
+            self.file(file_id)?.dirty.remove(&page);
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, file_id: u64, page: u32, data: &[u8]) -> io::Result<()> {
+        if let Some(copy) = self.copies.get_mut(&file_id) {
+            copy.save_before_write(page);
+        }
+        let mut file = &*self.file(file_id)?.file;
+        file.seek(SeekFrom::Start(page as u64 * data.len() as u64))?;
+        file.write_all(data)
+    }
+}
+
+// Pages past the end of the file read as zeros
+fn read_page(file: &File, page: u32, page_size: usize) -> io::Result<Vec<u8>> {
+    let offset = page as u64 * page_size as u64;
+    let mut data = vec![0; page_size];
+    let mut read = 0;
+    while read < page_size {
+        match file.read_at(&mut data[read..], offset + read as u64) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(data)
+}
+
+impl FileCopy {
+    fn copy_page(&mut self, page: u32) -> io::Result<()> {
+        let offset = page as u64 * self.page_size as u64;
+        let mut data = vec![];
+        self.source.seek(SeekFrom::Start(offset))?;
+        Read::by_ref(&mut self.source).take(self.page_size as u64).read_to_end(&mut data)?;
+        self.target.seek(SeekFrom::Start(offset))?;
+        self.target.write_all(&data)?;
+        self.copied[page as usize] = true;
+        Ok(())
+    }
+
+    // A failure here mustn't stop the write, it fails the copy instead
+    fn save_before_write(&mut self, page: u32) {
+        let pending = self.copied.get(page as usize) == Some(&false);
+        if !pending || self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.copy_page(page) {
+            self.error = Some(format!("Unable to save page {} before it was overwritten: {}", page, e));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::io;
+use std::io::Read;
+
+use bzip2::read::{BzDecoder, BzEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{DICTIONARY_SAMPLES, DICTIONARY_SIZE, ZSTD_LEVEL};
+use crate::types::create_custom_io_error;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+    Bzip2,
+}
+
+// Written to the header of every file, files keep the codec they were created with
+const NONE_CODEC: u32 = 0;
+const LZ4_CODEC: u32 = 1;
+const ZSTD_CODEC: u32 = 2;
+const BZIP2_CODEC: u32 = 3;
+const ZSTD_DICTIONARY_CODEC: u32 = 4;
+// With a dictionary each value starts with whether it was compressed with it, values written before
+// the dictionary was trained don't use it
+const WITHOUT_DICTIONARY: u8 = 0;
+const WITH_DICTIONARY: u8 = 1;
+
+pub struct Codec {
+    compression: Compression,
+    uses_dictionary: bool,
+    dictionary: Option<Dictionary>,
+    // Values kept to train the dictionary from, None once it's trained or training gave up
+    samples: Option<Vec<Vec<u8>>>,
+}
+
+struct Dictionary {
+    compressor: zstd::bulk::Compressor<'static>,
+    decompressor: zstd::bulk::Decompressor<'static>,
+}
+
+impl Codec {
+    pub fn new(compression: Compression, uses_dictionary: bool) -> Codec {
+        Codec {
+            compression,
+            uses_dictionary,
+            dictionary: None,
+            samples: if uses_dictionary { Some(vec![]) } else { None },
+        }
+    }
+
+    pub fn from_id(id: u32) -> io::Result<Codec> {
+        let (compression, uses_dictionary) = match id {
+            NONE_CODEC => (Compression::None, false),
+            LZ4_CODEC => (Compression::Lz4, false),
+            ZSTD_CODEC => (Compression::Zstd, false),
+            BZIP2_CODEC => (Compression::Bzip2, false),
+            ZSTD_DICTIONARY_CODEC => (Compression::Zstd, true),
+            id => return Err(create_custom_io_error(&format!("Unknown compression codec {}", id))),
+        };
+        Ok(Codec::new(compression, uses_dictionary))
+    }
+
+    pub fn id(&self) -> u32 {
+        match (self.compression, self.uses_dictionary) {
+            (Compression::None, _) => NONE_CODEC,
+            (Compression::Lz4, _) => LZ4_CODEC,
+            (Compression::Zstd, false) => ZSTD_CODEC,
+            (Compression::Zstd, true) => ZSTD_DICTIONARY_CODEC,
+            (Compression::Bzip2, _) => BZIP2_CODEC,
+        }
+    }
+
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<()> {
+        self.dictionary = Some(Dictionary {
+            compressor: zstd::bulk::Compressor::with_dictionary(ZSTD_LEVEL, dictionary)?,
+            decompressor: zstd::bulk::Decompressor::with_dictionary(dictionary)?,
+        });
+        self.samples = None;
+        Ok(())
+    }
+
+    // Keeps the value for training and returns the dictionary once enough values were seen
+    pub fn add_sample(&mut self, data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let samples = match self.samples.as_mut() {
+            Some(samples) => samples,
+            None => return Ok(None),
+        };
+        samples.push(data.to_vec());
+        if samples.len() % DICTIONARY_SAMPLES != 0 {
+            return Ok(None);
+        }
+        match zstd::dict::from_samples(samples, DICTIONARY_SIZE) {
+            Ok(dictionary) => {
+                self.set_dictionary(&dictionary)?;
+                Ok(Some(dictionary))
+            }
+            // Too little to go on, keep collecting for a while before giving up
+            Err(_) => {
+                if samples.len() >= DICTIONARY_SAMPLES * 4 {
+                    self.samples = None;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn compress(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self.compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            Compression::Bzip2 => {
+                let mut compressed = vec![];
+                BzEncoder::new(data, bzip2::Compression::Default).read_to_end(&mut compressed)?;
+                Ok(compressed)
+            }
+            Compression::Zstd if !self.uses_dictionary => zstd::bulk::compress(data, ZSTD_LEVEL),
+            Compression::Zstd => match self.dictionary.as_mut() {
+                Some(dictionary) => {
+                    let mut compressed = vec![WITH_DICTIONARY];
+                    compressed.extend(dictionary.compressor.compress(data)?);
+                    Ok(compressed)
+                }
+                None => {
+                    let mut compressed = vec![WITHOUT_DICTIONARY];
+                    compressed.extend(zstd::bulk::compress(data, ZSTD_LEVEL)?);
+                    Ok(compressed)
+                }
+            },
+        }
+    }
+
+    pub fn decompress(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self.compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(data)
+                .map_err(|e| create_custom_io_error(&format!("Corrupt lz4 value: {}", e))),
+            Compression::Bzip2 => {
+                let mut decompressed = vec![];
+                BzDecoder::new(data).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            Compression::Zstd if !self.uses_dictionary => decompress_zstd(data, None),
+            Compression::Zstd => match data.split_first() {
+                Some((&WITH_DICTIONARY, rest)) => match self.dictionary.as_mut() {
+                    Some(dictionary) => decompress_zstd(rest, Some(&mut dictionary.decompressor)),
+                    None => Err(create_custom_io_error("Value needs a compression dictionary the file doesn't have")),
+                },
+                Some((_, rest)) => decompress_zstd(rest, None),
+                None => Err(create_custom_io_error("Empty compressed value")),
+            },
+        }
+    }
+}
+
+fn decompress_zstd(data: &[u8], decompressor: Option<&mut zstd::bulk::Decompressor<'static>>) -> io::Result<Vec<u8>> {
+    // Values are compressed in one go, so the frame always records their size
+    let size = match zstd::zstd_safe::get_frame_content_size(data) {
+        Ok(Some(size)) => size as usize,
+        _ => return Err(create_custom_io_error("Corrupt zstd value")),
+    };
+    match decompressor {
+        Some(decompressor) => decompressor.decompress(data, size),
+        None => zstd::bulk::decompress(data, size),
+    }
+}
@@ -0,0 +1,30 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+
+use crate::constants::LOCK_FILE;
+use crate::types::create_custom_io_error;
+
+// Held for as long as a database has its storage directory open, so a second server or command can't
+// write to the same files. The lock is advisory and released by the OS if the process dies.
+pub struct DirectoryLock {
+    _file: File,
+}
+
+impl DirectoryLock {
+    pub fn acquire(directory: &str) -> io::Result<DirectoryLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(format!("{}/{}", directory, LOCK_FILE))?;
+        match file.try_lock() {
+            Ok(()) => Ok(DirectoryLock { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(create_custom_io_error(&format!(
+                "Storage directory {} is in use by another process",
+                directory
+            ))),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::types::create_custom_io_error;
+
+use super::storage_manager_v2::{is_v2_file, FORMAT_VERSION as V2_FORMAT_VERSION};
+
+const MAGIC: &[u8; 4] = b"RDBF";
+pub const HEADER_SIZE: usize = 20;
+
+// Kept in block 0, which holds no data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileHeader {
+    pub version: u32,
+    pub block_size: u32,
+    // Highest block number in use
+    pub block_count: u32,
+    // First block of the free space map, 0 until the map is written
+    pub map_root: u32,
+}
+
+impl FileHeader {
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut encoded = MAGIC.to_vec();
+        encoded.write_u32::<BigEndian>(self.version)?;
+        encoded.write_u32::<BigEndian>(self.block_size)?;
+        encoded.write_u32::<BigEndian>(self.block_count)?;
+        encoded.write_u32::<BigEndian>(self.map_root)?;
+        Ok(encoded)
+    }
+
+    // None when the block doesn't start with a header
+    pub fn decode(block: &[u8]) -> io::Result<Option<FileHeader>> {
+        if block.len() < HEADER_SIZE || &block[..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+        let mut cursor = Cursor::new(&block[MAGIC.len()..HEADER_SIZE]);
+        Ok(Some(FileHeader {
+            version: cursor.read_u32::<BigEndian>()?,
+            block_size: cursor.read_u32::<BigEndian>()?,
+            block_count: cursor.read_u32::<BigEndian>()?,
+            map_root: cursor.read_u32::<BigEndian>()?,
+        }))
+    }
+
+    // None for missing or empty files
+    pub fn read(file_name: &str) -> io::Result<Option<FileHeader>> {
+        let file = match File::open(file_name) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut block = vec![];
+        file.take(HEADER_SIZE as u64).read_to_end(&mut block)?;
+        FileHeader::decode(&block)
+    }
+}
+
+// The format a storage file was written in, files that don't exist yet get the given version
+pub fn format_version(file_name: &str, new_file_version: u32) -> io::Result<u32> {
+    if let Some(header) = FileHeader::read(file_name)? {
+        return Ok(header.version);
+    }
+    let is_new = match std::fs::metadata(file_name) {
+        Ok(metadata) => metadata.len() == 0,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+        Err(e) => return Err(e),
+    };
+    if is_new {
+        return Ok(new_file_version);
+    }
+    // Written before headers existed, in either of the first two layouts
+    match is_v2_file(file_name)? {
+        true => Ok(V2_FORMAT_VERSION),
+        false => Ok(1),
+    }
+}
+
+pub fn check_block_size(file_name: &str, header: &FileHeader, block_size: u32) -> io::Result<()> {
+    if header.block_size != block_size {
+        return Err(create_custom_io_error(&format!(
+            "{} uses {} byte blocks, expected {}",
+            file_name, header.block_size, block_size
+        )));
+    }
+    Ok(())
+}
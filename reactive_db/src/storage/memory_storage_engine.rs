@@ -0,0 +1,125 @@
+use std::cmp;
+use std::io;
+
+use crate::types::create_custom_io_error;
+
+use super::buffer_pool::PageCopy;
+use super::storage_engine::{StorageEngine, StorageStats, FIRST_ID};
+
+enum Slot {
+    Free,
+    // Handed out by allocate_block but not written yet
+    Reserved,
+    Stored(Vec<u8>),
+}
+
+// Keeps every value in memory under the same ids a file would, for tables that don't need to outlive the
+// process. Nothing is read from or written to the file name, it only names the storage in errors and stats.
+pub struct MemoryStorageEngine {
+    file_name: String,
+    // Indexed by id
+    slots: Vec<Slot>,
+    id_search_from: u32,
+    bytes: u64,
+}
+
+impl StorageEngine for MemoryStorageEngine {
+    fn start_read_session(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn start_write_session(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_session(&mut self) {}
+
+    fn allocate_block(&mut self) -> io::Result<u32> {
+        let start = self.id_search_from as usize;
+        let id = match self.slots.iter().skip(start).position(|slot| matches!(slot, Slot::Free)) {
+            Some(offset) => (start + offset) as u32,
+            None => cmp::max(self.slots.len(), start) as u32,
+        };
+        self.id_search_from = id + 1;
+        self.set(id, Slot::Reserved);
+        Ok(id)
+    }
+
+    fn write_data(&mut self, data: Vec<u8>, starting_block: Option<u32>) -> io::Result<u32> {
+        let id = match starting_block {
+            Some(id) => id,
+            None => self.allocate_block()?,
+        };
+        self.set(id, Slot::Stored(data));
+        Ok(id)
+    }
+
+    fn read_data(&mut self, starting_block: u32) -> io::Result<Vec<u8>> {
+        match self.slots.get(starting_block as usize) {
+            Some(Slot::Stored(data)) => Ok(data.clone()),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn delete_data(&mut self, starting_block: u32) -> io::Result<()> {
+        self.set(starting_block, Slot::Free);
+        if starting_block >= FIRST_ID {
+            self.id_search_from = cmp::min(self.id_search_from, starting_block);
+        }
+        Ok(())
+    }
+
+    fn is_empty(&mut self, block: u32) -> io::Result<bool> {
+        Ok(!matches!(self.slots.get(block as usize), Some(Slot::Stored(_))))
+    }
+
+    fn get_file_name(&mut self) -> String {
+        self.file_name.clone()
+    }
+
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>> {
+        Ok((0..self.slots.len() as u32).filter(|id| matches!(self.slots[*id as usize], Slot::Stored(_))).collect())
+    }
+
+    fn start_copy(&mut self, _destination: &str) -> io::Result<PageCopy> {
+        Err(create_custom_io_error(&format!("{} is kept in memory and has no file to copy", self.file_name)))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Every read is served from memory, so there are no cache figures
+    fn stats(&mut self) -> io::Result<StorageStats> {
+        Ok(StorageStats {
+            bytes: self.bytes,
+            cache_hits: 0,
+            cache_misses: 0,
+        })
+    }
+}
+
+impl MemoryStorageEngine {
+    pub fn new(file_name: String) -> MemoryStorageEngine {
+        MemoryStorageEngine {
+            file_name,
+            slots: vec![],
+            id_search_from: FIRST_ID,
+            bytes: 0,
+        }
+    }
+
+    fn set(&mut self, id: u32, slot: Slot) {
+        let id = id as usize;
+        if id >= self.slots.len() {
+            self.slots.resize_with(id + 1, || Slot::Free);
+        }
+        if let Slot::Stored(old) = &self.slots[id] {
+            self.bytes -= old.len() as u64;
+        }
+        if let Slot::Stored(data) = &slot {
+            self.bytes += data.len() as u64;
+        }
+        self.slots[id] = slot;
+    }
+}
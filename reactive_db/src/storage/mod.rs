@@ -1,5 +1,11 @@
+pub mod buffer_pool;
+pub mod compression;
+pub mod directory_lock;
+pub mod file_header;
+pub mod memory_storage_engine;
 pub mod storage_engine;
 pub mod storage_manager;
 pub mod storage_manager_v2;
+pub mod storage_manager_v3;
 pub mod versioned_storage_engine;
 mod tests;
\ No newline at end of file
@@ -1,13 +1,25 @@
+use std::fs;
 use std::io;
 
-pub trait StorageEngine{
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE};
+
+use super::buffer_pool::PageCopy;
+use super::compression::Compression;
+
+// The BTree keeps its root at id 1, everything else is handed out from here. Engines keep the lowest id that
+// may be free as id_search_from, so handing out an id doesn't look through the ones below it again
+pub const FIRST_ID: u32 = 2;
+
+pub trait StorageEngine: Send {
     fn start_read_session(&mut self) -> io::Result<()>;
 
     fn start_write_session(&mut self) -> io::Result<()>;
 
     fn end_session(&mut self);
 
-    fn allocate_block(&mut self) -> u32;
+    fn allocate_block(&mut self) -> io::Result<u32>;
 
     fn write_data(&mut self, data: Vec<u8>, starting_block: Option<u32>) -> io::Result<u32>;
 
@@ -18,4 +30,96 @@ pub trait StorageEngine{
     fn is_empty(&mut self, block: u32) -> io::Result<bool>;
 
     fn get_file_name(&mut self) -> String;
+
+    // Every id that holds a value, in order
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>>;
+
+    // Starts copying the file as it is now to a new file, the copy is finished through the buffer pool
+    fn start_copy(&mut self, destination: &str) -> io::Result<PageCopy>;
+
+    // Makes sure everything written so far is on disk
+    fn flush(&mut self) -> io::Result<()>;
+
+    fn stats(&mut self) -> io::Result<StorageStats>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StorageStats {
+    pub bytes: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+// Where a table keeps its rows and indexes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageKind {
+    #[default]
+    File,
+    // Gone when the table is closed, nothing is written to the data directory
+    Memory,
+}
+
+// Settings for files created from now on, existing files keep the ones they were written with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StorageOptions {
+    pub block_size: u32,
+    pub compression: Compression,
+    // Train a zstd dictionary from the first rows written and compress the rest with it
+    pub dictionary: bool,
+    pub kind: StorageKind,
+}
+
+impl Default for StorageOptions {
+    fn default() -> StorageOptions {
+        StorageOptions {
+            block_size: DEFAULT_BLOCK_SIZE,
+            compression: Compression::None,
+            dictionary: false,
+            kind: StorageKind::File,
+        }
+    }
+}
+
+impl StorageOptions {
+    pub fn new(
+        block_size: Option<u32>,
+        compression: Option<Compression>,
+        dictionary: Option<bool>,
+        kind: StorageKind,
+    ) -> Result<StorageOptions, String> {
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if !is_valid_block_size(block_size) {
+            return Err(format!(
+                "Block size {} has to be a power of two from {} to {}",
+                block_size, MIN_BLOCK_SIZE, MAX_BLOCK_SIZE
+            ));
+        }
+        let compression = compression.unwrap_or(Compression::None);
+        let dictionary = dictionary.unwrap_or(false);
+        if dictionary && compression != Compression::Zstd {
+            return Err("Compression dictionaries are only supported with Zstd".to_string());
+        }
+        Ok(StorageOptions { block_size, compression, dictionary, kind })
+    }
+}
+
+pub fn is_valid_block_size(block_size: u32) -> bool {
+    block_size.is_power_of_two() && (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+}
+
+// Files that haven't been written yet take no space
+pub fn file_size(file_name: &str) -> io::Result<u64> {
+    match fs::metadata(file_name) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+impl StorageStats {
+    pub fn add(&mut self, other: StorageStats) {
+        self.bytes += other.bytes;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+    }
 }
\ No newline at end of file
@@ -13,7 +13,9 @@ use std::io::BufWriter;
 use std::io::SeekFrom;
 use std::io::{Error, ErrorKind};
 
-use super::storage_engine::StorageEngine;
+use super::buffer_pool::PageCopy;
+use super::storage_engine::{file_size, StorageEngine, StorageStats};
+use tracing::debug;
 
 const CACHE_SIZE: usize = 100;
 const DATA_BLOCK_SIZE: u32 = 100;
@@ -54,18 +56,18 @@ impl StorageEngine for StorageManager {
         self.open_file = None;
     }
 
-    fn allocate_block(&mut self) -> u32 {
+    fn allocate_block(&mut self) -> io::Result<u32> {
         match self.open_blocks.pop() {
             Some(n) => {
                 let block = (-n) as u32;
                 if self.closed_blocks.contains(&block) {
                     return self.allocate_block();
                 }
-                return block;
+                Ok(block)
             }
             None => {
                 self.number_of_blocks += 1;
-                return self.number_of_blocks;
+                Ok(self.number_of_blocks)
             }
         }
     }
@@ -85,7 +87,7 @@ impl StorageEngine for StorageManager {
                 self.closed_blocks.insert(n);
                 n
             }
-            None => self.allocate_block(),
+            None => self.allocate_block()?,
         };
         let mut cursor = 0;
         let mut current_block = root_block;
@@ -95,7 +97,7 @@ impl StorageEngine for StorageManager {
             let mut next_ref_to_write = vec![0; REFERENCE_BLOCK_SIZE as usize];
             if end < data.len() {
                 let mut endian_rep = vec![];
-                next_ref = self.allocate_block();
+                next_ref = self.allocate_block()?;
                 endian_rep.write_u32::<BigEndian>(next_ref)?;
                 next_ref_to_write = vec![0; REFERENCE_BLOCK_SIZE as usize - endian_rep.len()];
                 next_ref_to_write.extend(endian_rep);
@@ -161,12 +163,35 @@ impl StorageEngine for StorageManager {
     fn get_file_name(&mut self) -> String {
         self.file_name.clone()
     }
+
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>> {
+        let mut roots = self.roots()?;
+        roots.sort();
+        Ok(roots)
+    }
+
+    // Files in this format are migrated as soon as they're opened, so one is never live
+    fn start_copy(&mut self, _destination: &str) -> io::Result<PageCopy> {
+        Err(Error::other("Only files in the current storage format can be copied"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        OpenOptions::new().write(true).open(&self.file_name)?.sync_all()
+    }
+
+    fn stats(&mut self) -> io::Result<StorageStats> {
+        Ok(StorageStats {
+            bytes: file_size(&self.file_name)?,
+            cache_hits: self.cache.hits(),
+            cache_misses: self.cache.misses(),
+        })
+    }
 }
 
 impl StorageManager {
     
     pub fn new(file_name: String) -> io::Result<StorageManager> {
-        print!("V1 detected");
+        debug!(file = %file_name, "Opening storage file with the V1 storage manager");
         let mut manager = StorageManager {
             file_name: file_name,
             open_blocks: BinaryHeap::new(),
@@ -183,6 +208,23 @@ impl StorageManager {
         return Ok(manager);
     }
 
+    // The first block of every row, found by leaving out blocks another block continues into
+    pub fn roots(&mut self) -> io::Result<Vec<u32>> {
+        let empty_block: Vec<u8> = vec![0; TOTAL_BLOCK_SIZE as usize];
+        let mut blocks = vec![];
+        let mut continuations = HashSet::new();
+        for n in 1..=self.number_of_blocks {
+            let raw_block = self.read_block(n)?;
+            if raw_block.len() < TOTAL_BLOCK_SIZE as usize || raw_block == empty_block {
+                continue;
+            }
+            let next = Cursor::new(&raw_block[DATA_BLOCK_SIZE as usize..]).read_u32::<BigEndian>()?;
+            continuations.insert(next);
+            blocks.push(n);
+        }
+        Ok(blocks.into_iter().filter(|block| !continuations.contains(block)).collect())
+    }
+
     // Write to a specific block
     fn write_block(&mut self, block_number: u32, mut data: Vec<u8>) -> io::Result<()> {
         if !self.session_open {
@@ -2,7 +2,6 @@ use crate::{utilities::max_size_hash_map::MaxSizeHashMap};
 use crate::io::Cursor;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::cmp;
-use std::collections::BinaryHeap;
 use std::collections::HashSet;
 use std::fs::File;
 use std::fs::OpenOptions;
@@ -15,25 +14,63 @@ use std::io::{Error, ErrorKind};
 use bzip2::Compression;
 use bzip2::read::{BzEncoder, BzDecoder};
 
-use super::storage_engine::StorageEngine;
+use super::file_header::{check_block_size, FileHeader};
+use super::buffer_pool::PageCopy;
+use super::storage_engine::{file_size, StorageEngine, StorageStats};
 
 const CACHE_SIZE: usize = 100;
 const DATA_BLOCK_SIZE: u32 = 100;
 const REFERENCE_BLOCK_SIZE: u32 = 4;
 const SIZE_BLOCK_SIZE: u32 = 4;
 const TOTAL_BLOCK_SIZE: u32 = DATA_BLOCK_SIZE + REFERENCE_BLOCK_SIZE+SIZE_BLOCK_SIZE;
+pub const FORMAT_VERSION: u32 = 2;
+// Each free space map block has a bit for this many blocks
+const BLOCKS_PER_MAP: u32 = DATA_BLOCK_SIZE * 8;
+
+const BZIP2_MAGIC: &[u8] = b"BZh";
 
 pub struct StorageManagerV2 {
     pub file_name: String,
-    pub open_blocks: BinaryHeap<isize>,
-    pub closed_blocks: HashSet<u32>,
     pub number_of_blocks: u32,
     pub session_open: bool,
     pub open_file: Option<File>,
     cache: MaxSizeHashMap<u32, Vec<u8>>,
+    map_root: u32,
+    // Read on the first allocation or delete so opening a file only reads the header
+    free_map: Option<FreeMap>,
     debug: bool
 }
 
+// One bit per block, set while the block is in use. The map is stored in a chain of blocks like any other data.
+struct FreeMap {
+    bits: Vec<u8>,
+    map_blocks: Vec<u32>,
+    // No block below this one is free
+    search_from: u32,
+}
+
+impl FreeMap {
+    fn covers(&self, block: u32) -> bool {
+        (block as usize) < self.bits.len() * 8
+    }
+
+    fn is_used(&self, block: u32) -> bool {
+        self.bits[block as usize / 8] & (1 << (block % 8)) != 0
+    }
+
+    // Returns whether the bit changed
+    fn set(&mut self, block: u32, used: bool) -> bool {
+        if self.is_used(block) == used {
+            return false;
+        }
+        self.bits[block as usize / 8] ^= 1 << (block % 8);
+        if !used {
+            self.search_from = cmp::min(self.search_from, block);
+        }
+        true
+    }
+}
+
 impl StorageEngine for StorageManagerV2 {
     fn start_read_session(&mut self) -> io::Result<()> {
         self.session_open = true;
@@ -58,19 +95,25 @@ impl StorageEngine for StorageManagerV2 {
         self.open_file = None;
     }
 
-    fn allocate_block(&mut self) -> u32 {
-        match self.open_blocks.pop() {
-            Some(n) => {
-                let block = (-n) as u32;
-                if self.closed_blocks.contains(&block) {
-                    return self.allocate_block();
-                }
-                return block;
-            }
-            None => {
-                self.number_of_blocks += 1;
-                return self.number_of_blocks;
+    fn allocate_block(&mut self) -> io::Result<u32> {
+        self.load_free_map()?;
+        let number_of_blocks = self.number_of_blocks;
+        let free_map = self.free_map.as_mut().unwrap();
+        let free_block = (free_map.search_from..=number_of_blocks).find(|block| !free_map.is_used(*block));
+        free_map.search_from = free_block.unwrap_or(number_of_blocks + 1);
+        if let Some(block) = free_block {
+            self.mark_block(block, true)?;
+            return Ok(block);
+        }
+        loop {
+            let block = self.number_of_blocks + 1;
+            if self.free_map.as_ref().unwrap().covers(block) {
+                self.number_of_blocks = block;
+                self.mark_block(block, true)?;
+                self.write_header()?;
+                return Ok(block);
             }
+            self.add_map_block()?;
         }
     }
 
@@ -85,13 +128,10 @@ impl StorageEngine for StorageManagerV2 {
         self.start_write_session()?;
         let root_block: u32 = match starting_block {
             Some(n) => {
-                if self.number_of_blocks <= n {
-                    self.number_of_blocks += 1;
-                }
-                self.closed_blocks.insert(n);
+                self.reserve_block(n)?;
                 n
             }
-            None => self.allocate_block(),
+            None => self.allocate_block()?,
         };
         let mut cursor = 0;
         let mut size_remaining = data.len();
@@ -106,7 +146,7 @@ impl StorageEngine for StorageManagerV2 {
             size_remaining_to_write.extend(endian_rep_size);
             if end < data.len() {
                 let mut endian_rep = vec![];
-                next_ref = self.allocate_block();
+                next_ref = self.allocate_block()?;
                 endian_rep.write_u32::<BigEndian>(next_ref)?;
                 next_ref_to_write = vec![0; REFERENCE_BLOCK_SIZE as usize - endian_rep.len()];
                 next_ref_to_write.extend(endian_rep);
@@ -164,9 +204,9 @@ impl StorageEngine for StorageManagerV2 {
             let raw_block = self.read_block(block_to_read as u32)?;
             let next_block_raw = raw_block[(DATA_BLOCK_SIZE) as usize..].to_vec();
             self.delete_block(block_to_read as u32)?;
-            if block_to_read != 1 && block_to_read != 0 {
-                self.open_blocks.push(-(block_to_read as isize));
-                self.closed_blocks.remove(&(block_to_read as u32));
+            // The BTree root always lives in block 1
+            if block_to_read != 1 {
+                self.mark_block(block_to_read as u32, false)?;
             }
             block_to_read = Cursor::new(next_block_raw).read_u32::<BigEndian>().unwrap() as usize;
         }
@@ -184,50 +224,77 @@ impl StorageEngine for StorageManagerV2 {
     fn get_file_name(&mut self) -> String {
         self.file_name.clone()
     }
+
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>> {
+        let mut roots = self.roots()?;
+        roots.sort();
+        Ok(roots)
+    }
+
+    // Files in this format are migrated as soon as they're opened, so one is never live
+    fn start_copy(&mut self, _destination: &str) -> io::Result<PageCopy> {
+        Err(Error::other("Only files in the current storage format can be copied"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        OpenOptions::new().write(true).open(&self.file_name)?.sync_all()
+    }
+
+    fn stats(&mut self) -> io::Result<StorageStats> {
+        Ok(StorageStats {
+            bytes: file_size(&self.file_name)?,
+            cache_hits: self.cache.hits(),
+            cache_misses: self.cache.misses(),
+        })
+    }
 }
 
 impl StorageManagerV2 {
     pub fn new(file_name: String) -> io::Result<StorageManagerV2> {
+        let header = FileHeader::read(&file_name)?;
         let mut manager = StorageManagerV2 {
             file_name: file_name,
-            open_blocks: BinaryHeap::new(),
-            closed_blocks: HashSet::new(),
             number_of_blocks: 0,
             session_open: false,
             open_file: None,
             cache: MaxSizeHashMap::new(CACHE_SIZE),
+            map_root: 0,
+            free_map: None,
             debug: false
         };
         manager.start_write_session()?;
-        manager.update_open_blocks()?;
+        match header {
+            Some(header) => {
+                check_block_size(&manager.file_name, &header, TOTAL_BLOCK_SIZE)?;
+                manager.number_of_blocks = header.block_count;
+                manager.map_root = header.map_root;
+            }
+            // New files and ones written before headers get a header and a free space map once
+            None => manager.write_free_map()?,
+        }
         manager.end_session();
 
         return Ok(manager);
     }
 
-    pub fn is_v2_storage_manager(_file_name: String) -> io::Result<bool> {
-        /*let mut manager = StorageManagerV2 {
-            file_name: file_name,
-            open_blocks: BinaryHeap::new(),
-            closed_blocks: HashSet::new(),
-            number_of_blocks: 0,
-            session_open: false,
-            open_file: None,
-            cache: MaxSizeHashMap::new(CACHE_SIZE),
-            debug: false
-        };
-        manager.start_read_session()?;
-        let data = manager.read_data(0);
-        match data {
-            Err(_) => return Ok(false),
-            Ok(d) => {
-                if d == vec![2] {
-                    return Ok(true);
-                }
-                return Ok(false);
+    // The first block of every row, which is what other files and the BTree refer to. Used when moving the
+    // rows to a newer format.
+    pub fn roots(&mut self) -> io::Result<Vec<u32>> {
+        self.load_free_map()?;
+        let map_blocks = self.free_map.as_ref().unwrap().map_blocks.clone();
+        let empty_block: Vec<u8> = vec![0; TOTAL_BLOCK_SIZE as usize];
+        let mut blocks = vec![];
+        let mut continuations = HashSet::new();
+        for n in 1..=self.number_of_blocks {
+            let raw_block = self.read_block(n)?;
+            if map_blocks.contains(&n) || raw_block.len() < TOTAL_BLOCK_SIZE as usize || raw_block == empty_block {
+                continue;
             }
-        };*/
-        Ok(true)
+            let next = Cursor::new(&raw_block[DATA_BLOCK_SIZE as usize..]).read_u32::<BigEndian>()?;
+            continuations.insert(next);
+            blocks.push(n);
+        }
+        Ok(blocks.into_iter().filter(|block| !continuations.contains(block)).collect())
     }
 
     // Write to a specific block
@@ -281,22 +348,124 @@ impl StorageManagerV2 {
         return Ok(());
     }
 
-    fn update_open_blocks(&mut self) -> io::Result<()> {
+    fn write_header(&mut self) -> io::Result<()> {
+        let header = FileHeader {
+            version: FORMAT_VERSION,
+            block_size: TOTAL_BLOCK_SIZE,
+            block_count: self.number_of_blocks,
+            map_root: self.map_root,
+        };
+        self.write_block(0, header.encode()?)
+    }
+
+    fn load_free_map(&mut self) -> io::Result<()> {
+        if self.free_map.is_some() {
+            return Ok(());
+        }
+        let mut free_map = FreeMap { bits: vec![], map_blocks: vec![], search_from: 0 };
+        let mut map_block = self.map_root;
+        while map_block != 0 {
+            let raw_block = self.read_block(map_block)?;
+            if raw_block.len() < TOTAL_BLOCK_SIZE as usize {
+                Err(Error::other(format!("Free space map block {} is truncated", map_block)))?;
+            }
+            free_map.bits.extend(&raw_block[..DATA_BLOCK_SIZE as usize]);
+            free_map.map_blocks.push(map_block);
+            map_block = Cursor::new(&raw_block[DATA_BLOCK_SIZE as usize..]).read_u32::<BigEndian>()?;
+        }
+        self.free_map = Some(free_map);
+        Ok(())
+    }
+
+    // Marks a block as in use or free and writes the part of the map holding its bit
+    fn mark_block(&mut self, block: u32, used: bool) -> io::Result<()> {
+        self.load_free_map()?;
+        while !self.free_map.as_ref().unwrap().covers(block) {
+            self.add_map_block()?;
+        }
+        if self.free_map.as_mut().unwrap().set(block, used) {
+            self.write_map_block((block / BLOCKS_PER_MAP) as usize)?;
+        }
+        Ok(())
+    }
+
+    // Writes to a block that wasn't allocated, like the BTree root, still have to take it out of the map
+    fn reserve_block(&mut self, block: u32) -> io::Result<()> {
+        if block > self.number_of_blocks {
+            self.number_of_blocks = block;
+            self.write_header()?;
+        }
+        self.mark_block(block, true)
+    }
+
+    // Appends a map block covering the next BLOCKS_PER_MAP blocks and links it to the last one
+    fn add_map_block(&mut self) -> io::Result<()> {
+        let location = self.number_of_blocks + 1;
+        self.number_of_blocks = location;
+        let free_map = self.free_map.as_mut().unwrap();
+        free_map.bits.extend(vec![0; DATA_BLOCK_SIZE as usize]);
+        free_map.map_blocks.push(location);
+        free_map.set(location, true);
+        let group = free_map.map_blocks.len() - 1;
+        self.write_map_block(group)?;
+        if group == 0 {
+            self.map_root = location;
+        } else {
+            self.write_map_block(group - 1)?;
+        }
+        self.write_header()
+    }
+
+    fn write_map_block(&mut self, group: usize) -> io::Result<()> {
+        let free_map = self.free_map.as_ref().unwrap();
+        let start = group * DATA_BLOCK_SIZE as usize;
+        let mut to_write = free_map.bits[start..start + DATA_BLOCK_SIZE as usize].to_vec();
+        let next = free_map.map_blocks.get(group + 1).copied().unwrap_or(0);
+        let location = free_map.map_blocks[group];
+        to_write.write_u32::<BigEndian>(next)?;
+        to_write.write_u32::<BigEndian>(DATA_BLOCK_SIZE)?;
+        self.write_block(location, to_write)
+    }
+
+    // Finds the free blocks of a file without a map by reading all of it, only done the first time it's opened
+    fn write_free_map(&mut self) -> io::Result<()> {
         let file = self.open_file.as_ref().unwrap();
-        let mut open_blocks = vec![];
         let empty_block: Vec<u8> = vec![0; TOTAL_BLOCK_SIZE as usize];
-        let file_len = file.metadata()?.len();
-        let num_blocks = file_len / (TOTAL_BLOCK_SIZE as u64);
+        let num_blocks = (file.metadata()?.len() / (TOTAL_BLOCK_SIZE as u64)) as u32;
+        let mut used_blocks = vec![0, 1];
         for n in 2..num_blocks {
-            let block = self.read_block(n as u32)?;
-            if block == empty_block {
-                open_blocks.push(n as u32);
+            if self.read_block(n)? != empty_block {
+                used_blocks.push(n);
             }
         }
-        for block in open_blocks {
-            self.open_blocks.push(-(block as isize));
+        self.number_of_blocks = cmp::max(num_blocks, 2) - 1;
+        self.free_map = Some(FreeMap { bits: vec![], map_blocks: vec![], search_from: 0 });
+        self.map_root = 0;
+        while !self.free_map.as_ref().unwrap().covers(self.number_of_blocks) {
+            self.add_map_block()?;
+        }
+        let free_map = self.free_map.as_mut().unwrap();
+        for block in used_blocks {
+            free_map.set(block, true);
+        }
+        for group in 0..free_map.map_blocks.len() {
+            self.write_map_block(group)?;
+        }
+        self.write_header()
+    }
+}
+
+// Tells apart files written before headers existed. Every row this layout writes is a bzip2 stream,
+// V1 rows are stored as they are
+pub fn is_v2_file(file_name: &str) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(file_name)?);
+    let mut block = vec![0; TOTAL_BLOCK_SIZE as usize];
+    loop {
+        match reader.read_exact(&mut block) {
+            Ok(()) if block.starts_with(BZIP2_MAGIC) => return Ok(true),
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
         }
-        self.number_of_blocks = num_blocks as u32;
-        return Ok(());
     }
 }
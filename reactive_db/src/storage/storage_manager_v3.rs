@@ -0,0 +1,634 @@
+use std::cmp;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use tracing::warn;
+
+use crate::types::create_custom_io_error;
+
+use super::buffer_pool;
+use super::buffer_pool::PageCopy;
+use super::compression::Codec;
+use super::file_header::{FileHeader, HEADER_SIZE};
+use super::storage_engine::{file_size, is_valid_block_size, StorageEngine, StorageOptions, StorageStats, FIRST_ID};
+
+pub const FORMAT_VERSION: u32 = 3;
+// The common header is followed by the directory root, the compression codec, the dictionary's first page, the highest
+// id, the lowest id that may be free and the page rows were last added to. Files written before the last three were
+// kept have 0 there
+const V3_HEADER_SIZE: usize = HEADER_SIZE + 24;
+// A row's location is its page number with the slot number in the low bits
+const SLOT_BITS: u32 = 8;
+const MAX_SLOTS: usize = 1 << SLOT_BITS;
+const MAX_PAGES: u32 = 1 << (31 - SLOT_BITS);
+// Set on locations of rows too big for a page, which are kept in a chain of whole pages
+const OVERFLOW: u32 = 1 << 31;
+// Directory entries of ids that hold nothing and of ids handed out that haven't been written yet
+const FREE_ID: u32 = 0;
+const RESERVED_ID: u32 = 1;
+// Space map value of a page nothing uses, others hold a slotted page's free space in 255ths of a page
+const EMPTY_PAGE: u8 = 255;
+// Slot count, unused, then where the lowest row on the page starts
+const PAGE_HEADER_SIZE: usize = 8;
+// Offset and length of a row, an offset of 0 marks a free slot
+const SLOT_SIZE: usize = 8;
+// Map and directory pages start with the next page of their chain
+const CHAIN_HEADER_SIZE: usize = 4;
+// Overflow pages start with the next page and how much of the page is used
+const OVERFLOW_HEADER_SIZE: usize = 8;
+
+// Rows are packed into slotted pages. Every row has a stable id, the directory maps ids to where the row
+// currently is so a row can move between pages when it's rewritten. Pages are tracked in a space map.
+// Page 0 holds the header, map and directory pages are found through it.
+pub struct StorageManagerV3 {
+    file_name: String,
+    file: File,
+    page_size: usize,
+    page_count: u32,
+    map_root: u32,
+    directory_root: u32,
+    // 0 until a dictionary is trained, it's kept in a chain of overflow pages
+    dictionary_root: u32,
+    codec: Codec,
+    // Pages are read and written through the shared buffer pool under this id
+    pool_file: u64,
+    // Set by writes since the file was last synced, flushing an untouched file skips the fsync
+    unsynced: bool,
+    // Both read on first use so opening a file only reads the header
+    space_map: Option<Chain>,
+    directory: Option<Chain>,
+    // Highest id handed out, these three are kept in the header so the directory isn't searched on open
+    id_count: u32,
+    id_search_from: u32,
+    // The page rows were last added to, tried first for the next one
+    fill_page: u32,
+}
+
+// Values spread over a chain of pages, a byte per page for the space map and a u32 per id for the directory
+struct Chain {
+    bytes: Vec<u8>,
+    pages: Vec<u32>,
+    width: usize,
+    per_page: usize,
+}
+
+impl Chain {
+    fn new(width: usize, page_size: usize) -> Chain {
+        Chain {
+            bytes: vec![],
+            pages: vec![],
+            width,
+            per_page: (page_size - CHAIN_HEADER_SIZE) / width,
+        }
+    }
+
+    fn covers(&self, index: u32) -> bool {
+        (index as usize) < self.pages.len() * self.per_page
+    }
+
+    fn get(&self, index: u32) -> u32 {
+        if !self.covers(index) {
+            return 0;
+        }
+        let start = index as usize * self.width;
+        self.bytes[start..start + self.width].iter().fold(0, |value, byte| (value << 8) | *byte as u32)
+    }
+
+    // Returns the position in the chain of the page holding the value if it changed
+    fn set(&mut self, index: u32, value: u32) -> Option<usize> {
+        if self.get(index) == value {
+            return None;
+        }
+        let start = index as usize * self.width;
+        for (position, byte) in self.bytes[start..start + self.width].iter_mut().enumerate() {
+            *byte = (value >> (8 * (self.width - 1 - position))) as u8;
+        }
+        Some(index as usize / self.per_page)
+    }
+
+    fn push_page(&mut self, page: u32) {
+        self.pages.push(page);
+        self.bytes.extend(vec![0; self.per_page * self.width]);
+    }
+
+    fn encode_page(&self, position: usize) -> io::Result<Vec<u8>> {
+        let mut encoded = vec![];
+        encoded.write_u32::<BigEndian>(self.pages.get(position + 1).copied().unwrap_or(0))?;
+        let size = self.per_page * self.width;
+        encoded.extend(&self.bytes[position * size..(position + 1) * size]);
+        Ok(encoded)
+    }
+}
+
+impl StorageEngine for StorageManagerV3 {
+    // The file stays open for as long as the manager lives, sessions have nothing to do
+    fn start_read_session(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn start_write_session(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_session(&mut self) {
+        // Errors leave the pages dirty, they're reported by the next flush
+        if let Err(e) = buffer_pool::global().write_back(self.pool_file) {
+            warn!(file = %self.file_name, error = %e, "Failed to write pages back");
+        }
+    }
+
+    fn allocate_block(&mut self) -> io::Result<u32> {
+        self.load()?;
+        let directory = self.directory.as_ref().unwrap();
+        let free_id = (self.id_search_from..=self.id_count).find(|id| directory.get(*id) == FREE_ID);
+        let id = match free_id {
+            Some(id) => id,
+            None => self.id_count + 1,
+        };
+        self.id_search_from = id + 1;
+        self.set_location(id, RESERVED_ID)?;
+        self.write_header()?;
+        Ok(id)
+    }
+
+    fn write_data(&mut self, data: Vec<u8>, starting_block: Option<u32>) -> io::Result<u32> {
+        self.load()?;
+        let id = match starting_block {
+            Some(id) => id,
+            None => self.allocate_block()?,
+        };
+        let old_location = self.location(id);
+        if !is_empty_location(old_location) {
+            self.free_row(old_location)?;
+        }
+        if let Some(dictionary) = self.codec.add_sample(&data)? {
+            self.dictionary_root = self.write_overflow(&dictionary)? & !OVERFLOW;
+            self.write_header()?;
+        }
+        let data = self.codec.compress(&data)?;
+        let location = if data.len() > self.max_row_size() {
+            self.write_overflow(&data)?
+        } else {
+            self.write_row(&data)?
+        };
+        self.set_location(id, location)?;
+        Ok(id)
+    }
+
+    fn read_data(&mut self, starting_block: u32) -> io::Result<Vec<u8>> {
+        self.load()?;
+        let location = self.location(starting_block);
+        if is_empty_location(location) {
+            return Ok(vec![]);
+        }
+        let stored = if location & OVERFLOW != 0 {
+            self.read_overflow(location & !OVERFLOW)?
+        } else {
+            let page = self.read_page(location >> SLOT_BITS)?;
+            let (offset, length) = slot(&page, (location as usize) % MAX_SLOTS);
+            if offset == 0 {
+                return Err(create_custom_io_error(&format!("Id {} points at an empty slot", starting_block)));
+            }
+            page[offset..offset + length].to_vec()
+        };
+        self.codec.decompress(&stored)
+    }
+
+    fn delete_data(&mut self, starting_block: u32) -> io::Result<()> {
+        self.load()?;
+        let location = self.location(starting_block);
+        if !is_empty_location(location) {
+            self.free_row(location)?;
+        }
+        self.set_location(starting_block, FREE_ID)?;
+        if starting_block >= FIRST_ID && starting_block < self.id_search_from {
+            self.id_search_from = starting_block;
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    fn is_empty(&mut self, block: u32) -> io::Result<bool> {
+        self.load()?;
+        Ok(is_empty_location(self.location(block)))
+    }
+
+    fn get_file_name(&mut self) -> String {
+        self.file_name.clone()
+    }
+
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>> {
+        self.load()?;
+        Ok((1..=self.id_count).filter(|id| !is_empty_location(self.location(*id))).collect())
+    }
+
+    fn start_copy(&mut self, destination: &str) -> io::Result<PageCopy> {
+        buffer_pool::global().start_copy(self.pool_file, self.page_size, destination)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        buffer_pool::global().write_back(self.pool_file)?;
+        if self.unsynced {
+            self.file.sync_all()?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self) -> io::Result<StorageStats> {
+        let (cache_hits, cache_misses) = buffer_pool::global().file_stats(self.pool_file);
+        Ok(StorageStats {
+            bytes: file_size(&self.file_name)?,
+            cache_hits,
+            cache_misses,
+        })
+    }
+}
+
+impl StorageManagerV3 {
+    pub fn new(file_name: String, options: StorageOptions) -> io::Result<StorageManagerV3> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&file_name)?;
+        let mut manager = StorageManagerV3 {
+            file_name,
+            file: file.try_clone()?,
+            page_size: options.block_size as usize,
+            page_count: 1,
+            map_root: 0,
+            directory_root: 0,
+            dictionary_root: 0,
+            codec: Codec::new(options.compression, options.dictionary),
+            pool_file: buffer_pool::global().register(&file)?,
+            unsynced: false,
+            space_map: None,
+            directory: None,
+            id_count: FIRST_ID - 1,
+            id_search_from: FIRST_ID,
+            fill_page: 0,
+        };
+        if file.metadata()?.len() == 0 {
+            manager.write_header()?;
+            return Ok(manager);
+        }
+        let mut header_bytes = vec![];
+        Read::by_ref(&mut file).take(V3_HEADER_SIZE as u64).read_to_end(&mut header_bytes)?;
+        let header = match FileHeader::decode(&header_bytes)? {
+            Some(header) if header.version == FORMAT_VERSION && header_bytes.len() == V3_HEADER_SIZE => header,
+            _ => return Err(create_custom_io_error(&format!("{} is not a V3 storage file", manager.file_name))),
+        };
+        if !is_valid_block_size(header.block_size) {
+            return Err(create_custom_io_error(&format!("{} has an invalid page size {}", manager.file_name, header.block_size)));
+        }
+        manager.page_size = header.block_size as usize;
+        manager.page_count = header.block_count;
+        manager.map_root = header.map_root;
+        let mut cursor = Cursor::new(&header_bytes[HEADER_SIZE..]);
+        manager.directory_root = cursor.read_u32::<BigEndian>()?;
+        manager.codec = Codec::from_id(cursor.read_u32::<BigEndian>()?)?;
+        manager.dictionary_root = cursor.read_u32::<BigEndian>()?;
+        manager.id_count = cursor.read_u32::<BigEndian>()?;
+        manager.id_search_from = cmp::max(cursor.read_u32::<BigEndian>()?, FIRST_ID);
+        manager.fill_page = cursor.read_u32::<BigEndian>()?;
+        Ok(manager)
+    }
+
+    fn max_row_size(&self) -> usize {
+        self.page_size - PAGE_HEADER_SIZE - SLOT_SIZE
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let header = FileHeader {
+            version: FORMAT_VERSION,
+            block_size: self.page_size as u32,
+            block_count: self.page_count,
+            map_root: self.map_root,
+        };
+        let mut encoded = header.encode()?;
+        encoded.write_u32::<BigEndian>(self.directory_root)?;
+        encoded.write_u32::<BigEndian>(self.codec.id())?;
+        encoded.write_u32::<BigEndian>(self.dictionary_root)?;
+        encoded.write_u32::<BigEndian>(self.id_count)?;
+        encoded.write_u32::<BigEndian>(self.id_search_from)?;
+        encoded.write_u32::<BigEndian>(self.fill_page)?;
+        self.write_page(0, encoded)
+    }
+
+    fn read_page(&mut self, page: u32) -> io::Result<Vec<u8>> {
+        buffer_pool::global().read(self.pool_file, page, self.page_size)
+    }
+
+    fn write_page(&mut self, page: u32, mut data: Vec<u8>) -> io::Result<()> {
+        data.resize(self.page_size, 0);
+        self.unsynced = true;
+        buffer_pool::global().write(self.pool_file, page, data)
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        if self.space_map.is_some() {
+            return Ok(());
+        }
+        let space_map = self.read_chain(self.map_root, 1)?;
+        let directory = self.read_chain(self.directory_root, 4)?;
+        if self.id_count == 0 {
+            let covered = (directory.pages.len() * directory.per_page) as u32;
+            self.id_count = (FIRST_ID..covered).rev().find(|id| directory.get(*id) != FREE_ID).unwrap_or(FIRST_ID - 1);
+        }
+        // Ids written after the header last reached the disk
+        while directory.get(self.id_count + 1) != FREE_ID {
+            self.id_count += 1;
+        }
+        self.space_map = Some(space_map);
+        self.directory = Some(directory);
+        if self.dictionary_root != 0 {
+            let dictionary = self.read_overflow(self.dictionary_root)?;
+            self.codec.set_dictionary(&dictionary)?;
+        }
+        Ok(())
+    }
+
+    fn read_chain(&mut self, root: u32, width: usize) -> io::Result<Chain> {
+        let mut chain = Chain::new(width, self.page_size);
+        let mut page_number = root;
+        while page_number != 0 {
+            let page = self.read_page(page_number)?;
+            chain.pages.push(page_number);
+            chain.bytes.extend(&page[CHAIN_HEADER_SIZE..CHAIN_HEADER_SIZE + chain.per_page * width]);
+            page_number = Cursor::new(&page[..CHAIN_HEADER_SIZE]).read_u32::<BigEndian>()?;
+        }
+        Ok(chain)
+    }
+
+    fn location(&self, id: u32) -> u32 {
+        self.directory.as_ref().unwrap().get(id)
+    }
+
+    fn set_location(&mut self, id: u32, location: u32) -> io::Result<()> {
+        // Ids past the end of the directory are already free
+        if location == FREE_ID && !self.directory.as_ref().unwrap().covers(id) {
+            return Ok(());
+        }
+        while !self.directory.as_ref().unwrap().covers(id) {
+            let page = self.take_page()?;
+            let directory = self.directory.as_mut().unwrap();
+            directory.push_page(page);
+            let position = directory.pages.len() - 1;
+            self.write_chain_page(false, position)?;
+            if position == 0 {
+                self.directory_root = page;
+                self.write_header()?;
+            } else {
+                self.write_chain_page(false, position - 1)?;
+            }
+        }
+        if let Some(position) = self.directory.as_mut().unwrap().set(id, location) {
+            self.write_chain_page(false, position)?;
+        }
+        if id > self.id_count {
+            self.id_count = id;
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    fn space(&self, page: u32) -> u8 {
+        self.space_map.as_ref().unwrap().get(page) as u8
+    }
+
+    fn set_space(&mut self, page: u32, value: u8) -> io::Result<()> {
+        if let Some(position) = self.space_map.as_mut().unwrap().set(page, value as u32) {
+            self.write_chain_page(true, position)?;
+        }
+        Ok(())
+    }
+
+    fn write_chain_page(&mut self, space_map: bool, position: usize) -> io::Result<()> {
+        let chain = if space_map { &self.space_map } else { &self.directory };
+        let chain = chain.as_ref().unwrap();
+        let page = chain.pages[position];
+        let encoded = chain.encode_page(position)?;
+        self.write_page(page, encoded)
+    }
+
+    // A page nothing uses yet, taken out of the space map until the caller records what it holds
+    fn take_page(&mut self) -> io::Result<u32> {
+        if let Some(page) = (1..self.page_count).find(|page| self.space(*page) == EMPTY_PAGE) {
+            self.set_space(page, 0)?;
+            return Ok(page);
+        }
+        if self.page_count >= MAX_PAGES {
+            return Err(create_custom_io_error(&format!("{} has no room for more pages", self.file_name)));
+        }
+        let page = self.page_count;
+        self.page_count += 1;
+        // The space map has to cover every page, its own pages come from the end of the file too
+        while !self.space_map.as_ref().unwrap().covers(self.page_count - 1) {
+            let map_page = self.page_count;
+            self.page_count += 1;
+            let space_map = self.space_map.as_mut().unwrap();
+            space_map.push_page(map_page);
+            let position = space_map.pages.len() - 1;
+            self.write_chain_page(true, position)?;
+            if position == 0 {
+                self.map_root = map_page;
+            } else {
+                self.write_chain_page(true, position - 1)?;
+            }
+        }
+        self.write_header()?;
+        Ok(page)
+    }
+
+    // Puts a row on a page with room for it, trying the page the last row went to first
+    fn write_row(&mut self, data: &[u8]) -> io::Result<u32> {
+        let needed = data.len() + SLOT_SIZE;
+        let page_size = self.page_size;
+        let has_room = |value: u8| value != EMPTY_PAGE && value as usize * page_size / 255 >= needed;
+        let page_number = if self.fill_page != 0 && self.space(self.fill_page) != 0 && self.space(self.fill_page) != EMPTY_PAGE
+            && free_space(&self.read_page(self.fill_page)?) >= needed {
+            self.fill_page
+        } else {
+            match (1..self.page_count).find(|page| has_room(self.space(*page))) {
+                Some(page) => page,
+                None => {
+                    let page = self.take_page()?;
+                    let mut empty = vec![0; self.page_size];
+                    set_data_start(&mut empty, self.page_size);
+                    self.write_page(page, empty)?;
+                    page
+                }
+            }
+        };
+        let mut page = self.read_page(page_number)?;
+        let slot_number = match insert_row(&mut page, data) {
+            Some(slot_number) => slot_number,
+            // The space map only ever underestimates, so this is a page that ran out of slots
+            None => {
+                self.set_space(page_number, 0)?;
+                return self.write_row(data);
+            }
+        };
+        self.set_space(page_number, space_value(&page))?;
+        self.write_page(page_number, page)?;
+        if self.fill_page != page_number {
+            self.fill_page = page_number;
+            self.write_header()?;
+        }
+        Ok((page_number << SLOT_BITS) | slot_number as u32)
+    }
+
+    fn write_overflow(&mut self, data: &[u8]) -> io::Result<u32> {
+        let chunk_size = self.page_size - OVERFLOW_HEADER_SIZE;
+        let mut pages = vec![];
+        for _ in data.chunks(chunk_size) {
+            pages.push(self.take_page()?);
+        }
+        for (position, chunk) in data.chunks(chunk_size).enumerate() {
+            let mut page = vec![];
+            page.write_u32::<BigEndian>(pages.get(position + 1).copied().unwrap_or(0))?;
+            page.write_u32::<BigEndian>(chunk.len() as u32)?;
+            page.extend(chunk);
+            self.write_page(pages[position], page)?;
+        }
+        Ok(OVERFLOW | pages[0])
+    }
+
+    fn read_overflow(&mut self, first_page: u32) -> io::Result<Vec<u8>> {
+        let mut output = vec![];
+        let mut page_number = first_page;
+        while page_number != 0 {
+            let page = self.read_page(page_number)?;
+            let mut cursor = Cursor::new(&page[..OVERFLOW_HEADER_SIZE]);
+            page_number = cursor.read_u32::<BigEndian>()?;
+            let length = cursor.read_u32::<BigEndian>()? as usize;
+            output.extend(&page[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + length]);
+        }
+        Ok(output)
+    }
+
+    fn free_row(&mut self, location: u32) -> io::Result<()> {
+        if location & OVERFLOW != 0 {
+            let mut page_number = location & !OVERFLOW;
+            while page_number != 0 {
+                let page = self.read_page(page_number)?;
+                self.set_space(page_number, EMPTY_PAGE)?;
+                page_number = Cursor::new(&page[..4]).read_u32::<BigEndian>()?;
+            }
+            return Ok(());
+        }
+        let page_number = location >> SLOT_BITS;
+        let mut page = self.read_page(page_number)?;
+        remove_row(&mut page, (location as usize) % MAX_SLOTS);
+        self.set_space(page_number, space_value(&page))?;
+        self.write_page(page_number, page)
+    }
+}
+
+fn is_empty_location(location: u32) -> bool {
+    location == FREE_ID || location == RESERVED_ID
+}
+
+fn read_u16(page: &[u8], at: usize) -> usize {
+    ((page[at] as usize) << 8) | page[at + 1] as usize
+}
+
+fn read_u32(page: &[u8], at: usize) -> usize {
+    Cursor::new(&page[at..at + 4]).read_u32::<BigEndian>().unwrap() as usize
+}
+
+fn write_u32(page: &mut [u8], at: usize, value: usize) {
+    page[at..at + 4].copy_from_slice(&(value as u32).to_be_bytes());
+}
+
+fn slot_count(page: &[u8]) -> usize {
+    read_u16(page, 0)
+}
+
+fn set_slot_count(page: &mut [u8], count: usize) {
+    page[..2].copy_from_slice(&(count as u16).to_be_bytes());
+}
+
+fn data_start(page: &[u8]) -> usize {
+    read_u32(page, 4)
+}
+
+fn set_data_start(page: &mut [u8], start: usize) {
+    write_u32(page, 4, start);
+}
+
+fn slot(page: &[u8], slot_number: usize) -> (usize, usize) {
+    if slot_number >= slot_count(page) {
+        return (0, 0);
+    }
+    let at = PAGE_HEADER_SIZE + slot_number * SLOT_SIZE;
+    (read_u32(page, at), read_u32(page, at + 4))
+}
+
+fn set_slot(page: &mut [u8], slot_number: usize, offset: usize, length: usize) {
+    let at = PAGE_HEADER_SIZE + slot_number * SLOT_SIZE;
+    write_u32(page, at, offset);
+    write_u32(page, at + 4, length);
+}
+
+fn free_space(page: &[u8]) -> usize {
+    data_start(page) - PAGE_HEADER_SIZE - slot_count(page) * SLOT_SIZE
+}
+
+fn space_value(page: &[u8]) -> u8 {
+    if slot_count(page) == 0 {
+        return EMPTY_PAGE;
+    }
+    cmp::min(free_space(page) * 255 / page.len(), EMPTY_PAGE as usize - 1) as u8
+}
+
+// Rows are kept packed against the end of the page, slots grow from the front
+fn insert_row(page: &mut [u8], data: &[u8]) -> Option<usize> {
+    let count = slot_count(page);
+    let free_slot = (0..count).find(|slot_number| slot(page, *slot_number).0 == 0);
+    let needed = data.len() + if free_slot.is_some() { 0 } else { SLOT_SIZE };
+    if free_space(page) < needed || (free_slot.is_none() && count == MAX_SLOTS) {
+        return None;
+    }
+    let slot_number = free_slot.unwrap_or(count);
+    if free_slot.is_none() {
+        set_slot_count(page, count + 1);
+    }
+    let offset = data_start(page) - data.len();
+    page[offset..offset + data.len()].copy_from_slice(data);
+    set_data_start(page, offset);
+    set_slot(page, slot_number, offset, data.len());
+    Some(slot_number)
+}
+
+fn remove_row(page: &mut [u8], slot_number: usize) {
+    let (offset, length) = slot(page, slot_number);
+    if offset == 0 {
+        return;
+    }
+    let start = data_start(page);
+    page.copy_within(start..offset, start + length);
+    page[start..start + length].iter_mut().for_each(|byte| *byte = 0);
+    for other in 0..slot_count(page) {
+        let (other_offset, other_length) = slot(page, other);
+        if other_offset != 0 && other_offset < offset {
+            set_slot(page, other, other_offset + length, other_length);
+        }
+    }
+    set_data_start(page, start + length);
+    set_slot(page, slot_number, 0, 0);
+    let mut count = slot_count(page);
+    while count > 0 && slot(page, count - 1).0 == 0 {
+        count -= 1;
+        set_slot(page, count, 0, 0);
+    }
+    set_slot_count(page, count);
+}
+
+impl Drop for StorageManagerV3 {
+    fn drop(&mut self) {
+        if let Err(e) = buffer_pool::global().unregister(self.pool_file) {
+            warn!(file = %self.file_name, error = %e, "Failed to write pages back");
+        }
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::storage::buffer_pool::BufferPool;
+
+    fn page(value: u8) -> Vec<u8> {
+        vec![value; 16]
+    }
+
+    #[test]
+    fn evicts_least_recently_used_pages() {
+        let file_name = "test_results/buffer_pool_eviction.db";
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(file_name).unwrap();
+        let pool = BufferPool::new(3 * 16);
+        let id = pool.register(&file).unwrap();
+        for n in 0..3 {
+            pool.write(id, n, page(n as u8 + 1)).unwrap();
+        }
+        pool.write_back(id).unwrap();
+        assert_eq!(pool.read(id, 0, 16).unwrap(), page(1));
+        pool.write(id, 1, page(9)).unwrap();
+        pool.write(id, 3, page(4)).unwrap();
+
+        // Page 2 was used longest ago
+        let stats = pool.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.bytes, 3 * 16);
+        assert_eq!(stats.dirty_pages, 2);
+        assert_eq!(pool.read(id, 2, 16).unwrap(), page(3));
+        // Reading page 2 back evicted page 0, which was clean, then the dirty page 1 on the next read
+        assert_eq!(pool.read(id, 0, 16).unwrap(), page(1));
+        assert_eq!(fs::read(file_name).unwrap()[16..32], page(9)[..]);
+        assert_eq!(pool.stats().dirty_pages, 1);
+        assert_eq!(pool.file_stats(id), (1, 2));
+    }
+
+    #[test]
+    fn serves_reads_from_several_threads() {
+        let file_name = "test_results/buffer_pool_parallel_reads.db";
+        fs::write(file_name, (0..64).flat_map(|n| page(n as u8)).collect::<Vec<u8>>()).unwrap();
+        let file = OpenOptions::new().read(true).write(true).open(file_name).unwrap();
+        // Room for only a few pages, so most reads go to disk
+        let pool = Arc::new(BufferPool::new(4 * 16));
+        let id = pool.register(&file).unwrap();
+        let readers: Vec<_> = (0..4).map(|reader| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for round in 0..50 {
+                    let n = (reader * 16 + round) % 64;
+                    assert_eq!(pool.read(id, n, 16).unwrap(), page(n as u8));
+                }
+            })
+        }).collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let (hits, misses) = pool.file_stats(id);
+        assert_eq!(hits + misses, 200);
+        assert!(pool.stats().bytes <= 4 * 16);
+    }
+
+    #[test]
+    fn keeps_dirty_pages_that_cant_be_written_back() {
+        let file_name = "test_results/buffer_pool_failed_write.db";
+        let other_name = "test_results/buffer_pool_failed_write_other.db";
+        fs::write(file_name, []).unwrap();
+        // Writes to a file opened for reading only fail
+        let read_only = OpenOptions::new().read(true).open(file_name).unwrap();
+        let other = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(other_name).unwrap();
+        let pool = BufferPool::new(2 * 16);
+        let failing = pool.register(&read_only).unwrap();
+        let id = pool.register(&other).unwrap();
+        pool.write(failing, 0, page(5)).unwrap();
+        pool.write(id, 0, page(1)).unwrap();
+
+        // Page 0 of the failing file is the oldest, but the other file's pages are evicted in its place
+        for n in 1..4 {
+            pool.write(id, n, page(n as u8 + 1)).unwrap();
+        }
+        assert_eq!(pool.stats().evictions, 3);
+        assert_eq!(pool.read(failing, 0, 16).unwrap(), page(5));
+        assert_eq!(fs::read(other_name).unwrap()[..48], [page(1), page(2), page(3)].concat()[..]);
+        assert!(pool.write_back(failing).is_err());
+        assert_eq!(pool.read(failing, 0, 16).unwrap(), page(5));
+        assert_eq!(pool.stats().dirty_pages, 2);
+    }
+
+    #[test]
+    fn writes_dirty_pages_back_when_unregistered() {
+        let file_name = "test_results/buffer_pool_unregister.db";
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(file_name).unwrap();
+        let pool = BufferPool::new(1024);
+        let id = pool.register(&file).unwrap();
+        pool.write(id, 1, page(7)).unwrap();
+        assert_eq!(fs::read(file_name).unwrap().len(), 0);
+        assert_eq!(pool.read(id, 1, 16).unwrap(), page(7));
+
+        pool.unregister(id).unwrap();
+        let written = fs::read(file_name).unwrap();
+        assert_eq!(written[16..], page(7)[..]);
+        assert_eq!(pool.stats().bytes, 0);
+        assert!(pool.read(id, 1, 16).is_err());
+    }
+
+    #[test]
+    fn copies_pages_as_they_were_when_the_copy_started() {
+        let file_name = "test_results/buffer_pool_copy.db";
+        let copy_name = "test_results/buffer_pool_copy.db.copy";
+        let _ = fs::remove_file(copy_name);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(file_name).unwrap();
+        let pool = BufferPool::new(1024);
+        let id = pool.register(&file).unwrap();
+        pool.write(id, 0, page(1)).unwrap();
+        pool.write(id, 1, page(2)).unwrap();
+        pool.write_back(id).unwrap();
+        // Still only in the pool when the copy starts
+        pool.write(id, 2, page(3)).unwrap();
+
+        let copy = pool.start_copy(id, 16, copy_name).unwrap();
+        assert!(pool.start_copy(id, 16, "test_results/buffer_pool_copy.db.second").is_err());
+        pool.write(id, 1, page(8)).unwrap();
+        pool.write(id, 3, page(9)).unwrap();
+        pool.unregister(id).unwrap();
+        pool.finish_copy(&copy).unwrap();
+
+        assert_eq!(fs::read(copy_name).unwrap(), [page(1), page(2), page(3)].concat());
+        assert_eq!(fs::read(file_name).unwrap(), [page(1), page(8), page(3), page(9)].concat());
+    }
+}
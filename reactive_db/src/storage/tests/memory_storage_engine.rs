@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::storage::{storage_engine::{StorageEngine, StorageKind, StorageOptions}, versioned_storage_engine::VersionedStorageEngine};
+
+    #[test]
+    fn hands_out_ids_like_a_file_without_touching_disk() {
+        let file_name = "test_results/memory_storage_engine.db";
+        let options = StorageOptions::new(None, None, None, StorageKind::Memory).unwrap();
+        let mut storage = VersionedStorageEngine::new(file_name.to_string(), options).unwrap();
+        let locations: Vec<u32> = (0..5)
+            .map(|n| storage.write_data(format!("row number {}", n).into_bytes(), None).unwrap())
+            .collect();
+        assert_eq!(locations, vec![2, 3, 4, 5, 6]);
+        assert_eq!(storage.read_data(4).unwrap(), b"row number 2".to_vec());
+        assert_eq!(storage.stats().unwrap().bytes, 5 * 12);
+
+        storage.delete_data(3).unwrap();
+        assert!(storage.is_empty(3).unwrap());
+        assert!(storage.read_data(3).unwrap().is_empty());
+        assert_eq!(storage.stored_ids().unwrap(), vec![2, 4, 5, 6]);
+        assert_eq!(storage.allocate_block().unwrap(), 3);
+        // Reserved but never written, so it still reads back empty
+        assert!(storage.is_empty(3).unwrap());
+        assert_eq!(storage.write_data(b"rewritten".to_vec(), Some(4)).unwrap(), 4);
+        assert_eq!(storage.read_data(4).unwrap(), b"rewritten".to_vec());
+        assert_eq!(storage.stats().unwrap().bytes, 3 * 12 + 9);
+
+        assert!(storage.start_copy("test_results/memory_copy.db").is_err());
+        storage.flush().unwrap();
+        assert!(!Path::new(file_name).exists());
+    }
+}
@@ -1 +1,3 @@
-mod storage_manager_v2;
\ No newline at end of file
+mod storage_manager_v2;mod storage_manager_v3;
+mod buffer_pool;
+mod memory_storage_engine;
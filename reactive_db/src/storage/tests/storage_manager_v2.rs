@@ -3,7 +3,7 @@ mod tests {
     
     use std::fs;
 
-    use crate::storage::{storage_engine::StorageEngine, storage_manager_v2::StorageManagerV2};
+    use crate::storage::{file_header::FileHeader, storage_engine::{StorageEngine, StorageOptions}, storage_manager_v2::StorageManagerV2, versioned_storage_engine::VersionedStorageEngine};
     fn get_storage_manager(file_name: String) -> StorageManagerV2 {
         let _ = fs::remove_file(file_name.clone());
         return StorageManagerV2::new(file_name).unwrap();
@@ -59,4 +59,57 @@ mod tests {
         let new_data2 = std::str::from_utf8(&recieved_data2).unwrap();
         assert_eq!(test_value2, new_data2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reopening_keeps_free_blocks_and_data() {
+        let file_name = "test_results/storage_manager_reopen.db".to_string();
+        let mut storage_manager = get_storage_manager(file_name.clone());
+        storage_manager.start_write_session().unwrap();
+        let locations: Vec<u32> = ["first", "second", "third"].iter()
+            .map(|value| storage_manager.write_data(value.as_bytes().to_vec(), None).unwrap())
+            .collect();
+        storage_manager.delete_data(locations[1]).unwrap();
+        storage_manager.end_session();
+        drop(storage_manager);
+
+        let header = FileHeader::read(&file_name).unwrap().unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.block_count, locations[2]);
+
+        let mut storage_manager = StorageManagerV2::new(file_name).unwrap();
+        storage_manager.start_write_session().unwrap();
+        assert_eq!(storage_manager.read_data(locations[2]).unwrap(), b"third".to_vec());
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[1]);
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[2] + 1);
+    }
+
+    #[test]
+    fn upgrades_files_written_without_a_header() {
+        let file_name = "test_results/storage_manager_headerless.db".to_string();
+        // Blocks 0 to 4 with data only in block 3
+        let mut legacy = vec![0; 5 * 108];
+        legacy[3 * 108] = 1;
+        fs::write(&file_name, legacy).unwrap();
+
+        let mut storage_manager = StorageManagerV2::new(file_name.clone()).unwrap();
+        storage_manager.start_write_session().unwrap();
+        assert_eq!(storage_manager.allocate_block().unwrap(), 2);
+        assert_eq!(storage_manager.allocate_block().unwrap(), 4);
+        // Block 5 now holds the free space map
+        assert_eq!(storage_manager.allocate_block().unwrap(), 6);
+        storage_manager.end_session();
+
+        let header = FileHeader::read(&file_name).unwrap().unwrap();
+        assert_eq!(header.map_root, 5);
+        assert_eq!(header.block_count, 6);
+    }
+
+    #[test]
+    fn rejects_unknown_format_versions() {
+        let file_name = "test_results/storage_manager_version.db".to_string();
+        let header = FileHeader { version: 9, block_size: 108, block_count: 1, map_root: 0 };
+        fs::write(&file_name, header.encode().unwrap()).unwrap();
+        let error = VersionedStorageEngine::new(file_name, StorageOptions::default()).err().unwrap();
+        assert!(error.to_string().contains("unsupported storage format version 9"));
+    }
+}
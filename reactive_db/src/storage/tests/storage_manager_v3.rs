@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::constants::DICTIONARY_SAMPLES;
+    use crate::storage::{compression::Compression, file_header::FileHeader, storage_engine::{StorageEngine, StorageKind, StorageOptions}, storage_manager::StorageManager, storage_manager_v2::StorageManagerV2, storage_manager_v3::StorageManagerV3, versioned_storage_engine::VersionedStorageEngine};
+
+    fn get_storage_manager(file_name: &str, block_size: u32) -> StorageManagerV3 {
+        let _ = fs::remove_file(file_name);
+        StorageManagerV3::new(file_name.to_string(), StorageOptions::new(Some(block_size), None, None, StorageKind::File).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn packs_small_rows_into_pages() {
+        let file_name = "test_results/storage_manager_v3_packing.db";
+        let mut storage_manager = get_storage_manager(file_name, 4096);
+        let locations: Vec<u32> = (0..100)
+            .map(|n| storage_manager.write_data(format!("row number {}", n).into_bytes(), None).unwrap())
+            .collect();
+        for (n, location) in locations.iter().enumerate() {
+            assert_eq!(storage_manager.read_data(*location).unwrap(), format!("row number {}", n).into_bytes());
+        }
+        // Header, space map, directory and a single page of rows
+        storage_manager.flush().unwrap();
+        assert_eq!(fs::metadata(file_name).unwrap().len(), 4 * 4096);
+
+        storage_manager.delete_data(locations[10]).unwrap();
+        assert!(storage_manager.is_empty(locations[10]).unwrap());
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[10]);
+        assert_eq!(storage_manager.read_data(locations[11]).unwrap(), b"row number 11".to_vec());
+    }
+
+    #[test]
+    fn rewrites_and_large_rows_survive_reopening() {
+        let file_name = "test_results/storage_manager_v3_reopen.db";
+        let mut storage_manager = get_storage_manager(file_name, 512);
+        let large: Vec<u8> = (0..5000).map(|n| (n % 251) as u8).collect();
+        let small = storage_manager.write_data(b"small".to_vec(), None).unwrap();
+        let rewritten = storage_manager.write_data(b"before".to_vec(), None).unwrap();
+        let large_location = storage_manager.write_data(large.clone(), None).unwrap();
+        storage_manager.write_data(vec![7; 300], Some(rewritten)).unwrap();
+        storage_manager.write_data(b"root".to_vec(), Some(1)).unwrap();
+        drop(storage_manager);
+
+        let header = FileHeader::read(file_name).unwrap().unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.block_size, 512);
+
+        // The page size of an existing file wins over the configured one
+        let mut storage_manager = StorageManagerV3::new(file_name.to_string(), StorageOptions::default()).unwrap();
+        assert_eq!(storage_manager.read_data(small).unwrap(), b"small".to_vec());
+        assert_eq!(storage_manager.read_data(rewritten).unwrap(), vec![7; 300]);
+        assert_eq!(storage_manager.read_data(large_location).unwrap(), large);
+        assert_eq!(storage_manager.read_data(1).unwrap(), b"root".to_vec());
+
+        let pages = fs::metadata(file_name).unwrap().len();
+        storage_manager.delete_data(large_location).unwrap();
+        storage_manager.write_data(large.clone(), None).unwrap();
+        storage_manager.flush().unwrap();
+        assert_eq!(fs::metadata(file_name).unwrap().len(), pages);
+    }
+
+    #[test]
+    fn reopened_files_hand_out_the_same_ids() {
+        let file_name = "test_results/storage_manager_v3_ids.db";
+        let mut storage_manager = get_storage_manager(file_name, 512);
+        let locations: Vec<u32> = (0..5)
+            .map(|n| storage_manager.write_data(format!("row {}", n).into_bytes(), None).unwrap())
+            .collect();
+        storage_manager.delete_data(locations[1]).unwrap();
+        storage_manager.flush().unwrap();
+        drop(storage_manager);
+        let saved = fs::read(file_name).unwrap();
+
+        let mut storage_manager = StorageManagerV3::new(file_name.to_string(), StorageOptions::default()).unwrap();
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[1]);
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[4] + 1);
+        drop(storage_manager);
+
+        // Files written before the header kept the ids find them in the directory instead
+        let mut older = saved.clone();
+        older[32..44].iter_mut().for_each(|byte| *byte = 0);
+        fs::write(file_name, older).unwrap();
+        let mut storage_manager = StorageManagerV3::new(file_name.to_string(), StorageOptions::default()).unwrap();
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[1]);
+        assert_eq!(storage_manager.allocate_block().unwrap(), locations[4] + 1);
+        assert_eq!(storage_manager.read_data(locations[4]).unwrap(), b"row 4".to_vec());
+    }
+
+    #[test]
+    fn migrates_v2_files() {
+        let file_name = "test_results/storage_manager_v3_migration.db".to_string();
+        let _ = fs::remove_file(&file_name);
+        let mut old = StorageManagerV2::new(file_name.clone()).unwrap();
+        old.start_write_session().unwrap();
+        let long_row: Vec<u8> = (0..2000).map(|n| (n * 7 % 256) as u8).collect();
+        old.write_data(b"root".to_vec(), Some(1)).unwrap();
+        let first = old.write_data(b"first".to_vec(), None).unwrap();
+        let long = old.write_data(long_row.clone(), None).unwrap();
+        let deleted = old.write_data(b"deleted".to_vec(), None).unwrap();
+        old.delete_data(deleted).unwrap();
+        old.end_session();
+        drop(old);
+
+        let mut storage_manager = VersionedStorageEngine::new(file_name.clone(), StorageOptions::default()).unwrap();
+        assert_eq!(FileHeader::read(&file_name).unwrap().unwrap().version, 3);
+        assert_eq!(storage_manager.read_data(1).unwrap(), b"root".to_vec());
+        assert_eq!(storage_manager.read_data(first).unwrap(), b"first".to_vec());
+        assert_eq!(storage_manager.read_data(long).unwrap(), long_row);
+        assert!(storage_manager.is_empty(deleted).unwrap());
+        assert!(fs::metadata(format!("{}.migrating", file_name)).is_err());
+    }
+
+    #[test]
+    fn migrates_v1_files() {
+        let file_name = "test_results/storage_manager_v3_v1_migration.db".to_string();
+        let _ = fs::remove_file(&file_name);
+        let mut old = StorageManager::new(file_name.clone()).unwrap();
+        old.start_write_session().unwrap();
+        // V1 leaves zero bytes out of the rows it reads back, so the row has none
+        let long_row: Vec<u8> = (0..2000).map(|n| (n * 7 % 255 + 1) as u8).collect();
+        let first = old.write_data(b"first".to_vec(), None).unwrap();
+        let long = old.write_data(long_row.clone(), None).unwrap();
+        let deleted = old.write_data(b"deleted".to_vec(), None).unwrap();
+        old.delete_data(deleted).unwrap();
+        old.end_session();
+        drop(old);
+        assert!(FileHeader::read(&file_name).unwrap().is_none());
+
+        let mut storage_manager = VersionedStorageEngine::new(file_name.clone(), StorageOptions::default()).unwrap();
+        assert_eq!(FileHeader::read(&file_name).unwrap().unwrap().version, 3);
+        assert_eq!(storage_manager.read_data(first).unwrap(), b"first".to_vec());
+        assert_eq!(storage_manager.read_data(long).unwrap(), long_row);
+        assert!(fs::metadata(format!("{}.migrating", file_name)).is_err());
+        // The deleted row isn't carried over, rows written afterwards don't land on the migrated ones
+        storage_manager.start_write_session().unwrap();
+        let added = storage_manager.write_data(b"added".to_vec(), None).unwrap();
+        storage_manager.end_session();
+        assert!(added != first && added != long);
+        assert_eq!(storage_manager.read_data(first).unwrap(), b"first".to_vec());
+        assert_eq!(storage_manager.read_data(long).unwrap(), long_row);
+        assert_eq!(storage_manager.read_data(added).unwrap(), b"added".to_vec());
+    }
+
+    #[test]
+    fn rejects_invalid_block_sizes() {
+        assert!(StorageOptions::new(Some(1000), None, None, StorageKind::File).is_err());
+        assert!(StorageOptions::new(Some(256), None, None, StorageKind::File).is_err());
+        assert_eq!(StorageOptions::new(None, None, None, StorageKind::File).unwrap().block_size, 4096);
+    }
+
+    fn json_row(n: usize) -> Vec<u8> {
+        format!("{{\"_entryId\":{{\"ID\":\"row-{}\"}},\"name\":{{\"Str\":\"person {}\"}},\"age\":{{\"Integer\":{}}}}}", n, n % 7, n % 90).into_bytes()
+    }
+
+    #[test]
+    fn files_keep_their_codec() {
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd, Compression::Bzip2].iter() {
+            let file_name = format!("test_results/storage_manager_v3_{:?}.db", compression);
+            let _ = fs::remove_file(&file_name);
+            let options = StorageOptions::new(None, Some(*compression), None, StorageKind::File).unwrap();
+            let mut storage_manager = StorageManagerV3::new(file_name.clone(), options).unwrap();
+            let large = vec![b'x'; 20000];
+            let small = storage_manager.write_data(json_row(1), None).unwrap();
+            let large_location = storage_manager.write_data(large.clone(), None).unwrap();
+            drop(storage_manager);
+
+            // Opened with other options the file still reads with the codec in its header
+            let options = StorageOptions::new(None, Some(Compression::Lz4), None, StorageKind::File).unwrap();
+            let mut storage_manager = StorageManagerV3::new(file_name, options).unwrap();
+            assert_eq!(storage_manager.read_data(small).unwrap(), json_row(1));
+            assert_eq!(storage_manager.read_data(large_location).unwrap(), large);
+        }
+    }
+
+    #[test]
+    fn trains_a_dictionary_from_early_rows() {
+        let file_name = "test_results/storage_manager_v3_dictionary.db";
+        let _ = fs::remove_file(file_name);
+        let options = StorageOptions::new(None, Some(Compression::Zstd), Some(true), StorageKind::File).unwrap();
+        let mut storage_manager = StorageManagerV3::new(file_name.to_string(), options).unwrap();
+        let locations: Vec<u32> = (0..DICTIONARY_SAMPLES * 2)
+            .map(|n| storage_manager.write_data(json_row(n), None).unwrap())
+            .collect();
+        drop(storage_manager);
+        // The dictionary's first page follows the codec in the header
+        let header = fs::read(file_name).unwrap();
+        assert_ne!(header[28..32], [0, 0, 0, 0]);
+
+        let mut storage_manager = StorageManagerV3::new(file_name.to_string(), StorageOptions::default()).unwrap();
+        for (n, location) in locations.iter().enumerate() {
+            assert_eq!(storage_manager.read_data(*location).unwrap(), json_row(n));
+        }
+        assert!(StorageOptions::new(None, Some(Compression::Lz4), Some(true), StorageKind::File).is_err());
+    }
+}
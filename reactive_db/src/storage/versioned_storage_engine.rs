@@ -1,106 +1,174 @@
-use std::path::Path;
-
+use std::fs;
 use std::io;
 
-use super::{storage_engine::StorageEngine, storage_manager::StorageManager, storage_manager_v2::StorageManagerV2};
+use tracing::info;
+
+use super::{buffer_pool::PageCopy, file_header::format_version, memory_storage_engine::MemoryStorageEngine, storage_engine::{StorageEngine, StorageKind, StorageOptions, StorageStats}, storage_manager::StorageManager, storage_manager_v2::{StorageManagerV2, FORMAT_VERSION as V2_FORMAT_VERSION}, storage_manager_v3::{StorageManagerV3, FORMAT_VERSION as V3_FORMAT_VERSION}};
+use crate::types::create_custom_io_error;
 
-// Structure for detecting storage format version changes
+// Structure for detecting storage format version changes, older formats only exist long enough to be migrated.
+// Tables kept in memory get a memory engine instead and never touch a file
 pub struct VersionedStorageEngine {
     storage_manager: StorageManagerVersion,
     file_name: String
 }
 
 pub enum StorageManagerVersion {
-    V1(StorageManager),
-    V2(StorageManagerV2)
+    V3(StorageManagerV3),
+    Memory(MemoryStorageEngine),
 }
 
 impl StorageEngine for VersionedStorageEngine {
     fn start_read_session(&mut self) -> std::io::Result<()> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.start_read_session(),
-            StorageManagerVersion::V2(sm) => sm.start_read_session()
+            StorageManagerVersion::V3(sm) => sm.start_read_session(),
+            StorageManagerVersion::Memory(sm) => sm.start_read_session()
         }
     }
 
     fn start_write_session(&mut self) -> std::io::Result<()> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.start_write_session(),
-            StorageManagerVersion::V2(sm) => sm.start_write_session()
+            StorageManagerVersion::V3(sm) => sm.start_write_session(),
+            StorageManagerVersion::Memory(sm) => sm.start_write_session()
         }
     }
 
     fn end_session(&mut self) {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.end_session(),
-            StorageManagerVersion::V2(sm) => sm.end_session()
+            StorageManagerVersion::V3(sm) => sm.end_session(),
+            StorageManagerVersion::Memory(sm) => sm.end_session()
         }
     }
 
-    fn allocate_block(&mut self) -> u32 {
+    fn allocate_block(&mut self) -> io::Result<u32> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.allocate_block(),
-            StorageManagerVersion::V2(sm) => sm.allocate_block()
+            StorageManagerVersion::V3(sm) => sm.allocate_block(),
+            StorageManagerVersion::Memory(sm) => sm.allocate_block()
         }
     }
 
     fn write_data(&mut self, data: Vec<u8>, starting_block: Option<u32>) -> std::io::Result<u32> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.write_data(data, starting_block),
-            StorageManagerVersion::V2(sm) => sm.write_data(data, starting_block)
+            StorageManagerVersion::V3(sm) => sm.write_data(data, starting_block),
+            StorageManagerVersion::Memory(sm) => sm.write_data(data, starting_block)
         }
     }
 
     fn read_data(&mut self, starting_block: u32) -> std::io::Result<Vec<u8>> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.read_data(starting_block),
-            StorageManagerVersion::V2(sm) => sm.read_data(starting_block)
+            StorageManagerVersion::V3(sm) => sm.read_data(starting_block),
+            StorageManagerVersion::Memory(sm) => sm.read_data(starting_block)
         }
     }
 
     fn delete_data(&mut self, starting_block: u32) -> std::io::Result<()> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.delete_data(starting_block),
-            StorageManagerVersion::V2(sm) => sm.delete_data(starting_block)
+            StorageManagerVersion::V3(sm) => sm.delete_data(starting_block),
+            StorageManagerVersion::Memory(sm) => sm.delete_data(starting_block)
         }
     }
 
     fn is_empty(&mut self, block: u32) -> std::io::Result<bool> {
         let storage_manager_version = &mut self.storage_manager;
         match storage_manager_version {
-            StorageManagerVersion::V1(sm) => sm.is_empty(block),
-            StorageManagerVersion::V2(sm) => sm.is_empty(block)
+            StorageManagerVersion::V3(sm) => sm.is_empty(block),
+            StorageManagerVersion::Memory(sm) => sm.is_empty(block)
         }
     }
 
     fn get_file_name(&mut self) -> String {
         self.file_name.clone()
     }
+
+    fn stored_ids(&mut self) -> io::Result<Vec<u32>> {
+        let storage_manager_version = &mut self.storage_manager;
+        match storage_manager_version {
+            StorageManagerVersion::V3(sm) => sm.stored_ids(),
+            StorageManagerVersion::Memory(sm) => sm.stored_ids()
+        }
+    }
+
+    fn start_copy(&mut self, destination: &str) -> io::Result<PageCopy> {
+        let storage_manager_version = &mut self.storage_manager;
+        match storage_manager_version {
+            StorageManagerVersion::V3(sm) => sm.start_copy(destination),
+            StorageManagerVersion::Memory(sm) => sm.start_copy(destination)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let storage_manager_version = &mut self.storage_manager;
+        match storage_manager_version {
+            StorageManagerVersion::V3(sm) => sm.flush(),
+            StorageManagerVersion::Memory(sm) => sm.flush()
+        }
+    }
+
+    fn stats(&mut self) -> io::Result<StorageStats> {
+        let storage_manager_version = &mut self.storage_manager;
+        match storage_manager_version {
+            StorageManagerVersion::V3(sm) => sm.stats(),
+            StorageManagerVersion::Memory(sm) => sm.stats()
+        }
+    }
 }
 
 impl VersionedStorageEngine {
-    // Run future migrations here
-    pub fn new(file_name: String) -> io::Result<VersionedStorageEngine> {
-        if Path::new(&file_name).exists() {
-            if !StorageManagerV2::is_v2_storage_manager(file_name.clone())? {
-                return Ok(VersionedStorageEngine {
-                    file_name: file_name.clone(),
-                    storage_manager: StorageManagerVersion::V1(StorageManager::new(file_name)?)
-                })
-            }
+    // Files in older formats are moved to the current one when they're opened
+    pub fn new(file_name: String, options: StorageOptions) -> io::Result<VersionedStorageEngine> {
+        if options.kind == StorageKind::Memory {
+            return Ok(VersionedStorageEngine {
+                storage_manager: StorageManagerVersion::Memory(MemoryStorageEngine::new(file_name.clone())),
+                file_name,
+            });
         }
-        return Ok(VersionedStorageEngine {
-            file_name: file_name.clone(),
-            storage_manager: StorageManagerVersion::V2(StorageManagerV2::new(file_name)?)
-        });
-        
+        let storage_manager = match format_version(&file_name, V3_FORMAT_VERSION)? {
+            1 => {
+                let mut old = StorageManager::new(file_name.clone())?;
+                StorageManagerVersion::V3(migrate(&mut old, &file_name, 1, options, |sm| sm.roots())?)
+            }
+            V2_FORMAT_VERSION => {
+                let mut old = StorageManagerV2::new(file_name.clone())?;
+                StorageManagerVersion::V3(migrate(&mut old, &file_name, V2_FORMAT_VERSION, options, |sm| sm.roots())?)
+            }
+            V3_FORMAT_VERSION => StorageManagerVersion::V3(StorageManagerV3::new(file_name.clone(), options)?),
+            version => Err(create_custom_io_error(&format!("{} has unsupported storage format version {}", file_name, version)))?,
+        };
+        Ok(VersionedStorageEngine {
+            file_name,
+            storage_manager,
+        })
     }
 }
 
+// Copies every row to a new file under the same ids and swaps it in, the old file is left alone if this fails
+fn migrate<S: StorageEngine>(
+    old: &mut S,
+    file_name: &str,
+    version: u32,
+    options: StorageOptions,
+    roots: impl Fn(&mut S) -> io::Result<Vec<u32>>,
+) -> io::Result<StorageManagerV3> {
+    let migrating_file = format!("{}.migrating", file_name);
+    let _ = fs::remove_file(&migrating_file);
+    let mut new = StorageManagerV3::new(migrating_file.clone(), options)?;
+    old.start_read_session()?;
+    let rows = roots(old)?;
+    for root in &rows {
+        let data = old.read_data(*root)?;
+        new.write_data(data, Some(*root))?;
+    }
+    old.end_session();
+    new.flush()?;
+    drop(new);
+    fs::rename(&migrating_file, file_name)?;
+    info!(file = %file_name, from = version, to = V3_FORMAT_VERSION, rows = rows.len(), "Migrated storage file");
+    StorageManagerV3::new(file_name.to_string(), options)
+}
@@ -75,4 +75,82 @@ impl Table for MultiSourceTable {
             MultiSourceTable::InHouse(table) => table.get_input_tables()
         }
     }
-}
\ No newline at end of file
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.flush()
+        }
+    }
+
+    fn scan(&mut self) -> std::io::Result<Vec<crate::types::Entry>> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.scan()
+        }
+    }
+
+    fn verify(&mut self) -> std::io::Result<Vec<String>> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.verify()
+        }
+    }
+
+    fn rebuild_indexes(&mut self) -> std::io::Result<usize> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.rebuild_indexes()
+        }
+    }
+
+    fn start_copy(&mut self, destination: &str) -> std::io::Result<Vec<(String, crate::storage::buffer_pool::PageCopy)>> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.start_copy(destination)
+        }
+    }
+
+    fn delete_storage(&mut self) -> std::io::Result<()> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.delete_storage()
+        }
+    }
+
+    fn storage_stats(&mut self) -> std::io::Result<crate::storage::storage_engine::StorageStats> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.storage_stats()
+        }
+    }
+
+    fn compact(&mut self) -> std::io::Result<(u64, u64)> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.compact()
+        }
+    }
+
+    fn storage_files(&mut self) -> Vec<String> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.storage_files()
+        }
+    }
+
+    fn move_storage(&mut self, path: &str, replaced: &[String]) -> std::io::Result<()> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.move_storage(path, replaced)
+        }
+    }
+
+    fn get_columns(&mut self) -> Vec<Column> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.get_columns()
+        }
+    }
+
+    fn add_column(&mut self, column: Column) -> std::io::Result<()> {
+        match self {
+            MultiSourceTable::InHouse(table) => table.add_column(column)
+        }
+    }
+
+    fn get_table_type(&mut self) -> &TableType {
+        match self {
+            MultiSourceTable::InHouse(table) => table.get_table_type()
+        }
+    }
+}
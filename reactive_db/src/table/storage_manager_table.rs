@@ -1,16 +1,23 @@
 use crate::constants::{BTREE_NODE_SIZE, ROW_ID_COLUMN_NAME};
-use crate::storage::{storage_engine::StorageEngine, versioned_storage_engine::VersionedStorageEngine};
+use crate::storage::{buffer_pool::{self, PageCopy}, storage_engine::{StorageEngine, StorageKind, StorageOptions, StorageStats}, versioned_storage_engine::VersionedStorageEngine};
 use crate::types::create_custom_io_error;
 use crate::types::DataType;
 use crate::types::{Entry, EntryValue};
 use crate::BTree;
 use serde_json::Result;
 use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::path::Path;
 use uuid::Uuid;
 
 use super::{table_trait::Table, types::{Column, TableType}};
 
+// Rows with the block they're stored in
+type StoredRows = Vec<(u32, Entry)>;
+
 pub struct StorageManagerTable {
     pub name: String,
     columns: HashMap<String, Column>,
@@ -20,6 +27,8 @@ pub struct StorageManagerTable {
     indexes: Vec<BTree>,
     entry_storage_manager: VersionedStorageEngine,
     path: String,
+    // Used for index files of columns found later on
+    options: StorageOptions,
 }
 
 impl Table for StorageManagerTable {
@@ -29,7 +38,7 @@ impl Table for StorageManagerTable {
             EntryValue::ID(Uuid::new_v4().to_hyphenated().to_string()),
         );
         self.entry_storage_manager.start_write_session()?;
-        let reserved_root = self.entry_storage_manager.allocate_block();
+        let reserved_root = self.entry_storage_manager.allocate_block()?;
         for (name, val) in &entry {
             match self.columns.get(name) {
                 Some(column) => {
@@ -235,6 +244,227 @@ impl Table for StorageManagerTable {
     fn get_input_tables(&mut self) -> &mut Vec<String> {
         &mut self.input_tables
     }
+
+    fn get_columns(&mut self) -> Vec<Column> {
+        let mut columns: Vec<Column> = self.columns.values().cloned().collect();
+        columns.sort_by(|a, b| a.name.cmp(&b.name));
+        columns
+    }
+
+    fn get_table_type(&mut self) -> &TableType {
+        &self.table_type
+    }
+
+    fn add_column(&mut self, column: Column) -> io::Result<()> {
+        if self.columns.contains_key(&column.name) {
+            return Err(create_custom_io_error(&format!("Table {} already has column {}", self.name, column.name)));
+        }
+        let path = self.path.clone();
+        self.create_new_index(column, &path)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.entry_storage_manager.flush()?;
+        for index in &mut self.indexes {
+            index.flush()?;
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self) -> io::Result<Vec<Entry>> {
+        let id_column = self.id_column()?;
+        let location_refs = self.indexes[id_column.index_loc].entries()?;
+        let mut output = vec![];
+        for location_ref in location_refs {
+            output.push(self.get_entry(location_ref.right_ref)?);
+        }
+        Ok(output)
+    }
+
+    fn verify(&mut self) -> io::Result<Vec<String>> {
+        let indexed_columns = self.indexed_columns();
+        let mut problems = vec![];
+        for column in &indexed_columns {
+            for problem in self.indexes[column.index_loc].check()? {
+                problems.push(format!("{}: index {} {}", self.name, column.name, problem));
+            }
+        }
+        let (rows, unreadable) = self.stored_rows()?;
+        problems.extend(unreadable);
+        let rows: HashMap<u32, Entry> = rows.into_iter().collect();
+        // Each index entry has to point at a stored row holding the indexed value
+        for column in &indexed_columns {
+            for node_entry in self.indexes[column.index_loc].entries()? {
+                let location = node_entry.right_ref;
+                let row = match rows.get(&location) {
+                    Some(row) => row,
+                    None => {
+                        problems.push(format!(
+                            "{}: index {} points at block {} which holds no readable row",
+                            self.name, column.name, location
+                        ));
+                        continue;
+                    }
+                };
+                let stored = match row.get(&column.name) {
+                    Some(value) => Some(value.to_index_value()?),
+                    None => None,
+                };
+                if stored.as_ref() != Some(&node_entry.index) {
+                    problems.push(format!(
+                        "{}: index {} has {:?} for block {} but the row holds {:?}",
+                        self.name, column.name, node_entry.index, location, stored
+                    ));
+                }
+            }
+        }
+        // And each stored row has to be findable through every index it has a value for
+        let mut locations: Vec<&u32> = rows.keys().collect();
+        locations.sort();
+        for location in locations {
+            let entry = &rows[location];
+            for column in &indexed_columns {
+                if let Some(value) = entry.get(&column.name) {
+                    let found = self.indexes[column.index_loc]
+                        .get_all(value.to_index_value()?)?
+                        .iter()
+                        .any(|node_entry| node_entry.right_ref == *location);
+                    if !found {
+                        problems.push(format!(
+                            "{}: row in block {} is missing from index {}",
+                            self.name, location, column.name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    fn rebuild_indexes(&mut self) -> io::Result<usize> {
+        self.flush()?;
+        // Rows that can't be read are left out, verify reports them
+        let (rows, _) = self.stored_rows()?;
+        let files: Vec<String> = self.file_names()[1..].to_vec();
+        if self.in_memory() {
+            self.indexes = self.build_indexes(&files, &rows)?;
+            return Ok(rows.len());
+        }
+        let compacting: Vec<String> = files.iter().map(|file| compacting_file_name(file)).collect();
+        for file in &compacting {
+            remove_if_present(file)?;
+        }
+        drop(self.build_indexes(&compacting, &rows)?);
+        self.replace_files(&files)?;
+        Ok(rows.len())
+    }
+
+    fn delete_storage(&mut self) -> io::Result<()> {
+        if self.in_memory() {
+            return Ok(());
+        }
+        fs::remove_file(self.entry_storage_manager.get_file_name())?;
+        for column in self.columns.values() {
+            if column.indexed {
+                fs::remove_file(index_file_name(&self.path, &self.name, &column.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Tables kept in memory have no files to copy
+    fn start_copy(&mut self, destination: &str) -> io::Result<Vec<(String, PageCopy)>> {
+        if self.in_memory() {
+            return Ok(vec![]);
+        }
+        let mut copies: Vec<(String, PageCopy)> = vec![];
+        for (position, file) in self.file_names().iter().enumerate() {
+            let name = Path::new(file).file_name().unwrap().to_string_lossy().to_string();
+            let target = Path::new(destination).join(&name).to_string_lossy().to_string();
+            let started = match position {
+                0 => self.entry_storage_manager.start_copy(&target),
+                _ => self.indexes[position - 1].start_copy(&target),
+            };
+            match started {
+                Ok(copy) => copies.push((name, copy)),
+                Err(e) => {
+                    for (_, copy) in &copies {
+                        buffer_pool::global().cancel_copy(copy);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(copies)
+    }
+
+    fn storage_stats(&mut self) -> io::Result<StorageStats> {
+        let mut stats = self.entry_storage_manager.stats()?;
+        for index in &mut self.indexes {
+            stats.add(index.storage_stats()?);
+        }
+        Ok(stats)
+    }
+
+    fn compact(&mut self) -> io::Result<(u64, u64)> {
+        self.flush()?;
+        let before = self.storage_stats()?.bytes;
+        // Memory gives back the space of deleted rows straight away, only emptied index nodes are left
+        if self.in_memory() {
+            self.rebuild_indexes()?;
+            return Ok((before, self.storage_stats()?.bytes));
+        }
+        // Rows that can't be read are left out, verify reports them
+        let (rows, _) = self.stored_rows()?;
+        let files = self.file_names();
+        for file in &files {
+            remove_if_present(&compacting_file_name(file))?;
+        }
+
+        // Rows get new ids in id order and the indexes are built from the new ids
+        let mut storage_manager = VersionedStorageEngine::new(compacting_file_name(&files[0]), self.options)?;
+        let mut renumbered = vec![];
+        storage_manager.start_write_session()?;
+        for (_, row) in rows {
+            let id = storage_manager.allocate_block()?;
+            storage_manager.write_data(serde_json::to_vec(&row)?, Some(id))?;
+            renumbered.push((id, row));
+        }
+        storage_manager.end_session();
+        storage_manager.flush()?;
+        drop(storage_manager);
+        let compacting: Vec<String> = files[1..].iter().map(|file| compacting_file_name(file)).collect();
+        drop(self.build_indexes(&compacting, &renumbered)?);
+        self.replace_files(&files)?;
+        Ok((before, self.storage_stats()?.bytes))
+    }
+
+    fn storage_files(&mut self) -> Vec<String> {
+        if self.in_memory() {
+            return vec![];
+        }
+        self.file_names()
+    }
+
+    fn move_storage(&mut self, path: &str, replaced: &[String]) -> io::Result<()> {
+        if self.in_memory() {
+            self.path = path.to_string();
+        } else {
+            self.flush()?;
+            let files = self.file_names();
+            self.path = path.to_string();
+            let moved = self.file_names();
+            for (file, target) in files.iter().zip(&moved) {
+                fs::rename(file, compacting_file_name(target))?;
+            }
+            self.replace_files(&moved)?;
+        }
+        let kept = self.storage_files();
+        for file in replaced.iter().filter(|file| !kept.contains(file)) {
+            remove_if_present(file)?;
+        }
+        Ok(())
+    }
 }
 
 impl StorageManagerTable {
@@ -243,16 +473,20 @@ impl StorageManagerTable {
         columns: Vec<Column>,
         table_type: TableType,
         file_path: String,
+        options: StorageOptions,
     ) -> io::Result<StorageManagerTable> {
+        if options.kind == StorageKind::File {
+            finish_compaction(&file_path, &table_name)?;
+        }
         let mut entry_storage_manager =
-            VersionedStorageEngine::new(format!("{}/{}.db", file_path, table_name))?;
+            VersionedStorageEngine::new(row_file_name(&file_path, &table_name), options)?;
         let mut indexes = vec![];
         let mut column_map = HashMap::new();
         for column in &columns {
             let mut column = column.clone();
             if column.data_type.is_indexible() {
-                let file_name = format!("{}/{}_{}.index", file_path, table_name, column.name);
-                let storage_manager = VersionedStorageEngine::new(file_name)?;
+                let file_name = index_file_name(&file_path, &table_name, &column.name);
+                let storage_manager = VersionedStorageEngine::new(file_name, options)?;
                 column.indexed = true;
                 column.index_loc = indexes.len();
                 indexes.push(BTree::new(BTREE_NODE_SIZE, Box::new(storage_manager))?);
@@ -261,33 +495,35 @@ impl StorageManagerTable {
         }
         match table_type {
             TableType::Derived(_) => {
-                entry_storage_manager.start_read_session()?;
-                match entry_storage_manager.read_data(2) {
-                    Ok(raw_entry) => {
+                // Derived tables learn their columns from the rows they get, so read one back
+                let first_row = match column_map.get(ROW_ID_COLUMN_NAME) {
+                    Some(column) if column.indexed => indexes[column.index_loc].first()?,
+                    _ => None,
+                };
+                if let Some(node_entry) = first_row {
+                    entry_storage_manager.start_read_session()?;
+                    if let Ok(raw_entry) = entry_storage_manager.read_data(node_entry.right_ref) {
                         entry_storage_manager.end_session();
                         let entry: Result<Entry> = serde_json::from_slice(raw_entry.as_slice());
-                        match entry {
-                            Ok(entry_unwrapped) => {
-                                for (column_name, value) in entry_unwrapped {
-                                    let data_type = get_data_type_of_entry(&value);
-                                    let mut column = Column::new(column_name.clone(), data_type);
-                                    if column.data_type.is_indexible() {
-                                        let file_name = format!(
-                                            "{}/{}_{}.index",
-                                            file_path, table_name, column.name
-                                        );
-                                        let storage_manager = VersionedStorageEngine::new(file_name)?;
-                                        column.indexed = true;
-                                        column.index_loc = indexes.len();
-                                        indexes.push(BTree::new(BTREE_NODE_SIZE, Box::new(storage_manager))?);
-                                    }
-                                    column_map.insert(column.name.clone(), column);
+                        if let Ok(entry_unwrapped) = entry {
+                            for (column_name, value) in entry_unwrapped {
+                                // Declared columns already have their index open
+                                if column_map.contains_key(&column_name) {
+                                    continue;
                                 }
+                                let data_type = get_data_type_of_entry(&value);
+                                let mut column = Column::new(column_name.clone(), data_type);
+                                if column.data_type.is_indexible() {
+                                    let file_name = index_file_name(&file_path, &table_name, &column.name);
+                                    let storage_manager = VersionedStorageEngine::new(file_name, options)?;
+                                    column.indexed = true;
+                                    column.index_loc = indexes.len();
+                                    indexes.push(BTree::new(BTREE_NODE_SIZE, Box::new(storage_manager))?);
+                                }
+                                column_map.insert(column.name.clone(), column);
                             }
-                            _ => {}
                         }
                     }
-                    Err(_) => {}
                 }
             }
             _ => {}
@@ -301,13 +537,14 @@ impl StorageManagerTable {
             indexes: indexes,
             entry_storage_manager: entry_storage_manager,
             path: file_path,
+            options,
         });
     }
 
     fn create_new_index(&mut self, mut column: Column, file_path: &String) -> io::Result<()> {
         if column.data_type.is_indexible() {
-            let file_name = format!("{}/{}_{}.index", file_path, self.name, column.name);
-            let storage_manager = VersionedStorageEngine::new(file_name)?;
+            let file_name = index_file_name(file_path, &self.name, &column.name);
+            let storage_manager = VersionedStorageEngine::new(file_name, self.options)?;
             column.indexed = true;
             column.index_loc = self.indexes.len();
             self.indexes
@@ -317,6 +554,88 @@ impl StorageManagerTable {
         return Ok(());
     }
 
+    fn indexed_columns(&self) -> Vec<Column> {
+        let mut indexed_columns: Vec<Column> = self.columns.values().filter(|c| c.indexed).cloned().collect();
+        indexed_columns.sort_by_key(|c| c.index_loc);
+        indexed_columns
+    }
+
+    // The row file followed by the index files in index order
+    fn file_names(&mut self) -> Vec<String> {
+        let mut files = vec![row_file_name(&self.path, &self.name)];
+        files.extend(self.indexed_columns().iter().map(|c| index_file_name(&self.path, &self.name, &c.name)));
+        files
+    }
+
+    // Every row in the row file with its id, and a problem for each one that can't be read
+    fn stored_rows(&mut self) -> io::Result<(StoredRows, Vec<String>)> {
+        self.entry_storage_manager.start_read_session()?;
+        let ids = self.entry_storage_manager.stored_ids();
+        self.entry_storage_manager.end_session();
+        let mut rows = vec![];
+        let mut problems = vec![];
+        for id in ids? {
+            match self.get_entry(id) {
+                Ok(entry) => rows.push((id, entry)),
+                Err(e) => problems.push(format!("{}: row in block {} is unreadable: {}", self.name, id, e)),
+            }
+        }
+        Ok((rows, problems))
+    }
+
+    // Opens new indexes under the given file names, in index order, and fills them from the rows and their ids
+    fn build_indexes(&self, files: &[String], rows: &[(u32, Entry)]) -> io::Result<Vec<BTree>> {
+        let mut indexes = vec![];
+        for file in files {
+            let index_storage = VersionedStorageEngine::new(file.clone(), self.options)?;
+            indexes.push(BTree::new(BTREE_NODE_SIZE, Box::new(index_storage))?);
+        }
+        for (id, row) in rows {
+            for column in self.indexed_columns() {
+                if let Some(value) = row.get(&column.name) {
+                    indexes[column.index_loc].insert(value.to_index_value()?, *id)?;
+                }
+            }
+        }
+        for index in &mut indexes {
+            index.flush()?;
+        }
+        Ok(indexes)
+    }
+
+    // Moves the new files written next to the given ones into place and opens the table's files again
+    fn replace_files(&mut self, files: &[String]) -> io::Result<()> {
+        // Once the marker is written the new files replace the old ones, even if the server dies part way
+        let marker = compaction_marker(&self.path, &self.name);
+        let mut marker_file = File::create(compacting_file_name(&marker))?;
+        marker_file.write_all(files.join("\n").as_bytes())?;
+        marker_file.sync_all()?;
+        fs::rename(compacting_file_name(&marker), &marker)?;
+        finish_compaction(&self.path, &self.name)?;
+
+        let files = self.file_names();
+        self.entry_storage_manager = VersionedStorageEngine::new(files[0].clone(), self.options)?;
+        let mut indexes = vec![];
+        for file in &files[1..] {
+            indexes.push(BTree::new(BTREE_NODE_SIZE, Box::new(VersionedStorageEngine::new(file.clone(), self.options)?))?);
+        }
+        self.indexes = indexes;
+        Ok(())
+    }
+
+    fn in_memory(&self) -> bool {
+        self.options.kind == StorageKind::Memory
+    }
+
+    fn id_column(&self) -> io::Result<Column> {
+        match self.columns.get(ROW_ID_COLUMN_NAME) {
+            Some(column) if column.indexed => Ok(column.clone()),
+            _ => Err(create_custom_io_error(
+                format!("Table {} has no {} index", self.name, ROW_ID_COLUMN_NAME).as_str(),
+            )),
+        }
+    }
+
     fn get_entry(&mut self, location_ref: u32) -> io::Result<Entry>{
         self.entry_storage_manager.start_read_session()?;
         let raw_entry = self
@@ -331,6 +650,48 @@ impl StorageManagerTable {
     }
 }
 
+fn row_file_name(file_path: &str, table_name: &str) -> String {
+    format!("{}/{}.db", file_path, table_name)
+}
+
+fn index_file_name(file_path: &str, table_name: &str, column_name: &str) -> String {
+    format!("{}/{}_{}.index", file_path, table_name, column_name)
+}
+
+fn compacting_file_name(file_name: &str) -> String {
+    format!("{}.compacting", file_name)
+}
+
+// Lists the files a compaction is moving into place
+fn compaction_marker(file_path: &str, table_name: &str) -> String {
+    format!("{}/{}.compaction", file_path, table_name)
+}
+
+fn remove_if_present(file_name: &str) -> io::Result<()> {
+    match fs::remove_file(file_name) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Moves every compacted file listed in the marker over the file it replaces. Without a marker any compacted
+// files are from a compaction that didn't finish writing them and are left for the next one to remove
+fn finish_compaction(file_path: &str, table_name: &str) -> io::Result<()> {
+    let marker = compaction_marker(file_path, table_name);
+    let files = match fs::read_to_string(&marker) {
+        Ok(files) => files,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for file in files.lines() {
+        if Path::new(&compacting_file_name(file)).exists() {
+            fs::rename(compacting_file_name(file), file)?;
+        }
+    }
+    File::open(file_path)?.sync_all()?;
+    fs::remove_file(marker)
+}
+
 fn get_data_type_of_entry(entry: &EntryValue) -> DataType {
     return match entry {
         EntryValue::Array(data) => DataType::Array(Box::new(get_data_type_of_entry(&data[0]))),
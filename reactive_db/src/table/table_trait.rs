@@ -1,7 +1,11 @@
 use std::io;
 
+use crate::storage::buffer_pool::PageCopy;
+use crate::storage::storage_engine::StorageStats;
 use crate::types::{Entry, EntryValue};
 
+use super::types::{Column, TableType};
+
 pub trait Table{
     fn insert(&mut self, entry: Entry) -> io::Result<Option<Entry>>;
 
@@ -39,4 +43,41 @@ pub trait Table{
     fn get_output_tables(&mut self) -> &mut Vec<String>;
 
     fn get_input_tables(&mut self) -> &mut Vec<String>;
+
+    fn get_columns(&mut self) -> Vec<Column>;
+
+    // Adds a column rows stored so far don't have, indexing it from now on if its type can be indexed
+    fn add_column(&mut self, column: Column) -> io::Result<()>;
+
+    fn get_table_type(&mut self) -> &TableType;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    // Every row in the table, ordered by row id
+    fn scan(&mut self) -> io::Result<Vec<Entry>>;
+
+    // Describes every place the indexes and stored rows disagree, empty when they're consistent
+    fn verify(&mut self) -> io::Result<Vec<String>>;
+
+    // Replaces every index with one built from the stored rows, returns how many rows it indexed
+    fn rebuild_indexes(&mut self) -> io::Result<usize>;
+
+    // Starts copying every file of the table into the directory as it is now, returns each copy with its file name
+    fn start_copy(&mut self, destination: &str) -> io::Result<Vec<(String, PageCopy)>>;
+
+    // Removes the table's files, it can't be used afterwards
+    fn delete_storage(&mut self) -> io::Result<()>;
+
+    // Summed over the row storage and every index
+    fn storage_stats(&mut self) -> io::Result<StorageStats>;
+
+    // Rewrites the rows and indexes into densely packed files, returns the bytes they took before and after
+    fn compact(&mut self) -> io::Result<(u64, u64)>;
+
+    // Files the rows and indexes are kept in, none for tables kept in memory
+    fn storage_files(&mut self) -> Vec<String>;
+
+    // Moves the table's files into the directory the way compaction puts its files in place, then removes
+    // the files of the table it replaces that weren't written over
+    fn move_storage(&mut self, path: &str, replaced: &[String]) -> io::Result<()>;
 }
\ No newline at end of file
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::btree::node::IndexValue;
+    use crate::storage::storage_engine::{StorageEngine, StorageOptions};
+    use crate::storage::versioned_storage_engine::VersionedStorageEngine;
+    use crate::BTree;
+
+    fn open(file_name: &str) -> BTree {
+        let storage_manager = VersionedStorageEngine::new(file_name.to_string(), StorageOptions::default()).unwrap();
+        BTree::new(4, Box::new(storage_manager)).unwrap()
+    }
+
+    #[test]
+    fn deletes_the_entry_of_the_given_row() {
+        let file_name = "test_results/btree_deletes.index";
+        let _ = fs::remove_file(file_name);
+        let mut tree = open(file_name);
+        for reference in 1..=40 {
+            tree.insert(IndexValue::Integer(reference as isize), reference).unwrap();
+        }
+        // Rows 41 to 43 share a value with row 20
+        for reference in 41..=43 {
+            tree.insert(IndexValue::Integer(20), reference).unwrap();
+        }
+        assert_eq!(tree.delete(IndexValue::Integer(20), Some(42), false).unwrap().len(), 0);
+        let mut references: Vec<u32> = tree.get_all(IndexValue::Integer(20)).unwrap().iter().map(|entry| entry.right_ref).collect();
+        references.sort();
+        assert_eq!(references, vec![20, 41, 43]);
+        assert_eq!(tree.delete(IndexValue::Integer(20), None, true).unwrap().len(), 3);
+        assert!(tree.search_exact(IndexValue::Integer(20)).unwrap().is_none());
+        assert_eq!(tree.search_exact(IndexValue::Integer(21)).unwrap().unwrap().right_ref, 21);
+    }
+
+    #[test]
+    fn emptied_leaves_stay_in_the_leaf_chain() {
+        let file_name = "test_results/btree_emptied_leaves.index";
+        let _ = fs::remove_file(file_name);
+        let mut tree = open(file_name);
+        for reference in 1..=100 {
+            tree.insert(IndexValue::Integer(reference as isize), reference).unwrap();
+        }
+        // Empties every leaf holding values 20 to 60
+        for reference in 20..=60 {
+            tree.delete(IndexValue::Integer(reference as isize), Some(reference), false).unwrap();
+        }
+        let references: Vec<u32> = tree.entries().unwrap().iter().map(|entry| entry.right_ref).collect();
+        let expected: Vec<u32> = (1..20).chain(61..=100).collect();
+        assert_eq!(references, expected);
+        let greater: Vec<u32> = tree.greater_than(IndexValue::Integer(30)).unwrap().iter().map(|entry| entry.right_ref).collect();
+        assert_eq!(greater, (61..=100).collect::<Vec<u32>>());
+        assert!(tree.search_exact(IndexValue::Integer(40)).unwrap().is_none());
+        assert_eq!(tree.search_exact(IndexValue::Integer(61)).unwrap().unwrap().right_ref, 61);
+    }
+
+    #[test]
+    fn deletes_keep_the_tree_sound() {
+        let file_name = "test_results/btree_sound_deletes.index";
+        let _ = fs::remove_file(file_name);
+        let mut tree = open(file_name);
+        // Every value is in the tree six times, so equal values span leaves
+        for reference in 1..=300 {
+            tree.insert(IndexValue::Integer(reference as isize % 50), reference).unwrap();
+        }
+        for reference in (1..=300).filter(|reference| reference % 3 != 0) {
+            let value = IndexValue::Integer(reference as isize % 50);
+            assert_eq!(tree.delete(value, Some(reference), false).unwrap().len(), 0);
+        }
+        assert_eq!(tree.check().unwrap(), Vec::<String>::new());
+        for value in 0..50 {
+            let mut references: Vec<u32> = tree.get_all(IndexValue::Integer(value)).unwrap()
+                .iter()
+                .map(|entry| entry.right_ref)
+                .collect();
+            references.sort();
+            let expected: Vec<u32> = (1..=300).filter(|reference| reference % 50 == value as u32 && reference % 3 == 0).collect();
+            assert_eq!(references, expected);
+        }
+        assert_eq!(tree.delete(IndexValue::Integer(0), None, true).unwrap().len(), 2);
+        assert!(tree.search_exact(IndexValue::Integer(0)).unwrap().is_none());
+        assert!(tree.search_exact(IndexValue::Integer(1)).unwrap().is_some());
+        assert_eq!(tree.check().unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_a_broken_leaf_chain_and_stray_blocks() {
+        let file_name = "test_results/btree_broken.index";
+        let _ = fs::remove_file(file_name);
+        {
+            let mut tree = open(file_name);
+            for reference in 1..=100 {
+                tree.insert(IndexValue::Integer(reference as isize), reference).unwrap();
+            }
+            assert!(tree.check().unwrap().is_empty());
+            tree.flush().unwrap();
+        }
+        {
+            // Cut the chain after the leftmost leaf and leave a block nothing points at
+            let mut storage_manager = VersionedStorageEngine::new(file_name.to_string(), StorageOptions::default()).unwrap();
+            storage_manager.start_write_session().unwrap();
+            let mut node: serde_json::Value = serde_json::from_slice(&storage_manager.read_data(1).unwrap()).unwrap();
+            while node["leaf"] == false {
+                let child = node["entries"][0]["left_ref"].as_u64().unwrap() as u32;
+                let mut leaf: serde_json::Value = serde_json::from_slice(&storage_manager.read_data(child).unwrap()).unwrap();
+                if leaf["leaf"] == true {
+                    leaf["next_node"] = 0.into();
+                    storage_manager.write_data(serde_json::to_vec(&leaf).unwrap(), Some(child)).unwrap();
+                }
+                node = leaf;
+            }
+            storage_manager.write_data(b"stray".to_vec(), None).unwrap();
+            storage_manager.end_session();
+            storage_manager.flush().unwrap();
+        }
+        let problems = open(file_name).check().unwrap();
+        assert!(problems.iter().any(|problem| problem.starts_with("leaf chain")), "{:?}", problems);
+        assert!(problems.iter().any(|problem| problem.contains("aren't reachable")), "{:?}", problems);
+    }
+}
@@ -1,18 +1,29 @@
 #[cfg(test)]
 mod tests {
-    use crate::{config::config_reader::{DbConfig, read_config_file}};
+    use crate::{config::config_reader::{DbConfig, TableConfig, read_config_file}};
     use crate::database::database_manager::DatabaseManager;
     use crate::Entry;
     use crate::EntryValue;
+    use crate::storage::storage_engine::StorageKind;
     use rand::Rng;
     use std::collections::BTreeMap;
     use std::fs;
+    use std::path::Path;
+    use std::thread;
+    // Keeps every table in memory, so nothing is left behind in the data directory
     fn get_db(data_destination: String) -> DatabaseManager {
+        let mut config:DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage = Some(StorageKind::Memory);
+
+        DatabaseManager::from_config(config, data_destination).unwrap()
+    }
+
+    fn get_file_db(data_destination: String) -> DatabaseManager {
         let _ = fs::remove_dir_all(data_destination.clone());
         let _ = fs::create_dir(data_destination.clone());
         let config:DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
-        
-        DatabaseManager::from_config(config, data_destination.clone()).unwrap()
+
+        DatabaseManager::from_config(config, data_destination).unwrap()
     }
 
     #[test]
@@ -33,15 +44,15 @@ mod tests {
             if n < 5 {
                 entries.push(entry_to_insert.build());
             }
-            let (temp_dbm, results) = dbm.insert_entry(
+            let results = dbm.insert_entry(
                 &"testTable".to_string(),
                 entry_to_insert.build(),
                 None);
             results.unwrap();
-            dbm = temp_dbm;
         }
         // Test source
         let results = dbm
+            .reader()
             .find_one(
                 &"testTable".to_string(),
                 "testForIteration".to_string(),
@@ -61,6 +72,7 @@ mod tests {
         print!("{:?}", results.get("_entryId").unwrap());
         // Test derived
         let results = dbm
+            .reader()
             .find_one(
                 &"derived".to_string(),
                 "_sourceEntryId".to_string(),
@@ -78,6 +90,7 @@ mod tests {
         }
 
         let results = dbm
+            .reader()
             .less_than_search(
                 &"testTable".to_string(),
                 "testForIteration".to_string(),
@@ -110,12 +123,12 @@ mod tests {
             if n < 5 {
                 entries.push(entry_to_insert.build());
             }
-            let (temp_dbm, results) = dbm.insert_entry(&"testTable".to_string(), entry_to_insert.build(), None);
+            let results = dbm.insert_entry("testTable", entry_to_insert.build(), None);
             results.unwrap();
-            dbm = temp_dbm;
         }
         // Test source
         let results = dbm
+            .reader()
             .less_than_search(
                 &"testTable".to_string(),
                 "testForIteration".to_string(),
@@ -148,12 +161,12 @@ mod tests {
             if n >= 10 {
                 entries.push(entry_to_insert.build());
             }
-            let (temp_dbm, result) = dbm.insert_entry(&"testTable".to_string(), entry_to_insert.build(), None);
-            dbm = temp_dbm;
+            let result = dbm.insert_entry("testTable", entry_to_insert.build(), None);
             result.unwrap();
         }
         // Test source
         let results = dbm
+            .reader()
             .greater_than_search(
                 &"testTable".to_string(),
                 "testForIteration".to_string(),
@@ -172,6 +185,118 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reads_from_other_threads_during_writes() {
+        let mut dbm = get_db("db/test4".to_string());
+        let reader = dbm.reader();
+        let reading_thread = thread::spawn(move || {
+            // Every read sees a whole number of inserts, never a half written entry
+            let mut last_seen = 0;
+            while last_seen < 20 {
+                let results = reader
+                    .less_than_search("testTable", "testForIteration".to_string(), EntryValue::Integer(100))
+                    .unwrap();
+                assert!(results.len() >= last_seen);
+                for result in &results {
+                    assert!(result.get("testForIndex").is_some());
+                }
+                last_seen = results.len();
+            }
+        });
+        for n in 0..20 {
+            let mut entry_to_insert = EntryBuilder::new();
+            entry_to_insert.column("testForIteration", EntryValue::Integer(n));
+            entry_to_insert.column("testForIndex", EntryValue::Integer(n));
+            dbm.insert_entry("testTable", entry_to_insert.build(), None).unwrap();
+        }
+        reading_thread.join().unwrap();
+    }
+
+    #[test]
+    fn closes_transactions_that_apply_no_edits() {
+        let mut dbm = get_db("db/test_transactions".to_string());
+        // The filter keeps none of these, so nothing is written
+        for n in 0..3 {
+            let mut entry_to_insert = EntryBuilder::new();
+            entry_to_insert.column("testForIteration", EntryValue::Integer(n));
+            entry_to_insert.column("testForIndex", EntryValue::Integer(n + 20));
+            assert!(dbm.insert_entry("filterTest", entry_to_insert.build(), Some("testTable")).unwrap().is_empty());
+        }
+        dbm.delete_all("testTable", "testForIndex".to_string(), EntryValue::Integer(1)).unwrap();
+        // Nothing is left open for the shutdown to roll back
+        assert_eq!(dbm.shutdown().unwrap(), 0);
+    }
+
+    #[test]
+    fn refuses_to_start_with_a_cycle() {
+        let config: DbConfig = serde_yaml::from_str("
+storage_destination: db/test_cycle
+tables:
+  - Derived:
+      name: loop
+      transform_definition:
+        FilterTransform:
+          source_table: loop
+          filter: age > 17
+").unwrap();
+        let error = DatabaseManager::from_config(config, "db/test_cycle".to_string()).err().unwrap();
+        assert!(error.contains("loop -> loop"));
+    }
+
+    #[test]
+    fn locks_the_storage_directory() {
+        let dbm = get_file_db("db/test_lock".to_string());
+        let config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        let error = DatabaseManager::from_config(config.clone(), "db/test_lock".to_string()).err().unwrap();
+        assert!(error.contains("in use by another process"));
+
+        drop(dbm);
+        assert!(DatabaseManager::from_config(config, "db/test_lock".to_string()).is_ok());
+    }
+
+    #[test]
+    fn keeps_memory_tables_out_of_the_data_directory() {
+        let destination = "db/test_memory".to_string();
+        let _ = fs::remove_dir_all(&destination);
+        let _ = fs::create_dir(&destination);
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        for table in config.tables.iter_mut() {
+            if let TableConfig::Source(source) = table {
+                if source.name == "testTable" {
+                    source.storage = Some(StorageKind::Memory);
+                }
+            }
+        }
+        let mut dbm = DatabaseManager::from_config(config.clone(), destination.clone()).unwrap();
+        for n in 0..10 {
+            let mut entry_to_insert = EntryBuilder::new();
+            entry_to_insert.column("testForIteration", EntryValue::Integer(n));
+            entry_to_insert.column("testForIndex", EntryValue::Integer(n));
+            dbm.insert_entry("testTable", entry_to_insert.build(), None).unwrap();
+        }
+        let found = dbm.reader().less_than_search("testTable", "testForIteration".to_string(), EntryValue::Integer(100)).unwrap();
+        assert_eq!(found.len(), 10);
+        let written: Vec<String> = fs::read_dir(&destination).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert!(!written.iter().any(|name| name.starts_with("testTable")));
+        assert!(written.iter().any(|name| name.starts_with("users")));
+
+        // Rows kept in memory don't survive a restart
+        drop(dbm);
+        let dbm = DatabaseManager::from_config(config, destination).unwrap();
+        let found = dbm.reader().less_than_search("testTable", "testForIteration".to_string(), EntryValue::Integer(100)).unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn keeps_every_table_in_memory_when_the_database_says_so() {
+        let destination = "db/test_all_memory".to_string();
+        let _ = fs::remove_dir_all(&destination);
+        let _dbm = get_db(destination.clone());
+        assert!(!Path::new(&destination).exists());
+    }
+
     #[derive(Clone)]
     pub struct EntryBuilder {
         map: Entry,
@@ -0,0 +1,300 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+    use tokio::sync::mpsc::{channel, Receiver, Sender};
+    use uuid::Uuid;
+
+    use crate::auth::{AccessControl, Principal};
+    use crate::config::config_reader::{DbConfig, DurabilityConfig, DurabilityMode, read_config_file};
+    use crate::commands::snapshot::restore_snapshot;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::database::db_thread::start_db_thread;
+    use crate::database::durability::Commits;
+    use crate::networking::types::{DBRequest, DBResponse, GetData, InsertData, ListenEvent, ListenRequest, Query, QueryRequest, RequestResponse, ToClientMessage};
+    use crate::EntryValue;
+
+    #[test]
+    fn drains_requests_and_closes_clients_on_shutdown() {
+        let _ = fs::remove_dir_all("db/test_shutdown");
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage_destination = "db/test_shutdown".to_string();
+        let (request_sender, request_reciever) = channel(10);
+        let (response_channel_sender, response_channel_reciever) = channel(10);
+        let db_thread = thread::spawn(move || {
+            start_db_thread(request_reciever, response_channel_reciever, config, "test_cfg.yaml".to_string(), Arc::new(AccessControl::new(None)))
+        });
+
+        let client_id = Uuid::new_v4();
+        let idle_client_id = Uuid::new_v4();
+        let (result_sender, mut result_reciever) = channel(10);
+        let (idle_result_sender, mut idle_result_reciever) = channel(10);
+        response_channel_sender.blocking_send((result_sender, client_id, Principal::anonymous())).unwrap();
+        response_channel_sender.blocking_send((idle_result_sender, idle_client_id, Principal::anonymous())).unwrap();
+        let mut entry = BTreeMap::new();
+        entry.insert("testForIteration".to_string(), EntryValue::Integer(1));
+        entry.insert("testForIndex".to_string(), EntryValue::Integer(1));
+        let request_id = Uuid::new_v4();
+        let insert = Query::InsertData(InsertData { table: "testTable".to_string(), entry });
+        request_sender.blocking_send((DBRequest::Query(QueryRequest { request_id, query: insert }), client_id)).unwrap();
+        // Closing every sender is what a shutdown looks like to the db thread
+        drop(request_sender);
+        drop(response_channel_sender);
+
+        db_thread.join().unwrap().unwrap();
+        match result_reciever.blocking_recv() {
+            Some(ToClientMessage::RequestResponse(response)) => {
+                assert_eq!(response.request_id, request_id);
+                assert!(matches!(response.response, DBResponse::ManyResults(Ok(_))));
+            }
+            _ => panic!("Expected the insert to be answered before closing"),
+        }
+        assert!(matches!(result_reciever.blocking_recv(), Some(ToClientMessage::Close(_))));
+        assert!(matches!(idle_result_reciever.blocking_recv(), Some(ToClientMessage::Close(_))));
+    }
+
+    #[test]
+    fn refuses_listens_on_missing_tables_and_keeps_serving() {
+        let _ = fs::remove_dir_all("db/test_missing_listen");
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage_destination = "db/test_missing_listen".to_string();
+        let (request_sender, request_reciever) = channel(10);
+        let (response_channel_sender, response_channel_reciever) = channel(10);
+        let db_thread = thread::spawn(move || {
+            start_db_thread(request_reciever, response_channel_reciever, config, "test_cfg.yaml".to_string(), Arc::new(AccessControl::new(None)))
+        });
+        let client_id = Uuid::new_v4();
+        let (result_sender, mut result_reciever) = channel(10);
+        response_channel_sender.blocking_send((result_sender, client_id, Principal::anonymous())).unwrap();
+
+        let listen = ListenRequest { table_name: "missing".to_string(), event: ListenEvent::Insert };
+        request_sender.blocking_send((DBRequest::StartListen(listen), client_id)).unwrap();
+        match result_reciever.blocking_recv() {
+            Some(ToClientMessage::ProtocolError(error)) => {
+                assert!(error.request_id.is_none());
+                assert!(error.message.contains("missing"));
+            }
+            _ => panic!("Expected the listen to be refused"),
+        }
+        let insert = insert(1);
+        let request_id = insert.request_id;
+        request_sender.blocking_send((DBRequest::Query(insert), client_id)).unwrap();
+        assert!(matches!(wait_for(&mut result_reciever, &mut HashMap::new(), request_id), DBResponse::ManyResults(Ok(_))));
+        drop(request_sender);
+        drop(response_channel_sender);
+        db_thread.join().unwrap().unwrap();
+    }
+
+    fn answers_writes(mode: DurabilityMode, destination: &str) {
+        let _ = fs::remove_dir_all(destination);
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage_destination = destination.to_string();
+        config.durability = Some(DurabilityConfig { mode, interval_ms: Some(5) });
+        let (request_sender, request_reciever) = channel(10);
+        let (response_channel_sender, response_channel_reciever) = channel(10);
+        let db_thread = thread::spawn(move || {
+            start_db_thread(request_reciever, response_channel_reciever, config, "test_cfg.yaml".to_string(), Arc::new(AccessControl::new(None)))
+        });
+
+        let client_id = Uuid::new_v4();
+        let (result_sender, mut result_reciever) = channel(10);
+        response_channel_sender.blocking_send((result_sender, client_id, Principal::anonymous())).unwrap();
+        let mut request_ids = vec![];
+        for n in 0..3 {
+            let mut entry = BTreeMap::new();
+            entry.insert("testForIteration".to_string(), EntryValue::Integer(n));
+            entry.insert("testForIndex".to_string(), EntryValue::Integer(n));
+            let request_id = Uuid::new_v4();
+            let insert = Query::InsertData(InsertData { table: "testTable".to_string(), entry });
+            request_sender.blocking_send((DBRequest::Query(QueryRequest { request_id, query: insert }), client_id)).unwrap();
+            request_ids.push(request_id);
+        }
+        // Answered while the server keeps running, periodic mode mustn't wait for more writes or a shutdown
+        for request_id in request_ids {
+            match result_reciever.blocking_recv() {
+                Some(ToClientMessage::RequestResponse(response)) => {
+                    assert_eq!(response.request_id, request_id);
+                    assert!(matches!(response.response, DBResponse::ManyResults(Ok(_))));
+                }
+                _ => panic!("Expected the insert to be answered"),
+            }
+        }
+        drop(request_sender);
+        drop(response_channel_sender);
+        db_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn answers_writes_after_syncing_each_one() {
+        answers_writes(DurabilityMode::PerCommit, "db/test_per_commit");
+    }
+
+    #[test]
+    fn answers_writes_after_a_periodic_sync() {
+        answers_writes(DurabilityMode::Periodic, "db/test_periodic");
+    }
+
+    fn commits(mode: DurabilityMode, destination: &str) -> (DatabaseManager, Commits) {
+        let _ = fs::remove_dir_all(destination);
+        fs::create_dir(destination).unwrap();
+        let config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        let dbm = DatabaseManager::from_config(config, destination.to_string()).unwrap();
+        (dbm, Commits::new(Some(DurabilityConfig { mode, interval_ms: Some(20) })))
+    }
+
+    fn write(dbm: &mut DatabaseManager, commits: &mut Commits, sender: &Sender<ToClientMessage>, n: isize) {
+        let mut entry = BTreeMap::new();
+        entry.insert("testForIteration".to_string(), EntryValue::Integer(n));
+        entry.insert("testForIndex".to_string(), EntryValue::Integer(n));
+        dbm.insert_entry("testTable", entry, None).unwrap();
+        let response = RequestResponse { request_id: Uuid::new_v4(), response: DBResponse::ManyResults(Ok(vec![])) };
+        commits.answer(dbm, sender.clone(), response);
+    }
+
+    #[test]
+    fn syncs_each_write_before_answering_it() {
+        let (mut dbm, mut commits) = commits(DurabilityMode::PerCommit, "db/test_per_commit_syncs");
+        let (sender, mut reciever) = channel(10);
+        for n in 0..3 {
+            write(&mut dbm, &mut commits, &sender, n);
+            assert_eq!(commits.syncs, n as usize + 1);
+            assert!(matches!(reciever.try_recv(), Ok(ToClientMessage::RequestResponse(_))));
+        }
+    }
+
+    #[test]
+    fn syncs_writes_within_an_interval_once() {
+        let (mut dbm, mut commits) = commits(DurabilityMode::Periodic, "db/test_periodic_syncs");
+        let (sender, mut reciever) = channel(10);
+        for n in 0..3 {
+            write(&mut dbm, &mut commits, &sender, n);
+        }
+        // Nothing is synced or answered before the interval is up
+        assert_eq!(commits.syncs, 0);
+        assert!(reciever.try_recv().is_err());
+
+        let (jobs, job_reciever) = mpsc::channel();
+        let next_job = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            jobs.send(()).unwrap();
+        });
+        assert_eq!(commits.next(&mut dbm, &job_reciever), Some(()));
+        next_job.join().unwrap();
+        assert_eq!(commits.syncs, 1);
+        for _ in 0..3 {
+            assert!(matches!(reciever.try_recv(), Ok(ToClientMessage::RequestResponse(_))));
+        }
+        // With no writes waiting there is nothing to sync
+        commits.sync(&mut dbm);
+        assert_eq!(commits.syncs, 1);
+    }
+
+    fn insert(n: isize) -> QueryRequest {
+        let mut entry = BTreeMap::new();
+        entry.insert("testForIteration".to_string(), EntryValue::Integer(n));
+        entry.insert("testForIndex".to_string(), EntryValue::Integer(n));
+        let query = Query::InsertData(InsertData { table: "testTable".to_string(), entry });
+        QueryRequest { request_id: Uuid::new_v4(), query }
+    }
+
+    #[test]
+    fn reads_see_writes_sent_before_them_on_the_same_connection() {
+        let _ = fs::remove_dir_all("db/test_read_your_writes");
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage_destination = "db/test_read_your_writes".to_string();
+        let (request_sender, request_reciever) = channel(100);
+        let (response_channel_sender, response_channel_reciever) = channel(10);
+        let db_thread = thread::spawn(move || {
+            start_db_thread(request_reciever, response_channel_reciever, config, "test_cfg.yaml".to_string(), Arc::new(AccessControl::new(None)))
+        });
+        let client_id = Uuid::new_v4();
+        let (result_sender, mut result_reciever) = channel(100);
+        response_channel_sender.blocking_send((result_sender, client_id, Principal::anonymous())).unwrap();
+
+        // Each read is sent straight after its write without waiting for the write's answer
+        let mut reads = vec![];
+        for n in 0..20 {
+            request_sender.blocking_send((DBRequest::Query(insert(n)), client_id)).unwrap();
+            let find = Query::FindOne(GetData { table: "testTable".to_string(), column: "testForIndex".to_string(), key: EntryValue::Integer(n) });
+            let request_id = Uuid::new_v4();
+            request_sender.blocking_send((DBRequest::Query(QueryRequest { request_id, query: find }), client_id)).unwrap();
+            reads.push(request_id);
+        }
+        let mut answers = HashMap::new();
+        for request_id in reads {
+            assert!(matches!(wait_for(&mut result_reciever, &mut answers, request_id), DBResponse::OneResult(Ok(Some(_)))));
+        }
+        drop(request_sender);
+        drop(response_channel_sender);
+        db_thread.join().unwrap().unwrap();
+    }
+
+    // Answers can arrive out of order, the ones not waited for yet are kept
+    fn wait_for(reciever: &mut Receiver<ToClientMessage>, answers: &mut HashMap<Uuid, DBResponse>, request_id: Uuid) -> DBResponse {
+        loop {
+            if let Some(response) = answers.remove(&request_id) {
+                return response;
+            }
+            match reciever.blocking_recv() {
+                Some(ToClientMessage::RequestResponse(response)) => answers.insert(response.request_id, response.response),
+                _ => panic!("Expected an answer to {}", request_id),
+            };
+        }
+    }
+
+    #[test]
+    fn snapshots_the_tables_as_of_the_request_while_writes_go_on() {
+        let _ = fs::remove_dir_all("db/test_snapshot");
+        let _ = fs::remove_dir_all("db/test_snapshot_copy");
+        let _ = fs::remove_dir_all("db/test_snapshot_restored");
+        let mut config: DbConfig = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        config.storage_destination = "db/test_snapshot".to_string();
+        let server_config = config.clone();
+        let (request_sender, request_reciever) = channel(10);
+        let (response_channel_sender, response_channel_reciever) = channel(10);
+        let db_thread = thread::spawn(move || {
+            start_db_thread(request_reciever, response_channel_reciever, server_config, "test_cfg.yaml".to_string(), Arc::new(AccessControl::new(None)))
+        });
+        let client_id = Uuid::new_v4();
+        let (result_sender, mut result_reciever) = channel(10);
+        response_channel_sender.blocking_send((result_sender, client_id, Principal::anonymous())).unwrap();
+        let mut answers = HashMap::new();
+        let send = |query_request: QueryRequest| {
+            let request_id = query_request.request_id;
+            request_sender.blocking_send((DBRequest::Query(query_request), client_id)).unwrap();
+            request_id
+        };
+        for n in 0..200 {
+            let request_id = send(insert(n));
+            wait_for(&mut result_reciever, &mut answers, request_id);
+        }
+
+        let snapshot_id = send(QueryRequest { request_id: Uuid::new_v4(), query: Query::Snapshot("db/test_snapshot_copy".to_string()) });
+        // Applied while the pages are being copied, but after the snapshot was taken
+        for n in 200..300 {
+            let request_id = send(insert(n));
+            wait_for(&mut result_reciever, &mut answers, request_id);
+        }
+        // The directory is taken by the first snapshot
+        let taken_id = send(QueryRequest { request_id: Uuid::new_v4(), query: Query::Snapshot("db/test_snapshot_copy".to_string()) });
+        assert!(matches!(wait_for(&mut result_reciever, &mut answers, taken_id), DBResponse::ManyResults(Err(_))));
+        let files = match wait_for(&mut result_reciever, &mut answers, snapshot_id) {
+            DBResponse::ManyResults(Ok(files)) => files,
+            _ => panic!("Expected the snapshot to be complete"),
+        };
+        assert!(files.iter().any(|file| file.get("file") == Some(&EntryValue::Str("testTable.db".to_string()))));
+        drop(request_sender);
+        drop(response_channel_sender);
+        db_thread.join().unwrap().unwrap();
+
+        config.storage_destination = "db/test_snapshot_restored".to_string();
+        assert_eq!(restore_snapshot(config.clone(), "db/test_snapshot_copy").unwrap(), files.len());
+        // Only into an empty data directory
+        assert!(restore_snapshot(config.clone(), "db/test_snapshot_copy").is_err());
+        let dbm = DatabaseManager::from_config(config, "db/test_snapshot_restored".to_string()).unwrap();
+        assert_eq!(dbm.reader().scan("testTable").unwrap().len(), 200);
+    }
+}
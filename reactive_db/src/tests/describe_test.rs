@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::config_reader::read_config_file;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::database::describe::to_dot;
+    use crate::types::{DataType, EntryValue};
+
+    #[test]
+    fn describes_tables_as_wired_at_runtime() {
+        let _ = fs::remove_dir_all("db/test_describe");
+        fs::create_dir("db/test_describe").unwrap();
+        let config = read_config_file("test_cfg.yaml".to_string()).unwrap();
+        let dbm = DatabaseManager::from_config(config, "db/test_describe".to_string()).unwrap();
+        let tables = dbm.describe_graph().unwrap();
+
+        let position = |name: &str| tables.iter().position(|table| table.name == name).unwrap();
+        assert!(position("testTable") < position("filterTest"));
+        let test_table = &tables[position("testTable")];
+        assert_eq!(test_table.table_type, "Source");
+        assert_eq!(test_table.transform, None);
+        assert_eq!(test_table.outputs, vec!["derived".to_string(), "filterTest".to_string()]);
+        assert_eq!(test_table.columns.get("testForIndex"), Some(&DataType::Integer));
+
+        let filter = &tables[position("filterTest")];
+        assert_eq!(filter.table_type, "Derived");
+        assert!(filter.transform.as_ref().unwrap().contains("FilterTransform"));
+        assert_eq!(filter.inputs, vec!["testTable".to_string()]);
+        assert_eq!(filter.to_entry().get("table"), Some(&EntryValue::Str("filterTest".to_string())));
+
+        let dot = to_dot(&tables);
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("\"testTable\" -> \"filterTest\";"));
+        assert!(dot.contains("\"users\" [shape=box];"));
+    }
+}
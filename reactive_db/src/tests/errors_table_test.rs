@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::config_check::check_config;
+    use crate::config::config_reader::DbConfig;
+    use crate::constants::{FAILED_ENTRY, FAILED_SOURCE_TABLE, ROW_ID_COLUMN_NAME, SOURCE_ENTRY_ID};
+    use crate::database::database_manager::{DatabaseManager, TableChange};
+    use crate::types::EntryValue;
+    use crate::Entry;
+
+    const TABLES: &str = "
+  - Source:
+      name: bonusPeople
+      columns:
+        name: Str
+        bonus: Integer
+  - Derived:
+      name: bonusDoubled
+      transform_definition:
+        FunctionTransform:
+          source_table: bonusPeople
+          functions:
+            - name ~ name
+            - doubled ~ bonus * 2
+";
+
+    fn setup(dir: &str) -> DatabaseManager {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let config: DbConfig = serde_yaml::from_str(&format!("storage_destination: {}\ntables:{}", dir, TABLES)).unwrap();
+        DatabaseManager::from_config(config, dir.to_string()).unwrap()
+    }
+
+    fn person(name: &str, bonus: Option<isize>) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("name".to_string(), EntryValue::Str(name.to_string()));
+        if let Some(bonus) = bonus {
+            entry.insert("bonus".to_string(), EntryValue::Integer(bonus));
+        }
+        entry
+    }
+
+    fn change(table: &str, change: &str, rows: usize) -> TableChange {
+        TableChange { table: table.to_string(), change: change.to_string(), rows }
+    }
+
+    #[test]
+    fn keeps_failed_rows_and_retries_them() {
+        let mut dbm = setup("db/test_errors_retry");
+        dbm.insert_entry("bonusPeople", person("ada", Some(5)), None).unwrap();
+        let source = dbm.insert_entry("bonusPeople", person("bob", None), None).unwrap();
+        let source_id = source[0].entry.get(ROW_ID_COLUMN_NAME).unwrap().clone();
+
+        assert_eq!(dbm.reader().scan("bonusDoubled").unwrap().len(), 1);
+        let failures = dbm.reader().scan("bonusDoubled_errors").unwrap();
+        assert_eq!(failures.len(), 1);
+        let failure = &failures[0];
+        assert_eq!(failure.get(SOURCE_ENTRY_ID), Some(&source_id));
+        assert_eq!(failure.get(FAILED_SOURCE_TABLE), Some(&EntryValue::Str("bonusPeople".to_string())));
+        assert_eq!(failure.get("transform"), Some(&EntryValue::Str("Function".to_string())));
+        match failure.get("error") {
+            Some(EntryValue::Str(error)) => assert!(error.contains("bonus")),
+            other => panic!("Expected an error message, got {:?}", other),
+        }
+        assert!(matches!(failure.get("failedAt"), Some(EntryValue::Integer(time)) if *time > 0));
+        let mut failed_entry = match failure.get(FAILED_ENTRY) {
+            Some(EntryValue::Map(entry)) => entry.clone(),
+            other => panic!("Expected the failed row, got {:?}", other),
+        };
+        assert_eq!(failed_entry.get("name"), Some(&EntryValue::Str("bob".to_string())));
+
+        // Nothing was fixed, so the row fails again and stays
+        let changes = dbm.retry_failed("bonusDoubled").unwrap();
+        assert_eq!(changes, vec![change("bonusDoubled", "retried", 0), change("bonusDoubled_errors", "still failing", 1)]);
+
+        // Fix the stored row and try again
+        let failure = dbm.reader().scan("bonusDoubled_errors").unwrap().remove(0);
+        dbm.delete_all("bonusDoubled_errors", ROW_ID_COLUMN_NAME.to_string(), failure.get(ROW_ID_COLUMN_NAME).unwrap().clone()).unwrap();
+        failed_entry.insert("bonus".to_string(), EntryValue::Integer(7));
+        let mut fixed = failure.clone();
+        fixed.remove(ROW_ID_COLUMN_NAME);
+        fixed.insert(FAILED_ENTRY.to_string(), EntryValue::Map(failed_entry));
+        dbm.insert_entry("bonusDoubled_errors", fixed, None).unwrap();
+
+        let changes = dbm.retry_failed("bonusDoubled").unwrap();
+        assert_eq!(changes, vec![change("bonusDoubled", "retried", 1), change("bonusDoubled_errors", "still failing", 0)]);
+        let doubled = dbm.reader().get_all("bonusDoubled", "name".to_string(), EntryValue::Str("bob".to_string())).unwrap();
+        assert_eq!(doubled[0].get("doubled"), Some(&EntryValue::Integer(14)));
+        assert!(dbm.reader().scan("bonusDoubled_errors").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_failed_rows_a_retry_could_not_insert() {
+        let mut dbm = setup("db/test_errors_retry_kept");
+        dbm.insert_entry("bonusPeople", person("bob", None), None).unwrap();
+        let failure = dbm.reader().scan("bonusDoubled_errors").unwrap().remove(0);
+        dbm.delete_all("bonusDoubled_errors", ROW_ID_COLUMN_NAME.to_string(), failure.get(ROW_ID_COLUMN_NAME).unwrap().clone()).unwrap();
+        // Booleans can't be indexed, so inserting the transformed row fails rather than the transform
+        let mut unstorable = failure.clone();
+        unstorable.remove(ROW_ID_COLUMN_NAME);
+        let mut failed_entry = match failure.get(FAILED_ENTRY) {
+            Some(EntryValue::Map(entry)) => entry.clone(),
+            other => panic!("Expected the failed row, got {:?}", other),
+        };
+        failed_entry.insert("bonus".to_string(), EntryValue::Integer(7));
+        failed_entry.insert("name".to_string(), EntryValue::Bool(true));
+        unstorable.insert(FAILED_ENTRY.to_string(), EntryValue::Map(failed_entry));
+        dbm.insert_entry("bonusDoubled_errors", unstorable, None).unwrap();
+
+        assert!(dbm.retry_failed("bonusDoubled").is_err());
+        assert_eq!(dbm.reader().scan("bonusDoubled_errors").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn forgets_failures_of_deleted_rows() {
+        let mut dbm = setup("db/test_errors_delete");
+        dbm.insert_entry("bonusPeople", person("bob", None), None).unwrap();
+        assert_eq!(dbm.reader().scan("bonusDoubled_errors").unwrap().len(), 1);
+
+        dbm.delete_all("bonusPeople", "name".to_string(), EntryValue::Str("bob".to_string())).unwrap();
+        assert!(dbm.reader().scan("bonusDoubled_errors").unwrap().is_empty());
+
+        assert!(dbm.retry_failed("bonusPeople").is_err());
+        assert!(dbm.retry_failed("missing").is_err());
+    }
+
+    #[test]
+    fn reserves_the_names_of_errors_tables() {
+        let config: DbConfig = serde_yaml::from_str(&format!("storage_destination: db/\ntables:{}
+  - Source:
+      name: bonusDoubled_errors
+      columns:
+        name: Str
+", TABLES)).unwrap();
+        let error = check_config(&config).err().unwrap();
+        assert!(error.contains("bonusDoubled_errors is reserved"));
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::config_reader::DbConfig;
+    use crate::constants::{AGGREGATION_KEY, ROW_ID_COLUMN_NAME, SOURCE_ENTRY_IDS, UNION_MATCHING_KEY};
+    use crate::database::database::DatabaseReader;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::database::lineage::{Direction, LineageRow, lineage};
+    use crate::table::{table_trait::Table, types::Column};
+    use crate::types::{DataType, EntryValue};
+    use crate::Entry;
+
+    const TABLES: &str = "
+  - Source:
+      name: users
+      columns:
+        name: Str
+        age: Integer
+  - Source:
+      name: grades
+      columns:
+        name: Str
+        grade: Integer
+  - Derived:
+      name: profiles
+      transform_definition:
+        UnionTransform:
+          tables_and_foreign_keys:
+          - - users
+            - name
+          - - grades
+            - name
+  - Derived:
+      name: totals
+      transform_definition:
+        AggregationTransform:
+          source_table: grades
+          aggregated_column: name
+          functions:
+            - sum ~ memo.sum + grade
+  - Derived:
+      name: honours
+      transform_definition:
+        FilterTransform:
+          source_table: grades
+          filter: grade > 80
+  - Derived:
+      name: topHonours
+      transform_definition:
+        FilterTransform:
+          source_table: honours
+          filter: grade > 95
+";
+
+    fn get_db() -> DatabaseManager {
+        let _ = fs::remove_dir_all("db/test_lineage");
+        fs::create_dir("db/test_lineage").unwrap();
+        let config: DbConfig = serde_yaml::from_str(&format!("storage_destination: db/test_lineage\ntables:{}", TABLES)).unwrap();
+        let dbm = DatabaseManager::from_config(config, "db/test_lineage".to_string()).unwrap();
+        // Unions and aggregations replace their rows by key, which needs the key column before the first row has it
+        for (table, key) in [("profiles", UNION_MATCHING_KEY), ("totals", AGGREGATION_KEY)].iter() {
+            let table = dbm.reader().get_table(table).unwrap();
+            table.lock().unwrap().add_column(Column::new(key.to_string(), DataType::Str)).unwrap();
+        }
+        dbm
+    }
+
+    fn row(name: &str, column: &str, value: isize) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("name".to_string(), EntryValue::Str(name.to_string()));
+        entry.insert(column.to_string(), EntryValue::Integer(value));
+        entry
+    }
+
+    fn id_where(reader: &DatabaseReader, table: &str, column: &str, value: EntryValue) -> EntryValue {
+        let entry = reader.get_all(table, column.to_string(), value).unwrap().remove(0);
+        entry.get(ROW_ID_COLUMN_NAME).unwrap().clone()
+    }
+
+    fn tables_in(rows: &[LineageRow], direction: Direction) -> Vec<String> {
+        let mut tables: Vec<String> = rows.iter()
+            .filter(|row| row.direction == direction)
+            .map(|row| row.table.clone())
+            .collect();
+        tables.sort();
+        tables
+    }
+
+    #[test]
+    fn walks_downstream_from_a_source_row() {
+        let mut dbm = get_db();
+        dbm.insert_entry("users", row("alice", "age", 20), None).unwrap();
+        dbm.insert_entry("grades", row("alice", "grade", 90), None).unwrap();
+        dbm.insert_entry("grades", row("alice", "grade", 97), None).unwrap();
+        dbm.insert_entry("grades", row("bob", "grade", 50), None).unwrap();
+        let reader = dbm.reader();
+
+        let grade = id_where(&reader, "grades", "grade", EntryValue::Integer(97));
+        let rows = lineage(&reader, "grades", grade.clone()).unwrap();
+        assert_eq!(rows[0].direction, Direction::Origin);
+        assert_eq!(rows[0].entry.get(ROW_ID_COLUMN_NAME), Some(&grade));
+        assert!(tables_in(&rows, Direction::Upstream).is_empty());
+        assert_eq!(tables_in(&rows, Direction::Downstream), vec!["honours", "profiles", "topHonours", "totals"]);
+
+        let bob = id_where(&reader, "grades", "grade", EntryValue::Integer(50));
+        let rows = lineage(&reader, "grades", bob).unwrap();
+        assert_eq!(tables_in(&rows, Direction::Downstream), vec!["profiles", "totals"]);
+    }
+
+    #[test]
+    fn unions_and_aggregations_lead_back_to_every_contributing_row() {
+        let mut dbm = get_db();
+        dbm.insert_entry("users", row("alice", "age", 20), None).unwrap();
+        dbm.insert_entry("grades", row("alice", "grade", 90), None).unwrap();
+        dbm.insert_entry("grades", row("alice", "grade", 97), None).unwrap();
+        let reader = dbm.reader();
+
+        let total = reader.get_all("totals", "aggregatedColumn".to_string(), EntryValue::Str("alice".to_string())).unwrap().remove(0);
+        match total.get(SOURCE_ENTRY_IDS) {
+            Some(EntryValue::Array(ids)) => assert_eq!(ids.len(), 2),
+            other => panic!("Expected the contributing ids, got {:?}", other),
+        }
+        let rows = lineage(&reader, "totals", total.get(ROW_ID_COLUMN_NAME).unwrap().clone()).unwrap();
+        assert_eq!(tables_in(&rows, Direction::Upstream), vec!["grades", "grades"]);
+
+        let profile = id_where(&reader, "profiles", "matchingKey", EntryValue::Str("alice".to_string()));
+        let rows = lineage(&reader, "profiles", profile).unwrap();
+        assert_eq!(tables_in(&rows, Direction::Upstream), vec!["grades", "grades", "users"]);
+        assert_eq!(rows[1].to_entry().get("direction"), Some(&EntryValue::Str("Upstream".to_string())));
+    }
+
+    #[test]
+    fn unknown_entries_are_an_error() {
+        let dbm = get_db();
+        assert!(lineage(&dbm.reader(), "grades", EntryValue::ID("missing".to_string())).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::Value;
+    use tracing::{error, info, info_span};
+
+    use crate::config::config_reader::{DbConfig, LogFormat, LoggingConfig};
+    use crate::logging::subscriber;
+
+    fn read_lines(path: &str) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn writes_json_with_spans_and_timing_to_a_file() {
+        let path = "db/test_logging.json";
+        let _ = fs::remove_file(path);
+        let config = LoggingConfig { level: Some("info".to_string()), format: Some(LogFormat::Json), file: Some(path.to_string()) };
+        tracing::subscriber::with_default(subscriber(Some(&config)).unwrap(), || {
+            let span = info_span!("transform", table = "totals");
+            let _entered = span.enter();
+            error!(error = "Division by zero", "Transform failed");
+        });
+
+        let lines = read_lines(path);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "ERROR");
+        assert_eq!(lines[0]["fields"]["message"], "Transform failed");
+        assert_eq!(lines[0]["fields"]["error"], "Division by zero");
+        assert_eq!(lines[0]["span"]["table"], "totals");
+        assert_eq!(lines[1]["fields"]["message"], "close");
+        assert!(lines[1]["fields"]["time.busy"].is_string());
+    }
+
+    #[test]
+    fn filters_by_level() {
+        let path = "db/test_logging_level.json";
+        let _ = fs::remove_file(path);
+        let config = LoggingConfig { level: Some("warn".to_string()), format: Some(LogFormat::Json), file: Some(path.to_string()) };
+        tracing::subscriber::with_default(subscriber(Some(&config)).unwrap(), || {
+            info!("Dropped");
+            error!("Kept");
+        });
+
+        let lines = read_lines(path);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["fields"]["message"], "Kept");
+    }
+
+    #[test]
+    fn reads_logging_from_the_config() {
+        let config: DbConfig = serde_yaml::from_str("
+storage_destination: db/
+tables: []
+logging:
+  level: reactive_db=debug
+  format: Json
+  file: server.log
+").unwrap();
+        let logging = config.logging.unwrap();
+        assert_eq!(logging.format, Some(LogFormat::Json));
+        assert_eq!(logging.file, Some("server.log".to_string()));
+
+        let invalid = LoggingConfig { level: Some("reactive_db=loud".to_string()), format: None, file: None };
+        assert!(subscriber(Some(&invalid)).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::config::config_reader::DbConfig;
+    use crate::database::database_manager::DatabaseManager;
+    use crate::metrics::{self, Metrics, REQUESTS, REQUEST_DURATION};
+    use crate::types::EntryValue;
+    use crate::Entry;
+
+    const TABLES: &str = "
+  - Source:
+      name: metricsPeople
+      columns:
+        name: Str
+        age: Integer
+  - Derived:
+      name: metricsAdults
+      transform_definition:
+        FilterTransform:
+          source_table: metricsPeople
+          filter: age > 17
+  - Derived:
+      name: metricsBroken
+      transform_definition:
+        FunctionTransform:
+          source_table: metricsPeople
+          functions:
+            - doubled ~ missingColumn * 2
+";
+
+    fn person(age: isize) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("name".to_string(), EntryValue::Str(format!("age {}", age)));
+        entry.insert("age".to_string(), EntryValue::Integer(age));
+        entry
+    }
+
+    fn value_of(output: &str, sample: &str) -> Option<f64> {
+        output.lines()
+            .find(|line| line.starts_with(sample) && line[sample.len()..].starts_with(' '))
+            .map(|line| line[sample.len() + 1..].parse().unwrap())
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.increment(REQUESTS, &[("query", "FindOne")], 2);
+        metrics.increment(REQUESTS, &[("query", "Say \"hi\"")], 1);
+        metrics.observe(REQUEST_DURATION, &[("query", "FindOne")], Duration::from_millis(3));
+        metrics.observe(REQUEST_DURATION, &[("query", "FindOne")], Duration::from_secs(10));
+        let output = metrics.render();
+
+        assert!(output.contains("# TYPE reactivedb_requests_total counter\n"));
+        assert_eq!(value_of(&output, "reactivedb_requests_total{query=\"FindOne\"}"), Some(2.0));
+        assert_eq!(value_of(&output, "reactivedb_requests_total{query=\"Say \\\"hi\\\"\"}"), Some(1.0));
+        assert_eq!(value_of(&output, "reactivedb_request_duration_seconds_bucket{query=\"FindOne\",le=\"0.001\"}"), Some(0.0));
+        assert_eq!(value_of(&output, "reactivedb_request_duration_seconds_bucket{query=\"FindOne\",le=\"0.005\"}"), Some(1.0));
+        assert_eq!(value_of(&output, "reactivedb_request_duration_seconds_bucket{query=\"FindOne\",le=\"5\"}"), Some(1.0));
+        assert_eq!(value_of(&output, "reactivedb_request_duration_seconds_bucket{query=\"FindOne\",le=\"+Inf\"}"), Some(2.0));
+        assert_eq!(value_of(&output, "reactivedb_request_duration_seconds_count{query=\"FindOne\"}"), Some(2.0));
+    }
+
+    #[test]
+    fn counts_rows_transforms_and_storage() {
+        let _ = fs::remove_dir_all("db/test_metrics");
+        fs::create_dir("db/test_metrics").unwrap();
+        let config: DbConfig = serde_yaml::from_str(&format!("storage_destination: db/test_metrics\ntables:{}", TABLES)).unwrap();
+        let mut dbm = DatabaseManager::from_config(config, "db/test_metrics".to_string()).unwrap();
+        for age in [12, 18, 40].iter() {
+            dbm.insert_entry("metricsPeople", person(*age), None).unwrap();
+        }
+        dbm.delete_all("metricsPeople", "age".to_string(), EntryValue::Integer(12)).unwrap();
+
+        let output = metrics::global().render();
+        assert_eq!(value_of(&output, "reactivedb_rows_inserted_total{table=\"metricsPeople\"}"), Some(3.0));
+        assert_eq!(value_of(&output, "reactivedb_rows_inserted_total{table=\"metricsAdults\"}"), Some(2.0));
+        assert_eq!(value_of(&output, "reactivedb_rows_deleted_total{table=\"metricsPeople\"}"), Some(1.0));
+        assert_eq!(value_of(&output, "reactivedb_transform_executions_total{table=\"metricsAdults\",transform=\"Filter\"}"), Some(3.0));
+        assert_eq!(value_of(&output, "reactivedb_transform_failures_total{table=\"metricsBroken\",transform=\"Function\"}"), Some(3.0));
+        assert_eq!(value_of(&output, "reactivedb_transform_failures_total{table=\"metricsAdults\",transform=\"Filter\"}"), None);
+
+        let metrics = Metrics::default();
+        metrics.watch_tables(dbm.reader());
+        let output = metrics.render();
+        assert!(value_of(&output, "reactivedb_storage_bytes{table=\"metricsPeople\"}").unwrap() > 0.0);
+        assert!(value_of(&output, "reactivedb_block_cache_hits_total{table=\"metricsPeople\"}").unwrap() > 0.0);
+        let ratio = value_of(&output, "reactivedb_block_cache_hit_ratio{table=\"metricsPeople\"}").unwrap();
+        assert!(ratio > 0.0 && ratio <= 1.0);
+    }
+}
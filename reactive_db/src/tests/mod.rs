@@ -1 +1,9 @@
 pub mod database_test;
+pub mod db_thread_test;
+pub mod reload_test;
+pub mod describe_test;
+pub mod lineage_test;
+pub mod logging_test;
+pub mod metrics_test;
+pub mod errors_table_test;
+pub mod btree_test;
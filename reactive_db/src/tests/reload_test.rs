@@ -0,0 +1,271 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use tokio::sync::mpsc::{channel, Receiver};
+    use uuid::Uuid;
+
+    use crate::config::config_reader::DbConfig;
+    use crate::database::database_manager::{DatabaseManager, TableChange};
+    use crate::hooks::listener_hook::NewListenerObj;
+    use crate::networking::types::{ListenEvent, ToClientMessage};
+    use crate::storage::storage_engine::StorageKind;
+    use crate::table::table_trait::Table;
+    use crate::types::EntryValue;
+    use crate::Entry;
+
+    const PEOPLE: &str = "
+  - Source:
+      name: people
+      columns:
+        name: Str
+        age: Integer
+";
+
+    fn config(tables: &str) -> DbConfig {
+        serde_yaml::from_str(&format!("storage_destination: db/test_reload\ntables:{}", tables)).unwrap()
+    }
+
+    fn filter(name: &str, source: &str, filter: &str) -> String {
+        format!("
+  - Derived:
+      name: {}
+      transform_definition:
+        FilterTransform:
+          source_table: {}
+          filter: {}
+", name, source, filter)
+    }
+
+    fn person(age: isize) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("name".to_string(), EntryValue::Str(format!("age {}", age)));
+        entry.insert("age".to_string(), EntryValue::Integer(age));
+        entry
+    }
+
+    fn change(table: &str, change: &str, rows: usize) -> TableChange {
+        TableChange { table: table.to_string(), change: change.to_string(), rows }
+    }
+
+    fn listen(dbm: &mut DatabaseManager, table: &str) -> Receiver<ToClientMessage> {
+        let (sender, receiver) = channel(10);
+        dbm.add_listener(NewListenerObj { uuid: Uuid::new_v4(), channel: sender, event: ListenEvent::Insert }, table).unwrap();
+        receiver
+    }
+
+    #[test]
+    fn backfills_new_and_changed_tables() {
+        let _ = fs::remove_dir_all("db/test_reload");
+        fs::create_dir("db/test_reload").unwrap();
+        let adults = filter("adults", "people", "age > 17");
+        let mut dbm = DatabaseManager::from_config(config(&format!("{}{}", PEOPLE, adults)), "db/test_reload".to_string()).unwrap();
+        for age in [12, 18, 40, 70].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+
+        // seniors reads from the changed adults table, so it is filled as adults is recomputed
+        let tables = format!("{}{}{}", PEOPLE, filter("adults", "people", "age > 30"), filter("seniors", "adults", "age > 64"));
+        let changes = dbm.reload(config(&tables), &mut |_, _| {}).unwrap();
+        assert!(changes.contains(&change("adults", "recomputed", 2)));
+        assert!(changes.contains(&change("seniors", "created", 1)));
+
+        let changes = dbm.reload(config(&format!("{}{}", PEOPLE, filter("adults", "people", "age > 30"))), &mut |_, _| {}).unwrap();
+        assert_eq!(changes, vec![change("seniors", "dropped", 0)]);
+        assert!(dbm.reader().scan("seniors").is_err());
+        assert!(!Path::new("db/test_reload/seniors.db").exists());
+        // Inserts still reach the tables that stayed
+        dbm.insert_entry("people", person(50), None).unwrap();
+        assert_eq!(dbm.reader().scan("adults").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn extends_source_tables_in_place() {
+        let _ = fs::remove_dir_all("db/test_reload_extended");
+        fs::create_dir("db/test_reload_extended").unwrap();
+        let mut dbm = DatabaseManager::from_config(config(PEOPLE), "db/test_reload_extended".to_string()).unwrap();
+        for age in [12, 40].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+        let extended = "
+  - Source:
+      name: people
+      columns:
+        name: Str
+        age: Integer
+        city: Str
+";
+        let changes = dbm.reload(config(extended), &mut |_, _| {}).unwrap();
+        assert_eq!(changes, vec![change("people", "extended", 2)]);
+
+        let mut entry = person(25);
+        entry.insert("city".to_string(), EntryValue::Str("Oslo".to_string()));
+        dbm.insert_entry("people", entry, None).unwrap();
+        assert_eq!(dbm.reader().scan("people").unwrap().len(), 3);
+        let found = dbm.reader().get_all("people", "city".to_string(), EntryValue::Str("Oslo".to_string())).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get("age"), Some(&EntryValue::Integer(25)));
+    }
+
+    #[test]
+    fn rejects_incompatible_changes_without_touching_tables() {
+        let _ = fs::remove_dir_all("db/test_reload_rejected");
+        fs::create_dir("db/test_reload_rejected").unwrap();
+        let mut dbm = DatabaseManager::from_config(config(PEOPLE), "db/test_reload_rejected".to_string()).unwrap();
+        let retyped = "
+  - Source:
+      name: people
+      columns:
+        name: Str
+        age: Str
+";
+        let error = dbm.reload(config(&format!("{}{}", retyped, filter("adults", "people", "age > 17"))), &mut |_, _| {}).err().unwrap();
+        assert!(error.contains("age"));
+        assert!(dbm.reader().scan("adults").is_err());
+        assert!(dbm.reader().scan("people").unwrap().is_empty());
+
+        let mut durable = config(PEOPLE);
+        durable.durability = serde_yaml::from_str("mode: per-commit").unwrap();
+        assert!(dbm.reload(durable, &mut |_, _| {}).err().unwrap().contains("Durability"));
+        let mut in_memory = config(PEOPLE);
+        in_memory.storage = Some(StorageKind::Memory);
+        assert!(dbm.reload(in_memory, &mut |_, _| {}).err().unwrap().contains("storage"));
+        assert_eq!(dbm.reload(config(PEOPLE), &mut |_, _| {}).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn rebuilds_derived_tables_and_their_outputs() {
+        let _ = fs::remove_dir_all("db/test_rebuild");
+        fs::create_dir("db/test_rebuild").unwrap();
+        let tables = format!("{}{}{}", PEOPLE, filter("adults", "people", "age > 17"), filter("seniors", "adults", "age > 64"));
+        let mut dbm = DatabaseManager::from_config(config(&tables), "db/test_rebuild".to_string()).unwrap();
+        for age in [12, 18, 40, 70].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+        // Knock the derived tables out of sync with people
+        dbm.delete_all("adults", "age".to_string(), EntryValue::Integer(70)).unwrap();
+        assert_eq!(dbm.reader().scan("seniors").unwrap().len(), 0);
+
+        let mut reported = vec![];
+        let reader = dbm.reader();
+        let changes = dbm.rebuild("adults", &mut |table, _| {
+            // Readers get the running tables until the rebuilt ones are filled
+            reported.push((table.to_string(), reader.scan("adults").unwrap().len(), reader.scan("seniors").unwrap().len()));
+        }).unwrap();
+        assert_eq!(changes, vec![change("adults", "rebuilt", 3), change("seniors", "rebuilt", 1)]);
+        assert_eq!(reported, vec![("adults".to_string(), 2, 0), ("seniors".to_string(), 2, 0)]);
+        assert_eq!(reader.scan("seniors").unwrap().len(), 1);
+        assert!(dbm.rebuild("people", &mut |_, _| {}).is_err());
+        assert!(dbm.rebuild("missing", &mut |_, _| {}).is_err());
+    }
+
+    #[test]
+    fn rebuilt_tables_keep_their_files_and_listeners() {
+        let _ = fs::remove_dir_all("db/test_rebuild_listeners");
+        fs::create_dir("db/test_rebuild_listeners").unwrap();
+        let tables = format!("{}{}{}", PEOPLE, filter("adults", "people", "age > 17"), filter("seniors", "adults", "age > 64"));
+        let mut dbm = DatabaseManager::from_config(config(&tables), "db/test_rebuild_listeners".to_string()).unwrap();
+        for age in [12, 18, 70].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+        let mut seniors = listen(&mut dbm, "seniors");
+        dbm.rebuild("adults", &mut |_, _| {}).unwrap();
+        assert!(seniors.try_recv().is_err());
+        assert!(!Path::new("db/test_rebuild_listeners/staging").exists());
+
+        dbm.insert_entry("people", person(80), None).unwrap();
+        assert!(matches!(seniors.try_recv(), Ok(ToClientMessage::Event(event)) if event.table_name == "seniors"));
+        dbm.sync().unwrap();
+        drop(dbm);
+        // The rebuilt rows are the ones in the table's files
+        let dbm = DatabaseManager::from_config(config(&tables), "db/test_rebuild_listeners".to_string()).unwrap();
+        assert_eq!(dbm.reader().scan("adults").unwrap().len(), 3);
+        assert_eq!(dbm.reader().scan("seniors").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn keeps_listeners_of_recomputed_tables() {
+        let _ = fs::remove_dir_all("db/test_reload_listeners");
+        fs::create_dir("db/test_reload_listeners").unwrap();
+        let tables = format!("{}{}{}", PEOPLE, filter("adults", "people", "age > 17"), filter("seniors", "people", "age > 64"));
+        let mut dbm = DatabaseManager::from_config(config(&tables), "db/test_reload_listeners".to_string()).unwrap();
+        dbm.insert_entry("people", person(40), None).unwrap();
+        let mut adults = listen(&mut dbm, "adults");
+        let mut seniors = listen(&mut dbm, "seniors");
+
+        let changes = dbm.reload(config(&format!("{}{}", PEOPLE, filter("adults", "people", "age > 30"))), &mut |_, _| {}).unwrap();
+        assert!(changes.contains(&change("adults", "recomputed", 1)));
+        // The replayed rows aren't sent, only rows inserted afterwards
+        assert!(adults.try_recv().is_err());
+        dbm.insert_entry("people", person(50), None).unwrap();
+        assert!(matches!(adults.try_recv(), Ok(ToClientMessage::Event(event)) if event.table_name == "adults"));
+        assert!(matches!(seniors.try_recv(), Ok(ToClientMessage::ProtocolError(error)) if error.message.contains("seniors")));
+        assert!(!Path::new("db/test_reload_listeners/staging").exists());
+    }
+
+    #[test]
+    fn serves_the_running_tables_while_a_reload_fills() {
+        let _ = fs::remove_dir_all("db/test_reload_reads");
+        fs::create_dir("db/test_reload_reads").unwrap();
+        let tables = format!("{}{}{}", PEOPLE, filter("adults", "people", "age > 17"), filter("seniors", "people", "age > 64"));
+        let mut dbm = DatabaseManager::from_config(config(&tables), "db/test_reload_reads".to_string()).unwrap();
+        for age in [12, 40, 70].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+        let reader = dbm.reader();
+        let mut seen = vec![];
+        let new_tables = format!("{}{}", PEOPLE, filter("adults", "people", "age > 50"));
+        dbm.reload(config(&new_tables), &mut |_, _| {
+            seen.push((reader.scan("adults").unwrap().len(), reader.scan("seniors").unwrap().len()));
+        }).unwrap();
+        assert_eq!(seen, vec![(2, 1)]);
+        assert_eq!(reader.scan("adults").unwrap().len(), 1);
+        assert!(reader.scan("seniors").is_err());
+        assert!(!Path::new("db/test_reload_reads/seniors.db").exists());
+    }
+
+    #[test]
+    fn keeps_running_tables_when_a_reload_fails() {
+        let _ = fs::remove_dir_all("db/test_reload_failed");
+        fs::create_dir("db/test_reload_failed").unwrap();
+        let tables = format!("{}{}", PEOPLE, filter("adults", "people", "age > 17"));
+        let mut dbm = DatabaseManager::from_config(config(&tables), "db/test_reload_failed".to_string()).unwrap();
+        for age in [12, 40].iter() {
+            dbm.insert_entry("people", person(*age), None).unwrap();
+        }
+        let mut adults = listen(&mut dbm, "adults");
+
+        // Booleans can't be indexed, so filling the new adults table fails part way
+        let failing = "
+  - Derived:
+      name: adults
+      transform_definition:
+        FunctionTransform:
+          source_table: people
+          functions:
+            - adult ~ age > 17
+";
+        let extended = "
+  - Source:
+      name: people
+      columns:
+        name: Str
+        age: Integer
+        city: Str
+";
+        let error = dbm.reload(config(&format!("{}{}{}", extended, failing, filter("kids", "people", "age < 18"))), &mut |_, _| {}).err().unwrap();
+        assert!(error.contains("Bool"));
+        let people = dbm.reader().get_table("people").unwrap();
+        assert!(!people.lock().unwrap().get_columns().iter().any(|column| column.name == "city"));
+        assert!(dbm.reader().scan("kids").is_err());
+        assert!(!Path::new("db/test_reload_failed/kids.db").exists());
+        assert!(!Path::new("db/test_reload_failed/staging").exists());
+        assert_eq!(dbm.reader().scan("adults").unwrap().len(), 1);
+        dbm.insert_entry("people", person(50), None).unwrap();
+        assert_eq!(dbm.reader().scan("adults").unwrap().len(), 2);
+        assert!(matches!(adults.try_recv(), Ok(ToClientMessage::Event(_))));
+        // The config the tables were built from is still the one reloads are compared against
+        assert_eq!(dbm.reload(config(&tables), &mut |_, _| {}).unwrap(), vec![]);
+    }
+}
@@ -3,9 +3,11 @@ use std::collections::VecDeque;
 use std::collections::HashMap;
 
 pub struct MaxSizeHashMap<K, V> {
-    map: HashMap<K, (V, usize)>,
+    map: HashMap<K, V>,
     inserts: VecDeque<K>,
-    size: usize
+    size: usize,
+    hits: u64,
+    misses: u64,
 }
 
 #[allow(dead_code)]
@@ -14,44 +16,65 @@ impl<K: Eq + std::hash::Hash + Clone, V> MaxSizeHashMap<K, V> {
         MaxSizeHashMap {
             map: HashMap::new(),
             inserts: VecDeque::new(),
-            size: max_size
+            size: max_size,
+            hits: 0,
+            misses: 0,
         }
     }
 
     pub fn get(&mut self, key: &K) -> Option<&V>{
         match self.map.get(key) {
-            Some((value, _)) => Some(value),
-            None => None
+            Some(value) => {
+                self.hits += 1;
+                Some(value)
+            },
+            None => {
+                self.misses += 1;
+                None
+            }
         }
     }
 
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V>{
         match self.map.get_mut(key) {
-            Some((value, _)) => Some(value),
-            None => None
+            Some(value) => {
+                self.hits += 1;
+                Some(value)
+            },
+            None => {
+                self.misses += 1;
+                None
+            }
         }
     }
 
+    // Lookups through get and get_mut that found their key
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    // Replacing a value keeps the key's place in the insertion order
     pub fn insert(&mut self, key: K, value: V) {
-        let index = self.inserts.len();
-        if index < self.size {
-            self.inserts.push_back(key.clone());
-            self.map.insert(key, (value, index));
+        if let Some(existing) = self.map.get_mut(&key) {
+            *existing = value;
+            return;
         }
-        else {
-            let removed_key = self.inserts.pop_front().unwrap(); 
-            self.map.remove(&removed_key);
-            self.insert(key, value);
+        if self.inserts.len() >= self.size {
+            if let Some(removed_key) = self.inserts.pop_front() {
+                self.map.remove(&removed_key);
+            }
         }
+        self.inserts.push_back(key.clone());
+        self.map.insert(key, value);
     }
 
     pub fn remove(&mut self, key: K) -> Option<V> {
-        match self.map.remove(&key) {
-            Some((value, index)) => {
-                self.inserts.remove(index);
-                Some(value)
-            },
-            None => None
-        }
+        let value = self.map.remove(&key)?;
+        self.inserts.retain(|inserted| *inserted != key);
+        Some(value)
     }
 }
\ No newline at end of file
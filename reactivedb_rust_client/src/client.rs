@@ -1,20 +1,37 @@
 use crate::types::ClientRequest;
 use crate::types::DBRequest;
 use crate::types::ListenEvent;
-use crate::types::{DBResponse, ToClientMessage};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crate::types::{DBResponse, ProtocolError, ToClientMessage};
+use crate::types::{Credentials, Encoding, Handshake, HandshakeResponse, PROTOCOL_VERSION};
+use crate::framing::{read_frame, write_frame, Frame, DEFAULT_MAX_FRAME_SIZE};
+use crate::tls::TlsConfig;
 use std::io;
-use std::io::Cursor;
 use std::io::{Error, ErrorKind};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::io::{ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+// Errors kept for `take_protocol_errors` when they aren't taken
+const MAX_PROTOCOL_ERRORS: usize = 100;
+
+// Plain and TLS connections are used the same way once they are open
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
 pub struct Client {
     addr: String,
-    connection: Option<WriteHalf<TcpStream>>,
+    connection: Option<WriteHalf<Box<dyn Connection>>>,
     response_subscribe_channel: Option<SubscriptionMaker<ToClientMessage>>,
+    max_frame_size: usize,
+    encoding: Encoding,
+    protocol_version: Option<u32>,
+    credentials: Option<Credentials>,
+    tls: Option<TlsConfig>,
+    // Errors the server sent that aren't tied to a request, such as a listen it refused
+    protocol_errors: Arc<Mutex<Vec<ProtocolError>>>,
 }
 
 impl Client {
@@ -23,17 +40,66 @@ impl Client {
             addr: addr.to_string(),
             connection: None,
             response_subscribe_channel: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            encoding: Encoding::Json,
+            protocol_version: None,
+            credentials: None,
+            tls: None,
+            protocol_errors: Arc::new(Mutex::new(vec![])),
         }
     }
 
+    // Credentials sent during the handshake of the next `open_connection`
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = Some(credentials);
+    }
+
+    // Connections opened after this are made over TLS
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    // Encoding requested during the handshake of the next `open_connection`
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    // Protocol version agreed on with the server, `None` until a connection is open
+    pub fn get_protocol_version(&self) -> Option<u32> {
+        self.protocol_version
+    }
+
+    // Errors the server sent that aren't tied to a request since the last call, oldest first, at most the latest 100.
+    // A listen the server refuses, because the table doesn't exist or the client may not listen to it, ends up here,
+    // as does a message over the maximum frame size, which closes the connection
+    pub fn take_protocol_errors(&mut self) -> Vec<ProtocolError> {
+        mem::take(&mut *self.protocol_errors.lock().unwrap())
+    }
+
+    // Largest frame that will be sent or accepted, should match the server's `max_frame_size`
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
     pub async fn open_connection(&mut self) -> io::Result<()> {
-        let stream = TcpStream::connect(&self.addr).await?;
+        let tcp_stream = TcpStream::connect(&self.addr).await?;
+        let mut stream: Box<dyn Connection> = match &self.tls {
+            Some(tls) => {
+                let (connector, server_name) = tls.connector()?;
+                Box::new(connector.connect(server_name, tcp_stream).await?)
+            }
+            None => Box::new(tcp_stream),
+        };
+        self.protocol_version = Some(self.handshake(&mut stream).await?);
         let (read, write) = tokio::io::split(stream);
         self.connection = Some(write);
         let (subscription_channel, subscription_manager) =
             SubscriptionManager::<ToClientMessage>::new();
+        let max_frame_size = self.max_frame_size;
+        let encoding = self.encoding;
+        let protocol_errors = self.protocol_errors.clone();
         tokio::spawn(async move {
-            Client::listen_for_messages(read, subscription_manager).await;
+            Client::listen_for_messages(read, subscription_manager, protocol_errors, max_frame_size, encoding).await;
         });
         self.response_subscribe_channel = Some(subscription_channel);
         Ok(())
@@ -41,32 +107,45 @@ impl Client {
 
     pub fn close_connection(&mut self) -> io::Result<()> {
         self.connection = None;
+        self.protocol_version = None;
         Ok(())
     }
 
+    // Handshake frames are always JSON, everything after uses the negotiated encoding
+    async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: &mut S) -> io::Result<u32> {
+        let handshake = Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            encoding: self.encoding,
+            credentials: self.credentials.clone(),
+        };
+        write_frame(stream, Encoding::Json.encode(&handshake)?.as_slice()).await?;
+        let response = match read_frame(stream, self.max_frame_size).await? {
+            Frame::Message(response) => response,
+            Frame::TooLarge(_) => Err(Error::new(ErrorKind::InvalidData, "Handshake response exceeds the maximum frame size"))?,
+            Frame::Closed => Err(Error::new(ErrorKind::ConnectionAborted, "Connection closed during the handshake"))?,
+        };
+        match Encoding::Json.decode(response.as_slice())? {
+            HandshakeResponse::Accepted(accepted) => {
+                if accepted.protocol_version > PROTOCOL_VERSION || accepted.encoding != self.encoding {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Server accepted unsupported protocol version {} with {:?}", accepted.protocol_version, accepted.encoding),
+                    ));
+                }
+                Ok(accepted.protocol_version)
+            }
+            HandshakeResponse::Rejected(reason) => Err(Error::new(ErrorKind::ConnectionRefused, reason)),
+        }
+    }
+
     pub async fn make_request(&mut self, client_request: ClientRequest) -> io::Result<DBResponse> {
         let (request, request_id) = client_request;
-        match &mut self.connection {
-            Some(stream) => {
-                let serialized_request = serde_json::to_string(&request).unwrap();
-                let mut total_request: Vec<u8> = vec![];
-                WriteBytesExt::write_u32::<BigEndian>(
-                    &mut total_request,
-                    serialized_request.len() as u32,
-                )?;
-                let mut bytes = serialized_request.into_bytes();
-                total_request.append(&mut bytes);
-                stream.write(total_request.as_slice()).await?;
-            }
-            None => Err(Error::new(
-                ErrorKind::Other,
-                "Connection to server not open",
-            ))?,
-        };
+        self.write_request(&request).await?;
         let mut receiver = match &mut self.response_subscribe_channel {
             Some(subscription_maker) => subscription_maker.subscribe(Box::new(move |entry: ToClientMessage| -> bool{
                 match entry {
                     ToClientMessage::RequestResponse(message) => message.request_id == request_id.clone(),
+                    ToClientMessage::ProtocolError(error) => error.request_id == Some(request_id),
                     _ => false
                 }
             })).await,
@@ -76,13 +155,20 @@ impl Client {
             ))?,
         };
         loop {
-            match receiver.recv().await.unwrap() {
-                ToClientMessage::RequestResponse(response) => {
+            match receiver.recv().await {
+                Some(ToClientMessage::RequestResponse(response)) => {
                     if response.request_id == request_id {
                         return Ok(response.response);
                     }
                 }
-                ToClientMessage::Event(_) => {}
+                Some(ToClientMessage::ProtocolError(error)) => {
+                    return Err(Error::new(ErrorKind::InvalidData, error.message));
+                }
+                Some(ToClientMessage::Event(_)) => {}
+                None | Some(ToClientMessage::Close(_)) => Err(Error::new(
+                    ErrorKind::ConnectionAborted,
+                    "Connection to server closed",
+                ))?,
             }
         }
     }
@@ -94,23 +180,7 @@ impl Client {
         callback: Box<dyn Fn(DBResponse) -> Result<(), ()> + Send>,
     ) -> io::Result<()> {
         let request = DBRequest::new_listen(table_name.clone(), event.clone());
-        match &mut self.connection {
-            Some(stream) => {
-                let serialized_request = serde_json::to_string(&request).unwrap();
-                let mut total_request: Vec<u8> = vec![];
-                WriteBytesExt::write_u32::<BigEndian>(
-                    &mut total_request,
-                    serialized_request.len() as u32,
-                )?;
-                let mut bytes = serialized_request.into_bytes();
-                total_request.append(&mut bytes);
-                stream.write(total_request.as_slice()).await?;
-            }
-            None => Err(Error::new(
-                ErrorKind::Other,
-                "Connection to server not open",
-            ))?,
-        };
+        self.write_request(&request).await?;
 
         let mut receiver = match &mut self.response_subscribe_channel {
             Some(subscription_maker) => subscription_maker.subscribe(Box::new(move |entry: ToClientMessage| -> bool{
@@ -125,18 +195,17 @@ impl Client {
             ))?,
         };
         tokio::spawn(async move {
-            loop {
-                match receiver.recv().await.unwrap() {
-                    ToClientMessage::RequestResponse(_) => {}
-                    ToClientMessage::Event(event) => {
-                        let _ = callback(event.value);
-                    }
+            while let Some(message) = receiver.recv().await {
+                if let ToClientMessage::Event(event) = message {
+                    let _ = callback(event.value);
                 }
             }
         });
         Ok(())
     }
 
+    // Runs the callback for every event until the connection closes. An error from the server that isn't tied to
+    // a request, such as the listen being refused, ends it with that error
     pub async fn subscribe_to_event_blocking(
         &mut self,
         table_name: String,
@@ -144,28 +213,13 @@ impl Client {
         callback: Box<dyn Fn(DBResponse) -> Result<(), ()> + Send>,
     ) -> io::Result<()> {
         let request = DBRequest::new_listen(table_name.clone(), event.clone());
-        match &mut self.connection {
-            Some(stream) => {
-                let serialized_request = serde_json::to_string(&request).unwrap();
-                let mut total_request: Vec<u8> = vec![];
-                WriteBytesExt::write_u32::<BigEndian>(
-                    &mut total_request,
-                    serialized_request.len() as u32,
-                )?;
-                let mut bytes = serialized_request.into_bytes();
-                total_request.append(&mut bytes);
-                stream.write(total_request.as_slice()).await?;
-            }
-            None => Err(Error::new(
-                ErrorKind::Other,
-                "Connection to server not open",
-            ))?,
-        };
+        self.write_request(&request).await?;
 
         let mut receiver = match &mut self.response_subscribe_channel {
             Some(subscription_maker) => subscription_maker.subscribe(Box::new(move |entry: ToClientMessage| -> bool{
                 match entry {
                     ToClientMessage::Event(message) => message.event == event.clone() && message.table_name == table_name.clone(),
+                    ToClientMessage::ProtocolError(error) => error.request_id.is_none(),
                     _ => false
                 }
             })).await,
@@ -174,35 +228,82 @@ impl Client {
                 "Connection to server not open",
             ))?,
         };
-        loop {
-            match receiver.recv().await.unwrap() {
-                ToClientMessage::RequestResponse(_) => {}
+        while let Some(message) = receiver.recv().await {
+            match message {
                 ToClientMessage::Event(event) => {
                     let _ = callback(event.value);
                 }
+                ToClientMessage::ProtocolError(error) => return Err(Error::new(ErrorKind::InvalidData, error.message)),
+                _ => {}
             }
         }
+        Err(Error::new(
+            ErrorKind::ConnectionAborted,
+            "Connection to server closed",
+        ))
+    }
+
+    async fn write_request(&mut self, request: &DBRequest) -> io::Result<()> {
+        let serialized_request = self.encoding.encode(request)?;
+        if serialized_request.len() > self.max_frame_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Request of {} bytes exceeds the maximum frame size of {} bytes", serialized_request.len(), self.max_frame_size),
+            ));
+        }
+        match &mut self.connection {
+            Some(stream) => write_frame(stream, serialized_request.as_slice()).await?,
+            None => Err(Error::new(
+                ErrorKind::Other,
+                "Connection to server not open",
+            ))?,
+        };
+        Ok(())
     }
 
     async fn listen_for_messages(
-        mut stream: ReadHalf<TcpStream>,
+        mut stream: ReadHalf<Box<dyn Connection>>,
         mut subscription_manager: SubscriptionManager<ToClientMessage>,
+        protocol_errors: Arc<Mutex<Vec<ProtocolError>>>,
+        max_frame_size: usize,
+        encoding: Encoding,
     ) {
         loop {
-            let mut size_buffer = [0; 4];
-            stream.read(&mut size_buffer).await.unwrap();
-            let message_size = ReadBytesExt::read_u32::<BigEndian>(&mut Cursor::new(size_buffer))
-                .unwrap() as usize;
-            let mut message_buffer = vec![0; message_size];
-            stream.read(&mut message_buffer).await.unwrap();
-            let results: serde_json::Result<ToClientMessage> =
-                serde_json::from_slice(message_buffer.as_slice());
-            let db_response = results.unwrap();
-            subscription_manager.send_message(db_response).await;
+            let message_buffer = match read_frame(&mut stream, max_frame_size).await {
+                Ok(Frame::Message(message_buffer)) => message_buffer,
+                // There is no telling which request or listener the message was for, so the connection is closed
+                // rather than leaving a request waiting for an answer that was thrown away
+                Ok(Frame::TooLarge(message_size)) => {
+                    let message = format!("Message of {} bytes exceeds the maximum frame size of {} bytes", message_size, max_frame_size);
+                    record_protocol_error(&protocol_errors, ProtocolError { request_id: None, message });
+                    break;
+                }
+                Ok(Frame::Closed) | Err(_) => break,
+            };
+            let results: io::Result<ToClientMessage> = encoding.decode(message_buffer.as_slice());
+            match results {
+                // Dropping the subscriptions ends pending requests and listeners with a closed connection error
+                Ok(ToClientMessage::Close(_)) => break,
+                Ok(ToClientMessage::ProtocolError(error)) if error.request_id.is_none() => {
+                    record_protocol_error(&protocol_errors, error.clone());
+                    subscription_manager.send_message(ToClientMessage::ProtocolError(error)).await
+                }
+                Ok(db_response) => subscription_manager.send_message(db_response).await,
+                Err(_) => {}
+            }
         }
     }
 }
 
+// Keeps the latest errors for `take_protocol_errors`, dropping the oldest once it holds MAX_PROTOCOL_ERRORS
+fn record_protocol_error(protocol_errors: &Mutex<Vec<ProtocolError>>, error: ProtocolError) {
+    let mut protocol_errors = protocol_errors.lock().unwrap();
+    if protocol_errors.len() >= MAX_PROTOCOL_ERRORS {
+        protocol_errors.remove(0);
+    }
+    protocol_errors.push(error);
+}
+
 type ClosureType<T> = Box<dyn Fn(T)->bool + Send + Sync>;
 
 struct SubscriptionMaker<T> {
@@ -246,7 +347,7 @@ impl<T: Clone> SubscriptionManager<T> {
         }
         for (channel, condition) in &self.subscriptions {
             if condition(value.clone()){
-                channel.send(value.clone()).await;
+                let _ = channel.send(value.clone()).await;
             }
         }
     }
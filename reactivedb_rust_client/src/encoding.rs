@@ -0,0 +1,26 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io;
+
+// Shared by the client and the server so both ends encode messages the same way.
+// Wire encoding for everything sent after the handshake, the handshake itself is always JSON
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    pub fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Encoding::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, message: &[u8]) -> io::Result<T> {
+        match self {
+            Encoding::Json => serde_json::from_slice(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Encoding::MessagePack => rmp_serde::from_slice(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
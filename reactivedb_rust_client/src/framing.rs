@@ -0,0 +1,66 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io;
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Shared by the client and the server so both ends frame messages the same way
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+const FRAME_HEADER_SIZE: usize = 4;
+const DISCARD_CHUNK_SIZE: usize = 8192;
+
+// A single length prefixed message read off of a connection
+pub enum Frame {
+    Message(Vec<u8>),
+    // The declared size was over the limit, the payload has been discarded so the stream is still aligned
+    TooLarge(usize),
+    Closed,
+}
+
+// Reads one `u32` length prefixed frame, waiting for the whole payload even if it is split across reads
+pub async fn read_frame<R>(stream: &mut R, max_frame_size: usize) -> io::Result<Frame>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut size_buffer = [0; FRAME_HEADER_SIZE];
+    match stream.read_exact(&mut size_buffer).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(Frame::Closed),
+        Err(e) => return Err(e),
+    };
+    let message_size = ReadBytesExt::read_u32::<BigEndian>(&mut Cursor::new(size_buffer))? as usize;
+    if message_size == 0 {
+        return Ok(Frame::Closed);
+    }
+    if message_size > max_frame_size {
+        discard(stream, message_size).await?;
+        return Ok(Frame::TooLarge(message_size));
+    }
+    let mut message_buffer = vec![0; message_size];
+    stream.read_exact(&mut message_buffer).await?;
+    Ok(Frame::Message(message_buffer))
+}
+
+// Writes the length prefix and payload of a frame in full
+pub async fn write_frame<W>(stream: &mut W, message: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buff = Vec::with_capacity(FRAME_HEADER_SIZE + message.len());
+    WriteBytesExt::write_u32::<BigEndian>(&mut buff, message.len() as u32)?;
+    buff.extend_from_slice(message);
+    stream.write_all(buff.as_slice()).await?;
+    stream.flush().await
+}
+
+async fn discard<R>(stream: &mut R, mut size: usize) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut sink = vec![0; DISCARD_CHUNK_SIZE.min(size)];
+    while size > 0 {
+        let chunk = DISCARD_CHUNK_SIZE.min(size);
+        stream.read_exact(&mut sink[..chunk]).await?;
+        size -= chunk;
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
 pub mod client;
+pub mod encoding;
+pub mod framing;
+pub mod tls;
 pub mod types;
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,74 @@
+use rustls_pemfile::{certs, private_key};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Error, ErrorKind};
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+// How to reach a server that has `network.tls` configured
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    // Name the server certificate was issued for
+    pub server_name: String,
+    // PEM file with the certificate authorities trusted to sign the server certificate
+    pub ca_cert_path: String,
+    // PEM certificate chain and key presented to servers that require client certificates
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new(server_name: &str, ca_cert_path: &str) -> TlsConfig {
+        TlsConfig {
+            server_name: server_name.to_string(),
+            ca_cert_path: ca_cert_path.to_string(),
+            client_cert_path: None,
+            client_key_path: None,
+        }
+    }
+
+    pub fn with_client_certificate(mut self, cert_path: &str, key_path: &str) -> TlsConfig {
+        self.client_cert_path = Some(cert_path.to_string());
+        self.client_key_path = Some(key_path.to_string());
+        self
+    }
+
+    pub(crate) fn connector(&self) -> io::Result<(TlsConnector, ServerName<'static>)> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_cert_path)? {
+            roots.add(cert).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        }
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match (&self.client_cert_path, &self.client_key_path) {
+            (Some(cert_path), Some(key_path)) => builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "A client certificate needs both a cert and a key path"))?,
+        };
+        let server_name = ServerName::try_from(self.server_name.clone())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok((TlsConnector::from(Arc::new(config)), server_name))
+    }
+}
+
+// Shared with the server so both ends read PEM files the same way
+pub fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let loaded = certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if loaded.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("No certificates found in {}", path)));
+    }
+    Ok(loaded)
+}
+
+pub fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match private_key(&mut reader)? {
+        Some(key) => Ok(key),
+        None => Err(Error::new(ErrorKind::InvalidData, format!("No private key found in {}", path))),
+    }
+}
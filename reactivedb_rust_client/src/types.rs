@@ -3,6 +3,35 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
+pub use crate::encoding::Encoding;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub encoding: Encoding,
+    pub credentials: Option<Credentials>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Credentials {
+    ApiToken(String),
+    UserPassword(String, String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum HandshakeResponse {
+    Accepted(AcceptedHandshake),
+    Rejected(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AcceptedHandshake {
+    pub protocol_version: u32,
+    pub encoding: Encoding,
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialOrd, PartialEq, Ord)]
 pub struct ListenRequest {
     pub table_name: String,
@@ -17,6 +46,18 @@ pub enum Query {
     GreaterThan(GetData),
     InsertData(InsertData),
     DeleteData(DeleteData),
+    // Rereads the server's config file and applies table changes, needs the Admin permission
+    ReloadConfig,
+    // Recomputes a derived table and everything downstream of it, needs the Admin permission on that table
+    RebuildTable(String),
+    // Runs the rows a derived table's transform failed on through it again, needs the Admin permission on that table
+    RetryFailed(String),
+    // Lists every table with its type, transform, inputs, outputs and columns, needs the Admin permission
+    DescribeGraph,
+    // Finds the rows an entry was derived from and every row derived from it, needs the Read permission on every table
+    Lineage(LineageData),
+    // Copies every table and index file into a new directory on the server without stopping writes, needs the Admin permission
+    Snapshot(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialOrd, PartialEq, Ord)]
@@ -86,6 +127,49 @@ impl DBRequest {
             request_id,
         )
     }
+    pub fn new_reload_config() -> (DBRequest, Uuid) {
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query: Query::ReloadConfig }),
+            request_id,
+        )
+    }
+    pub fn new_rebuild_table(table: String) -> (DBRequest, Uuid) {
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query: Query::RebuildTable(table) }),
+            request_id,
+        )
+    }
+    pub fn new_retry_failed(table: String) -> (DBRequest, Uuid) {
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query: Query::RetryFailed(table) }),
+            request_id,
+        )
+    }
+    pub fn new_describe_graph() -> (DBRequest, Uuid) {
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query: Query::DescribeGraph }),
+            request_id,
+        )
+    }
+    pub fn new_lineage(table: String, entry_id: EntryValue) -> (DBRequest, Uuid) {
+        let query = Query::Lineage(LineageData { table, entry_id });
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query }),
+            request_id,
+        )
+    }
+    pub fn new_snapshot(destination: String) -> (DBRequest, Uuid) {
+        let request_id = Uuid::new_v4();
+        (
+            DBRequest::Query(QueryRequest { request_id, query: Query::Snapshot(destination) }),
+            request_id,
+        )
+    }
     pub fn new_listen(table: String, listen_event: ListenEvent) -> DBRequest {
         let listen_request = ListenRequest {
             table_name: table,
@@ -112,6 +196,12 @@ pub struct InsertData {
 
 pub type DeleteData = GetData;
 
+#[derive(Serialize, Deserialize, Debug, Eq, PartialOrd, PartialEq, Ord, Clone)]
+pub struct LineageData {
+    pub table: String,
+    pub entry_id: EntryValue,
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialOrd, PartialEq, Ord, Clone)]
 pub enum DBResponse {
     ManyResults(Result<Vec<Entry>, String>),
@@ -157,6 +247,15 @@ impl EntryBuilder {
 pub enum ToClientMessage {
     Event(ListenResponse),
     RequestResponse(RequestResponse),
+    ProtocolError(ProtocolError),
+    // The server is shutting down and closes the connection after this
+    Close(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProtocolError {
+    pub request_id: Option<Uuid>,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]